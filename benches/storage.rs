@@ -0,0 +1,102 @@
+//! Compares the rope used for `Buffer` storage against the gap buffer it replaced,
+//! on a generated text of around 100 MB.
+//!
+//! Run with `cargo bench --bench storage`.
+
+#![feature(test)]
+
+extern crate test;
+extern crate gapbuffer;
+extern crate credits;
+
+use std::sync::OnceLock;
+
+use gapbuffer::GapBuffer;
+use credits::Rope;
+use test::{Bencher, black_box};
+
+const TEXT_SIZE: usize = 100 * 1024 * 1024;
+
+static TEXT: OnceLock<Vec<u8>> = OnceLock::new();
+static LINES: OnceLock<usize> = OnceLock::new();
+
+/// The generated text, shared by all the benches
+fn text() -> &'static [u8] {
+    TEXT.get_or_init(|| {
+        let mut text = Vec::with_capacity(TEXT_SIZE + 128);
+        let mut n = 0;
+        while text.len() < TEXT_SIZE {
+            text.extend(format!("{:>10} the quick brown fox jumps over the lazy dog\n", n).bytes());
+            n += 1;
+        }
+        text
+    })
+}
+
+fn line_count() -> usize {
+    *LINES.get_or_init(|| text().iter().filter(|b| **b == b'\n').count())
+}
+
+fn gap_buffer() -> GapBuffer<u8> {
+    let mut buffer = GapBuffer::new();
+    buffer.extend(text().iter().cloned());
+    buffer
+}
+
+fn rope() -> Rope {
+    Rope::from(text())
+}
+
+#[bench]
+fn gap_buffer_line_start(b: &mut Bencher) {
+    let text = gap_buffer();
+    let line = line_count() / 2;
+    // how `get_line_index_absolute` found a line before the rope
+    b.iter(|| {
+        black_box((0..text.len()).filter(|i| text[*i] == b'\n').nth(line - 1).map(|i| i + 1))
+    });
+}
+
+#[bench]
+fn rope_line_start(b: &mut Bencher) {
+    let text = rope();
+    let line = line_count() / 2;
+    b.iter(|| black_box(text.line_start(line)));
+}
+
+#[bench]
+fn gap_buffer_line_of(b: &mut Bencher) {
+    let text = gap_buffer();
+    let idx = text.len() / 2;
+    // how `get_line_info` found the line number of a mark before the rope
+    b.iter(|| {
+        black_box((0..idx + 1).rev().filter(|i| *i == 0 || text[*i - 1] == b'\n').count())
+    });
+}
+
+#[bench]
+fn rope_line_of(b: &mut Bencher) {
+    let text = rope();
+    let idx = text.len() / 2;
+    b.iter(|| black_box(text.line_of(idx)));
+}
+
+#[bench]
+fn gap_buffer_insert_remove_middle(b: &mut Bencher) {
+    let mut text = gap_buffer();
+    let idx = text.len() / 2;
+    b.iter(|| {
+        text.insert(idx, b'x');
+        black_box(text.remove(idx))
+    });
+}
+
+#[bench]
+fn rope_insert_remove_middle(b: &mut Bencher) {
+    let mut text = rope();
+    let idx = text.len() / 2;
+    b.iter(|| {
+        text.insert(idx, b'x');
+        black_box(text.remove(idx))
+    });
+}
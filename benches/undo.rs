@@ -0,0 +1,63 @@
+//! Undo and redo of large edits, which are logged as a single change each
+//! rather than one change per byte.
+//!
+//! Run with `cargo bench --bench undo`.
+
+#![feature(test)]
+
+extern crate test;
+extern crate credits;
+
+use credits::Buffer;
+use test::Bencher;
+
+const TEXT_SIZE: usize = 1024 * 1024;
+
+fn text() -> String {
+    let mut text = String::with_capacity(TEXT_SIZE + 128);
+    let mut n = 0;
+    while text.len() < TEXT_SIZE {
+        text.push_str(&format!("{:>10} the quick brown fox jumps over the lazy dog\n", n));
+        n += 1;
+    }
+    text
+}
+
+fn buffer() -> Buffer {
+    Buffer::read_from(&mut text().as_bytes()).unwrap()
+}
+
+#[bench]
+fn undo_large_paste(b: &mut Bencher) {
+    let mut buffer = Buffer::new();
+    let paste = text();
+    buffer.replace_ranges(&[(0, 0, paste)]);
+    b.iter(|| {
+        buffer.undo();
+        buffer.redo();
+    });
+}
+
+#[bench]
+fn undo_large_delete(b: &mut Bencher) {
+    let mut buffer = buffer();
+    let end = buffer.len() - 1;
+    buffer.remove_range(0, end);
+    b.iter(|| {
+        buffer.undo();
+        buffer.redo();
+    });
+}
+
+#[bench]
+fn undo_line_delete(b: &mut Bencher) {
+    let mut buffer = buffer();
+    // the end of the first thousand lines
+    let end = (0..1000).fold(0, |idx, _| buffer.line_range(idx).1);
+    buffer.remove_range(0, end);
+    // redoing rather than deleting again keeps the undo tree from growing
+    b.iter(|| {
+        buffer.undo();
+        buffer.redo();
+    });
+}
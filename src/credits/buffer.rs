@@ -1,19 +1,21 @@
-//FIXME: Check unicode support
 // stdlib dependencies
 use std::cmp;
 use std::collections::HashMap;
+use std::fmt;
 use std::path::PathBuf;
-use std::fs::File;
-use std::io::{Stdin, Read};
-use std::convert::From;
+use std::fs::{self, File};
+use std::io::{self, Read};
 
 // external dependencies
-use gapbuffer::GapBuffer;
+use regex::Regex;
 
 // local dependencies
+use crate::fileformat::{self, FileFormat};
 use crate::log::{Log, Change, LogEntry};
-use crate::input::Input;
 use crate::iterators::Lines;
+use crate::rope::Rope;
+use crate::syntax::{self, Highlighter, Span};
+use crate::utils;
 use crate::textobject::{TextObject, Kind, Offset, Anchor};
 
 
@@ -48,12 +50,17 @@ impl From<(usize, usize, usize)> for MarkPosition {
 
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[allow(clippy::enum_variant_names)] // DisplayMark
+/// A named position in a buffer's text, kept in place as the text changes.
 pub enum Mark {
     /// For keeping track of cursors.
     Cursor(usize),
 
     /// For using in determining some display of characters
     DisplayMark(usize),
+
+    /// The end of a selection which stays put while the cursor moves
+    Selection(usize),
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
@@ -61,9 +68,10 @@ pub enum WordEdgeMatch {
     Whitespace,
 }
 
+/// The text of a file being edited, with its marks and undo history.
 pub struct Buffer {
     /// Current buffers text
-    text: GapBuffer<u8>,
+    text: Rope,
 
     /// Table of marked indices in the text
     marks: HashMap<Mark, MarkPosition>,
@@ -76,18 +84,39 @@ pub struct Buffer {
 
     /// Whether or not the Buffer has unsaved changes
     pub dirty: bool,
+
+    /// Syntax highlighting for the text, picked by the file's extension
+    syntax: Option<Highlighter>,
+
+    /// Number of columns between tab stops
+    pub tab_width: usize,
+
+    /// Encoding, line endings, byte order mark and final newline of the file on disk
+    pub format: FileFormat,
+
+    /// Whether the text is a view of a binary file, which can't be changed or saved
+    pub read_only: bool,
+
+    /// Whether the undo history is kept along with the file when it is saved
+    pub persistent_undo: bool,
 }
 
-#[cfg_attr(feature="clippy", allow(len_without_is_empty))]
+// the length counts the end of the text, so a buffer is never empty
+#[allow(clippy::len_without_is_empty)]
 impl Buffer {
     /// Constructor for empty buffer.
     pub fn new() -> Buffer {
         Buffer {
             file_path: None,
-            text: GapBuffer::new(),
+            text: Rope::new(),
             marks: HashMap::new(),
             log: Log::new(),
             dirty: false,
+            syntax: None,
+            tab_width: 4,
+            format: FileFormat::default(),
+            read_only: false,
+            persistent_undo: false,
         }
     }
 
@@ -97,9 +126,13 @@ impl Buffer {
     }
 
     /// The x,y coordinates of a mark within the file. None if not a valid mark.
+    ///
+    /// The x coordinate is the display width of the text between the start of
+    /// the line and the mark, so wide characters take up more than one column.
     pub fn get_mark_display_coords(&self, mark: Mark) -> Option<(usize, usize)> {
         if let Some(mark_pos) = self.marks.get(&mark) {
-            return Some((mark_pos.absolute - mark_pos.absolute_line_start, mark_pos.line_number))
+            let column = display_width(&self.text, mark_pos.absolute_line_start, mark_pos.absolute, self.tab_width);
+            return Some((column, mark_pos.line_number))
         }
 
         None
@@ -114,6 +147,52 @@ impl Buffer {
         } else { None }
     }
 
+    /// Copy out the text from `start` up to `end`.
+    pub fn slice(&self, start: usize, end: usize) -> Vec<u8> {
+        self.text.slice(start, end)
+    }
+
+    /// The index of the char after the one at `idx`, or the end of the text.
+    pub fn next_char(&self, idx: usize) -> usize {
+        advance_chars(&self.text, idx, 1, self.text.len())
+    }
+
+    /// Start and end of the line that `idx` is on, the end including its line break.
+    pub fn line_range(&self, idx: usize) -> (usize, usize) {
+        let line = self.text.line_of(idx);
+        let start = self.text.line_start(line).unwrap_or(0);
+        let end = self.text.nth_newline(line).map_or(self.text.len(), |newline| newline + 1);
+        (start, end)
+    }
+
+    /// Number of lines in the text, counting the one after a final line break.
+    pub fn line_count(&self) -> usize {
+        self.text.newlines() + 1
+    }
+
+    /// Start and end of the whole lines between a mark and the line `offset`
+    /// lines away from it, stopping at the first or last line of the buffer.
+    ///
+    /// An absolute offset is a single line, counting from 1.
+    pub fn lines_range(&self, offset: Offset) -> Option<(usize, usize)> {
+        let last_line = self.text.newlines();
+        let (first, last) = match offset {
+            Offset::Forward(n, mark) => {
+                let line = self.text.line_of(self.get_mark_idx(mark)?);
+                (line, cmp::min(line + n, last_line))
+            }
+            Offset::Backward(n, mark) => {
+                let line = self.text.line_of(self.get_mark_idx(mark)?);
+                (line.saturating_sub(n), line)
+            }
+            Offset::Absolute(n) if n > 0 && n - 1 <= last_line => (n - 1, n - 1),
+            Offset::Absolute(_) => return None,
+        };
+        let start = self.text.line_start(first)?;
+        let end = self.text.nth_newline(last).map_or(self.text.len(), |newline| newline + 1);
+        Some((start, end))
+    }
+
     /// Creates an iterator on the text by lines.
     pub fn lines(&self) -> Lines {
         Lines {
@@ -138,6 +217,127 @@ impl Buffer {
         None
     }
 
+    /// Set the file the buffer is saved to, and pick the syntax highlighting for it.
+    pub fn set_file_path(&mut self, path: PathBuf) {
+        self.syntax = syntax::grammar_for(&path).map(Highlighter::new);
+        self.file_path = Some(path);
+    }
+
+    /// Syntax highlighting of `count` lines, starting with the line of `mark`.
+    ///
+    /// Empty if the buffer has no syntax highlighting.
+    pub fn highlight_lines(&mut self, mark: Mark, count: usize) -> Vec<Vec<Span>> {
+        let first = match self.marks.get(&mark) {
+            Some(mark_pos) => mark_pos.line_number,
+            None => return Vec::new(),
+        };
+        let text = &self.text;
+        match self.syntax {
+            Some(ref mut syntax) => syntax.highlight(first, count, |line| line_text(text, line).map(|l| l.1)),
+            None => Vec::new(),
+        }
+    }
+
+    /// Forget the highlighting of lines from the one containing `idx` on, after it changed.
+    fn text_changed(&mut self, idx: usize) {
+        if let Some(ref mut syntax) = self.syntax {
+            syntax.invalidate_from(self.text.line_of(idx));
+        }
+    }
+
+    /// Find the first match of `pattern` starting at or after `from`.
+    ///
+    /// The search wraps around to the start of the buffer. Matches can not span
+    /// lines, and empty matches are skipped. Returns the start and end index.
+    pub fn find_forward(&self, pattern: &Regex, from: usize) -> Option<(usize, usize)> {
+        let line_count = self.text.newlines() + 1;
+        let first_line = self.text.line_of(from);
+        for n in 0..line_count + 1 {
+            let (start, text) = line_text(&self.text, (first_line + n) % line_count)?;
+            let found = pattern.find_iter(&text)
+                .map(|(s, e)| (start + s, start + e))
+                .filter(|&(s, e)| s != e)
+                .find(|&(s, _)| match n {
+                    0 => s >= from,
+                    _ if n == line_count => s < from, // wrapped back around
+                    _ => true,
+                });
+            if found.is_some() { return found }
+        }
+        None
+    }
+
+    /// Find the last match of `pattern` starting before `from`.
+    ///
+    /// The search wraps around to the end of the buffer. Matches can not span
+    /// lines, and empty matches are skipped. Returns the start and end index.
+    pub fn find_backward(&self, pattern: &Regex, from: usize) -> Option<(usize, usize)> {
+        let line_count = self.text.newlines() + 1;
+        let first_line = self.text.line_of(from);
+        for n in 0..line_count + 1 {
+            let (start, text) = line_text(&self.text, (first_line + line_count - n % line_count) % line_count)?;
+            let found = pattern.find_iter(&text)
+                .map(|(s, e)| (start + s, start + e))
+                .filter(|&(s, e)| s != e)
+                .filter(|&(s, _)| match n {
+                    0 => s < from,
+                    _ if n == line_count => s >= from, // wrapped back around
+                    _ => true,
+                })
+                .last();
+            if found.is_some() { return found }
+        }
+        None
+    }
+
+    /// Every match of `pattern`, along with the text to replace it with.
+    ///
+    /// Capture groups of the match can be used in `replacement` as `$1` or
+    /// `$name`, `$$` is a literal `$`. Matches can not span lines, and empty
+    /// matches are skipped. Returns the start and end index of each match.
+    pub fn replacements(&self, pattern: &Regex, replacement: &str) -> Vec<(usize, usize, String)> {
+        let mut replacements = Vec::new();
+        for line in 0..self.text.newlines() + 1 {
+            let (start, text) = match line_text(&self.text, line) {
+                Some(line) => line,
+                None => break,
+            };
+            for captures in pattern.captures_iter(&text) {
+                if let Some((s, e)) = captures.pos(0) {
+                    if s != e {
+                        replacements.push((start + s, start + e, captures.expand(replacement)));
+                    }
+                }
+            }
+        }
+        replacements
+    }
+
+    /// Replace each range of text with the given text, as a single transaction.
+    ///
+    /// The ranges must be in order and must not overlap.
+    pub fn replace_ranges(&mut self, replacements: &[(usize, usize, String)]) {
+        let first = match replacements.first() {
+            Some(&(start, _, _)) if !self.read_only => start,
+            _ => return,
+        };
+        self.dirty = true;
+        let mut transaction = self.log.start(first);
+        // going backwards keeps the indexes of the earlier ranges valid
+        for &(start, end, ref text) in replacements.iter().rev() {
+            let removed = self.text.remove_range(start, end);
+            if !removed.is_empty() {
+                transaction.log(Change::RemoveRange(start, removed), start);
+            }
+            if !text.is_empty() {
+                self.text.insert_slice(start, text.as_bytes());
+                transaction.log(Change::InsertStr(start, text.clone().into_bytes()), start);
+            }
+        }
+        drop(transaction);
+        self.text_changed(first);
+    }
+
     /// Return the buffer index of a TextObject
     pub fn get_object_index(&self, obj: TextObject) -> Option<MarkPosition> {
         match obj.kind {
@@ -150,7 +350,8 @@ impl Buffer {
     /// Get the position of a specific character in the buffer
     ///
     /// This character can be at an absolute position, or a postion relative
-    /// to a given mark.
+    /// to a given mark. Offsets are counted in chars, the returned index is
+    /// always the byte index of a char boundary.
     ///
     /// ie: get the index of the 7th character after the cursor
     /// or: get the index of the 130th character from the start of the buffer
@@ -165,7 +366,7 @@ impl Buffer {
             Offset::Forward(offset, from_mark) => {
                 let last = self.len() - 1;
                 if let Some(mark_pos) = self.marks.get(&from_mark) {
-                    let new_absolute_position = mark_pos.absolute + offset;
+                    let new_absolute_position = advance_chars(text, mark_pos.absolute, offset, last);
                     if new_absolute_position < last {
                         // FIXME: it would be nice if we could avoid using get_line_info here...
                         let new_mark_pos = get_line_info(new_absolute_position, text).unwrap();
@@ -186,14 +387,10 @@ impl Buffer {
             // or: get the index of the char which is 5 chars before the Cursor
             Offset::Backward(offset, from_mark) => {
                 if let Some(mark_pos) = self.marks.get(&from_mark) {
-                    if mark_pos.absolute >= offset {
-                        let new_absolute_position = mark_pos.absolute - offset;
-                        // FIXME: it would be nice if we could avoid using get_line_info here...
-                        let new_mark_pos = get_line_info(new_absolute_position, text).unwrap();
-                        return Some(new_mark_pos);
-                    } else {
-                        return None
-                    }
+                    let new_absolute_position = retreat_chars(text, mark_pos.absolute, offset)?;
+                    // FIXME: it would be nice if we could avoid using get_line_info here...
+                    let new_mark_pos = get_line_info(new_absolute_position, text).unwrap();
+                    return Some(new_mark_pos);
                 }
 
                 None
@@ -204,7 +401,7 @@ impl Buffer {
             // ie: get the index of the 5th char in the buffer
             Offset::Absolute(absolute_char_offset) => {
                 let mut mark_pos = MarkPosition::start();
-                mark_pos.absolute = absolute_char_offset;
+                mark_pos.absolute = advance_chars(text, 0, absolute_char_offset, text.len());
                 Some(mark_pos)
             },
         }
@@ -237,25 +434,28 @@ impl Buffer {
     /// or: Get the index of the start of the 23th line
     fn get_line_index_absolute(&self, anchor: Anchor, line_number: usize) -> Option<MarkPosition> {
         let text = &self.text;
+        // line numbers here start from 1, the rope counts lines from 0
+        let line_idx = line_number.saturating_sub(1);
 
-        let nlines = (0..text.len()).filter(|i| text[*i] == b'\n')
-                                    .take(line_number + 1)
-                                    .collect::<Vec<usize>>();
         match anchor {
             Anchor::Start => {
                 let mut mark_pos = MarkPosition::start();
-                let line_start = nlines[0] + 1;
+                let line_start = text.line_start(line_idx)?;
 
                 mark_pos.absolute = line_start;
                 mark_pos.absolute_line_start = line_start;
-                mark_pos.line_number = line_number - 1;
+                mark_pos.line_number = line_idx;
 
                 Some(mark_pos)
             }
 
             Anchor::End => {
                 let mut mark_pos = MarkPosition::start();
-                let end_offset = nlines[line_number - 1];
+                let end_offset = match text.nth_newline(line_idx) {
+                    Some(idx) => idx,
+                    None if line_idx == text.newlines() => text.len(),
+                    None => return None,
+                };
 
                 mark_pos.absolute = end_offset;
 
@@ -273,9 +473,10 @@ impl Buffer {
     fn get_line_index_backward(&self, anchor: Anchor, offset: usize, from_mark: Mark) -> Option<MarkPosition> {
         let text = &self.text;
         if let Some(mark_pos) = self.marks.get(&from_mark) {
-            let nlines = (0..mark_pos.absolute).rev().filter(|i| text[*i] == b'\n')
-                                         .take(offset + 1)
-                                         .collect::<Vec<usize>>();
+            // the line breaks before the mark, nearest first
+            let line = text.line_of(mark_pos.absolute);
+            let nlines = (0..cmp::min(line, offset + 1)).filter_map(|n| text.nth_newline(line - n - 1))
+                                                        .collect::<Vec<usize>>();
 
             match anchor {
                 // Get the index of the start of the desired line
@@ -302,12 +503,13 @@ impl Buffer {
                 Anchor::Same => {
                     let mut new_mark_pos = MarkPosition::start();
 
+                    let column = count_chars(text, mark_pos.absolute_line_start, mark_pos.absolute);
                     if offset == nlines.len() {
-                        new_mark_pos.absolute = cmp::min(mark_pos.absolute - mark_pos.absolute_line_start, nlines[0]);
+                        new_mark_pos.absolute = advance_chars(text, 0, column, nlines[0]);
                     } else if offset > nlines.len() || offset == 0 {
                         return Some(new_mark_pos)
                     } else {
-                        new_mark_pos.absolute = cmp::min(mark_pos.absolute - mark_pos.absolute_line_start + nlines[offset] + 1, nlines[offset-1]);
+                        new_mark_pos.absolute = advance_chars(text, nlines[offset] + 1, column, nlines[offset-1]);
                         new_mark_pos.line_number = mark_pos.line_number - offset;
                         new_mark_pos.absolute_line_start = nlines[nlines.len() - 1] + 1;
                     }
@@ -329,9 +531,10 @@ impl Buffer {
         let text = &self.text;
         let last = self.len() - 1;
         if let Some(mark_pos) = self.marks.get(&from_mark) {
-            let nlines = (mark_pos.absolute..text.len()).filter(|i| text[*i] == b'\n')
-                                            .take(offset + 1)
-                                            .collect::<Vec<usize>>();
+            // the line breaks at or after the mark, nearest first
+            let line = text.line_of(mark_pos.absolute);
+            let nlines = (line..line + offset + 1).map_while(|n| text.nth_newline(n))
+                                                  .collect::<Vec<usize>>();
             if nlines.is_empty() { return None }
 
             match anchor {
@@ -343,9 +546,10 @@ impl Buffer {
                 Anchor::Same => {
                     let mut new_pos = MarkPosition::start();
                     let new_line_start = nlines[0] + 1;
+                    let column = count_chars(text, mark_pos.absolute_line_start, mark_pos.absolute);
 
                     if offset == nlines.len() {
-                        new_pos.absolute = cmp::min(mark_pos.absolute - mark_pos.absolute_line_start + nlines[offset-1] + 1, last);
+                        new_pos.absolute = advance_chars(text, nlines[offset-1] + 1, column, last);
                         new_pos.absolute_line_start = nlines[offset - 1] + 1;
                         new_pos.line_number = mark_pos.line_number + offset;
                     } else if offset > nlines.len() {
@@ -353,7 +557,7 @@ impl Buffer {
                         new_pos.line_number = (last - new_pos.absolute) + 1;
                         new_pos.absolute_line_start = new_line_start;
                     } else {
-                        new_pos.absolute = cmp::min(mark_pos.absolute - mark_pos.absolute_line_start + nlines[offset-1] + 1, nlines[offset]);
+                        new_pos.absolute = advance_chars(text, nlines[offset-1] + 1, column, nlines[offset]);
                         new_pos.line_number = mark_pos.line_number + offset;
                         new_pos.absolute_line_start = new_line_start;
                     }
@@ -509,7 +713,10 @@ impl Buffer {
     }
 
     /// Sets the mark to a given absolute index. Adds a new mark or overwrites an existing mark.
+    ///
+    /// If the index falls inside a multi-byte char, the mark is placed at the start of that char.
     pub fn set_mark(&mut self, mark: Mark, idx: usize) {
+        let idx = char_start(&self.text, idx);
         if let Some(mark_pos) = get_line_info(idx, &self.text) {
             if let Some(existing_pos) = self.marks.get_mut(&mark) {
                 existing_pos.absolute = mark_pos.absolute;
@@ -521,22 +728,25 @@ impl Buffer {
         }
     }
 
-    // Remove the chars in the range from start to end
+    /// Removes the mark, if it is set.
+    pub fn clear_mark(&mut self, mark: Mark) {
+        self.marks.remove(&mark);
+    }
+
+    /// Remove the chars in the range from start to end, returning them.
     pub fn remove_range(&mut self, start: usize, end: usize) -> Option<Vec<u8>> {
+        if self.read_only { return None }
         self.dirty = true;
-        let text = &mut self.text;
-        let mut transaction = self.log.start(start);
-        let mut vec = (start..end)
-            .rev()
-            .filter_map(|idx| text.remove(idx).map(|ch| (idx, ch)))
-            .inspect(|&(idx, ch)| transaction.log(Change::Remove(idx, ch), idx))
-            .map(|(_, ch)| ch)
-            .collect::<Vec<u8>>();
-        vec.reverse();
-        Some(vec)
-    }
-
-    // Remove the chars between mark and object
+        let removed = self.text.remove_range(start, end);
+        if !removed.is_empty() {
+            let mut transaction = self.log.start(start);
+            transaction.log(Change::RemoveRange(start, removed.clone()), start);
+        }
+        self.text_changed(start);
+        Some(removed)
+    }
+
+    /// Remove the chars between mark and object, returning them.
     pub fn remove_from_mark_to_object(&mut self, mark: Mark, object: TextObject) -> Option<Vec<u8>> {
 
         let (start, end) = {
@@ -552,6 +762,7 @@ impl Buffer {
         self.remove_range(start, end)
     }
 
+    /// Remove the chars of a text object, returning them.
     pub fn remove_object(&mut self, object: TextObject) -> Option<Vec<u8>> {
         let object_start = TextObject { kind: object.kind.with_anchor(Anchor::Start), offset: object.offset };
         let object_end = TextObject { kind: object.kind.with_anchor(Anchor::End), offset: object.offset };
@@ -566,84 +777,142 @@ impl Buffer {
     }
 
     /// Insert a char at the mark.
-    pub fn insert_char(&mut self, mark: Mark, ch: u8) {
-        if let Some(mark_pos) = self.marks.get(&mark) {
-            self.text.insert(mark_pos.absolute, ch);
-            let mut transaction = self.log.start(mark_pos.absolute);
-            transaction.log(Change::Insert(mark_pos.absolute, ch), mark_pos.absolute);
-            self.dirty = true;
+    ///
+    /// The char is stored UTF-8 encoded, all of its bytes are logged in a single change.
+    pub fn insert_char(&mut self, mark: Mark, ch: char) {
+        let idx = match self.marks.get(&mark) {
+            Some(mark_pos) if !self.read_only => mark_pos.absolute,
+            _ => return,
+        };
+        {
+            let mut bytes = [0; 4];
+            let bytes = ch.encode_utf8(&mut bytes).as_bytes();
+            self.text.insert_slice(idx, bytes);
+            self.log.start(idx).log(Change::InsertStr(idx, bytes.to_vec()), idx);
         }
+        self.dirty = true;
+        self.text_changed(idx);
     }
 
     /// Redo most recently undone action.
-    pub fn redo(&mut self) -> Option<&LogEntry> {
-        if let Some(transaction) = self.log.redo() {
-            commit(transaction, &mut self.text);
-            Some(transaction)
-        } else { None }
+    ///
+    /// Returns where the change leaves the cursor.
+    pub fn redo(&mut self) -> Option<usize> {
+        let entry = self.log.redo();
+        self.apply_log_entries(entry)
     }
 
     /// Undo most recently performed action.
-    pub fn undo(&mut self) -> Option<&LogEntry> {
-        if let Some(transaction) = self.log.undo() {
-            commit(transaction, &mut self.text);
-            Some(transaction)
-        } else { None }
+    ///
+    /// Returns where the change leaves the cursor.
+    pub fn undo(&mut self) -> Option<usize> {
+        let entry = self.log.undo();
+        self.apply_log_entries(entry)
     }
 
-}
+    /// Go back to the state of the text made before the current one, which
+    /// may be on another branch of the undo tree.
+    pub fn undo_older(&mut self) -> Option<usize> {
+        let entries = self.log.older();
+        self.apply_log_entries(entries)
+    }
 
+    /// Go forward to the state of the text made after the current one.
+    pub fn undo_newer(&mut self) -> Option<usize> {
+        let entries = self.log.newer();
+        self.apply_log_entries(entries)
+    }
 
-// This is a bit of a hack to get around an error I was getting when
-// implementing From<R: Read> for Buffer with From<PathBuf> for Buffer.
-// The compiler was telling me this was a conflicting implementation even
-// though Read is not implemented for PathBuf. Changing R: Read to
-// R: Read + BufferFrom fixes the error.
-//
-// TODO: investigate this further - possible compiler bug?
-pub trait BufferFrom {}
-impl BufferFrom for Stdin {}
-impl BufferFrom for File {}
-
-impl From<PathBuf> for Buffer {
-    fn from(path: PathBuf) -> Buffer {
-        match File::open(&path) {
-            Ok(file) => {
-                let mut buf = Buffer::from(file);
-                buf.file_path = Some(path);
-                buf
-            }
-            Err(_) => {
-                Buffer::new()
+    /// Go to any state of the text in the undo tree, numbered in the order they were made.
+    pub fn undo_to(&mut self, state: usize) -> Option<usize> {
+        let entries = self.log.goto(state);
+        self.apply_log_entries(entries)
+    }
+
+    /// Make the changes of entries taken from the log, returning where the
+    /// last one leaves the cursor.
+    fn apply_log_entries<I: IntoIterator<Item = LogEntry>>(&mut self, entries: I) -> Option<usize> {
+        let mut point = None;
+        for transaction in entries {
+            commit(&transaction, &mut self.text);
+            if let Some(ref mut syntax) = self.syntax {
+                syntax.invalidate_from(self.text.line_of(first_change(&transaction)));
             }
+            point = Some(transaction.end_point);
         }
+        point
     }
+
 }
 
-impl<R: Read + BufferFrom> From<R> for Buffer {
-    fn from(mut reader: R) -> Buffer {
-        let mut buff = Buffer::new();
-        let mut contents = String::new();
-        if reader.read_to_string(&mut contents).is_ok() {
-            buff.text.extend(contents.bytes());
+
+/// Why a file couldn't be loaded into a buffer
+#[derive(Debug)]
+pub enum OpenError {
+    /// There is no file at the path yet
+    NotFound,
+    PermissionDenied,
+    IsDirectory,
+    Io(io::Error),
+}
+
+impl From<io::Error> for OpenError {
+    fn from(e: io::Error) -> OpenError {
+        match e.kind() {
+            io::ErrorKind::NotFound => OpenError::NotFound,
+            io::ErrorKind::PermissionDenied => OpenError::PermissionDenied,
+            io::ErrorKind::IsADirectory => OpenError::IsDirectory,
+            _ => OpenError::Io(e),
         }
-        buff
     }
 }
 
-impl From<Input> for Buffer {
-    fn from(input: Input) -> Buffer {
-        match input {
-            Input::Filename(path) => {
-                match path {
-                    Some(path) => Buffer::from(PathBuf::from(path)),
-                    None       => Buffer::new(),
-                }
-            },
-            Input::Stdin(reader) => {
-                Buffer::from(reader)
+impl fmt::Display for OpenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OpenError::NotFound => write!(f, "no such file"),
+            OpenError::PermissionDenied => write!(f, "permission denied"),
+            OpenError::IsDirectory => write!(f, "is a directory"),
+            OpenError::Io(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Default for Buffer {
+    fn default() -> Buffer {
+        Buffer::new()
+    }
+}
+
+impl Buffer {
+    /// Load a file into a new buffer, which saves back to it.
+    pub fn open(path: PathBuf) -> Result<Buffer, OpenError> {
+        // a directory can be opened like a file on some platforms, only
+        // failing once it is read
+        if fs::metadata(&path)?.is_dir() {
+            return Err(OpenError::IsDirectory)
+        }
+        let mut buffer = Buffer::read_from(&mut File::open(&path)?)?;
+        buffer.set_file_path(path);
+        Ok(buffer)
+    }
+
+    /// Load text from a reader, such as stdin, into a new buffer without a file name.
+    pub fn read_from(reader: &mut dyn Read) -> io::Result<Buffer> {
+        let mut buffer = Buffer::new();
+        let mut contents = Vec::new();
+        reader.read_to_end(&mut contents)?;
+        match FileFormat::detect(&contents) {
+            Some((format, text)) => {
+                buffer.format = format;
+                buffer.text.extend(text);
+            }
+            None => {
+                buffer.text.extend(fileformat::hex_dump(&contents));
+                buffer.read_only = true;
             }
         }
+        Ok(buffer)
     }
 }
 
@@ -651,9 +920,8 @@ impl From<Input> for Buffer {
 impl WordEdgeMatch {
     /// If c1 -> c2 is the start of a word.
     /// If end of word matching is wanted then pass the chars in reversed.
-    fn is_word_edge(&self, c1: &u8, c2: &u8) -> bool {
-        // FIXME: unicode support - issue #69
-        match (self, *c1 as char, *c2 as char) {
+    fn is_word_edge(&self, c1: char, c2: char) -> bool {
+        match (self, c1, c2) {
             (_, '\n', '\n') => true, // Blank lines are always counted as a word
             (&WordEdgeMatch::Whitespace, c1, c2) => c1.is_whitespace() && !c2.is_whitespace(),
             // (&WordEdgeMatch::Alphabet, c1, c2) if c1.is_whitespace() => !c2.is_whitespace(),
@@ -664,50 +932,131 @@ impl WordEdgeMatch {
     }
 }
 
-fn get_words(mark: usize, n_words: usize, edger: WordEdgeMatch, text: &GapBuffer<u8>) -> Option<usize> {
+fn get_words(mark: usize, n_words: usize, edger: WordEdgeMatch, text: &Rope) -> Option<usize> {
     let text_len = text.len();
     if text_len == 0 { return None; }
 
     (mark + 1..text_len - 1)
-        .filter(|idx| edger.is_word_edge(&text[*idx - 1], &text[*idx]))
+        .filter(|idx| is_char_boundary(text, *idx))
+        .filter(|idx| edger.is_word_edge(char_before(*idx, text), char_at(*idx, text)))
         .take(n_words)
         .last()
 }
 
-fn get_words_rev(mark: usize, n_words: usize, edger: WordEdgeMatch, text: &GapBuffer<u8>) -> Option<usize> {
+fn get_words_rev(mark: usize, n_words: usize, edger: WordEdgeMatch, text: &Rope) -> Option<usize> {
     (1..mark)
         .rev()
-        .filter(|idx| edger.is_word_edge(&text[*idx - 1], &text[*idx]))
+        .filter(|idx| is_char_boundary(text, *idx))
+        .filter(|idx| edger.is_word_edge(char_before(*idx, text), char_at(*idx, text)))
         .take(n_words)
         .last()
 }
 
-fn get_line_info(mark: usize, text: &GapBuffer<u8>) -> Option<MarkPosition> {
-    let val = cmp::min(mark, text.len());
-    let line_starts: Vec<usize> = (0..val + 1).rev().filter(|idx| *idx == 0 || text[*idx - 1] == b'\n').collect();
+fn get_line_info(mark: usize, text: &Rope) -> Option<MarkPosition> {
+    let line_number = text.line_of(mark);
 
-
-    if line_starts.is_empty() {
-        None
-    } else {
+    text.line_start(line_number).map(|line_start| {
         let mut mark_pos = MarkPosition::start();
-        mark_pos.absolute_line_start = line_starts[0];
-        mark_pos.line_number = line_starts.len() - 1;
+        mark_pos.absolute_line_start = line_start;
+        mark_pos.line_number = line_number;
         mark_pos.absolute = mark;
-        Some(mark_pos)
+        mark_pos
+    })
+}
+
+/// Whether the byte at `idx` is the first byte of a UTF-8 encoded char.
+///
+/// The end of the text counts as a char boundary.
+fn is_char_boundary(text: &Rope, idx: usize) -> bool {
+    idx >= text.len() || text[idx] & 0b1100_0000 != 0b1000_0000
+}
+
+/// The index of the first byte of the char containing `idx`.
+fn char_start(text: &Rope, idx: usize) -> usize {
+    let mut idx = idx;
+    while idx > 0 && !is_char_boundary(text, idx) {
+        idx -= 1;
+    }
+    idx
+}
+
+/// The index `n` chars after `idx`, stopping early at `limit`.
+fn advance_chars(text: &Rope, idx: usize, n: usize, limit: usize) -> usize {
+    let limit = cmp::min(limit, text.len());
+    let mut idx = idx;
+    for _ in 0..n {
+        if idx >= limit { break }
+        idx += 1;
+        while !is_char_boundary(text, idx) {
+            idx += 1;
+        }
+    }
+    cmp::min(idx, limit)
+}
+
+/// The index `n` chars before `idx`, or None if there are fewer than `n` chars before it.
+fn retreat_chars(text: &Rope, idx: usize, n: usize) -> Option<usize> {
+    let mut idx = idx;
+    for _ in 0..n {
+        if idx == 0 { return None }
+        idx = char_start(text, idx - 1);
     }
+    Some(idx)
+}
+
+/// Number of chars in the byte range from `start` to `end`.
+fn count_chars(text: &Rope, start: usize, end: usize) -> usize {
+    let end = cmp::min(end, text.len());
+    (start..end).filter(|idx| is_char_boundary(text, *idx)).count()
+}
+
+/// Decodes the char starting at `idx`.
+fn char_at(idx: usize, text: &Rope) -> char {
+    let mut bytes = [0u8; 4];
+    let mut len = 0;
+    while len < 4 && idx + len < text.len() && (len == 0 || !is_char_boundary(text, idx + len)) {
+        bytes[len] = text[idx + len];
+        len += 1;
+    }
+    ::std::str::from_utf8(&bytes[..len]).ok()
+        .and_then(|s| s.chars().next())
+        .unwrap_or('\u{FFFD}')
+}
 
+/// Decodes the char ending just before `idx`.
+fn char_before(idx: usize, text: &Rope) -> char {
+    char_at(char_start(text, idx - 1), text)
+}
+
+/// Display width of the text in the byte range from `start` to `end`.
+fn display_width(text: &Rope, start: usize, end: usize, tab_width: usize) -> usize {
+    let end = cmp::min(end, text.len());
+    (start..end)
+        .filter(|idx| is_char_boundary(text, *idx))
+        .fold(0, |width, idx| width + utils::char_width(char_at(idx, text), false, tab_width, width).unwrap_or(0))
+}
+
+/// Start index and text of the given line, without its line break.
+fn line_text(text: &Rope, line: usize) -> Option<(usize, String)> {
+    let start = text.line_start(line)?;
+    let end = text.next_newline(start).unwrap_or_else(|| text.len());
+    Some((start, String::from_utf8_lossy(&text.slice(start, end)).into_owned()))
+}
+
+/// The lowest index touched by the changes of a log entry
+fn first_change(transaction: &LogEntry) -> usize {
+    transaction.changes.iter().map(Change::idx).min().unwrap_or(0)
 }
 
 /// Performs a transaction on the passed in buffer.
-fn commit(transaction: &LogEntry, text: &mut GapBuffer<u8>) {
+fn commit(transaction: &LogEntry, text: &mut Rope) {
     for change in &transaction.changes {
         match *change {
-            Change::Insert(idx, ch) => {
-                text.insert(idx, ch);
+            Change::InsertStr(idx, ref bytes) => {
+                text.insert_slice(idx, bytes);
             }
-            Change::Remove(idx, _) => {
-                text.remove(idx);
+            Change::RemoveRange(idx, ref bytes) => {
+                text.remove_range(idx, idx + bytes.len());
             }
         }
     }
@@ -716,10 +1065,16 @@ fn commit(transaction: &LogEntry, text: &mut GapBuffer<u8>) {
 #[cfg(test)]
 mod test {
 
-    use crate::buffer::{Buffer, Mark, MarkPosition};
+    use crate::buffer::{Buffer, Mark, MarkPosition, OpenError};
     use crate::textobject::{TextObject, Offset, Kind, Anchor};
     use super::get_line_info;
 
+    use std::fs;
+    use std::path::PathBuf;
+
+    use regex::Regex;
+    use tempdir::TempDir;
+
     fn setup_buffer(testcase: &'static str) -> Buffer {
         let mut buffer = Buffer::new();
         buffer.text.extend(testcase.bytes());
@@ -1054,7 +1409,7 @@ mod test {
     #[test]
     fn test_insert() {
         let mut buffer = setup_buffer("");
-        buffer.insert_char(Mark::Cursor(0), b'A');
+        buffer.insert_char(Mark::Cursor(0), 'A');
         assert_eq!(buffer.len(), 2);
         assert_eq!(buffer.lines().next().unwrap(), [b'A']);
     }
@@ -1137,6 +1492,88 @@ mod test {
         assert_eq!(mark_pos, get_line_info(10, &buffer.text).unwrap());
     }
 
+    #[test]
+    fn test_insert_multibyte() {
+        let mut buffer = setup_buffer("");
+        let mark = Mark::Cursor(0);
+        for ch in "né漢😀".chars() {
+            buffer.insert_char(mark, ch);
+            let obj = TextObject {
+                kind: Kind::Char,
+                offset: Offset::Forward(1, mark),
+            };
+            buffer.set_mark_to_object(mark, obj);
+        }
+
+        assert_eq!(buffer.lines().next().unwrap(), "né漢😀".as_bytes());
+        assert_eq!(buffer.get_mark_idx(mark).unwrap(), 10);
+        assert_eq!(buffer.get_mark_display_coords(mark).unwrap(), (6, 0));
+    }
+
+    #[test]
+    fn test_remove_multibyte() {
+        let mut buffer = setup_buffer("aé漢b");
+        let mark = Mark::Cursor(0);
+        buffer.set_mark(mark, 6);
+        let obj = TextObject {
+            kind: Kind::Char,
+            offset: Offset::Backward(1, mark),
+        };
+        buffer.remove_from_mark_to_object(mark, obj);
+
+        assert_eq!(buffer.lines().next().unwrap(), "aéb".as_bytes());
+
+        buffer.undo();
+        assert_eq!(buffer.lines().next().unwrap(), "aé漢b".as_bytes());
+    }
+
+    #[test]
+    fn move_mark_over_multibyte_chars() {
+        let mut buffer = setup_buffer("añb😀c");
+        let mark = Mark::Cursor(0);
+        let right = TextObject {
+            kind: Kind::Char,
+            offset: Offset::Forward(2, mark),
+        };
+        let left = TextObject {
+            kind: Kind::Char,
+            offset: Offset::Backward(1, mark),
+        };
+
+        buffer.set_mark_to_object(mark, right);
+        assert_eq!(buffer.get_mark_idx(mark).unwrap(), 3);
+
+        buffer.set_mark_to_object(mark, right);
+        assert_eq!(buffer.get_mark_idx(mark).unwrap(), 8);
+
+        buffer.set_mark_to_object(mark, left);
+        assert_eq!(buffer.get_mark_idx(mark).unwrap(), 4);
+        assert_eq!(buffer.get_mark_display_coords(mark).unwrap(), (3, 0));
+    }
+
+    #[test]
+    fn move_mark_line_down_multibyte() {
+        let mut buffer = setup_buffer("ééé\nabc");
+        let mark = Mark::Cursor(0);
+        let obj = TextObject {
+            kind: Kind::Line(Anchor::Same),
+            offset: Offset::Forward(1, mark),
+        };
+
+        buffer.set_mark(mark, 4);
+        buffer.set_mark_to_object(mark, obj);
+
+        assert_eq!(*buffer.marks.get(&mark).unwrap(), MarkPosition::from((9, 7, 1)));
+    }
+
+    #[test]
+    fn test_set_mark_inside_char() {
+        let mut buffer = setup_buffer("a漢");
+        buffer.set_mark(Mark::Cursor(0), 2);
+
+        assert_eq!(buffer.get_mark_idx(Mark::Cursor(0)).unwrap(), 1);
+    }
+
     #[test]
     fn get_move_word_forward_emtpy_buffer() {
         let mut buffer = setup_buffer("");
@@ -1154,4 +1591,120 @@ mod test {
         assert_eq!(buffer.get_mark_display_coords(mark).unwrap(), (0, 0));
     }
 
+    #[test]
+    fn test_find_forward() {
+        let buffer = setup_buffer("foo bar\nbar foo\n");
+        let pattern = Regex::new("foo").unwrap();
+
+        assert_eq!(buffer.find_forward(&pattern, 0), Some((0, 3)));
+        assert_eq!(buffer.find_forward(&pattern, 1), Some((12, 15)));
+        // wraps around to the start
+        assert_eq!(buffer.find_forward(&pattern, 13), Some((0, 3)));
+    }
+
+    #[test]
+    fn test_find_backward() {
+        let buffer = setup_buffer("foo bar\nbar foo\n");
+        let pattern = Regex::new("bar").unwrap();
+
+        assert_eq!(buffer.find_backward(&pattern, 9), Some((8, 11)));
+        assert_eq!(buffer.find_backward(&pattern, 8), Some((4, 7)));
+        // wraps around to the end
+        assert_eq!(buffer.find_backward(&pattern, 4), Some((8, 11)));
+    }
+
+    #[test]
+    fn test_find_no_match() {
+        let buffer = setup_buffer("foo bar");
+        let pattern = Regex::new("baz").unwrap();
+
+        assert_eq!(buffer.find_forward(&pattern, 0), None);
+        assert_eq!(buffer.find_backward(&pattern, 0), None);
+    }
+
+    #[test]
+    fn test_find_regex_multibyte() {
+        let buffer = setup_buffer("ééé a1 b22");
+        let pattern = Regex::new("[a-z][0-9]+").unwrap();
+
+        assert_eq!(buffer.find_forward(&pattern, 0), Some((7, 9)));
+        assert_eq!(buffer.find_forward(&pattern, 8), Some((10, 13)));
+    }
+
+    #[test]
+    fn test_replace_ranges() {
+        let mut buffer = setup_buffer("foo bar\nbar foo\n");
+        let pattern = Regex::new("foo").unwrap();
+        let replacements = buffer.replacements(&pattern, "quux");
+        buffer.replace_ranges(&replacements);
+
+        assert_eq!(buffer.lines().collect::<Vec<_>>(), vec![b"quux bar\n".to_vec(), b"bar quux\n".to_vec(), b"".to_vec()]);
+    }
+
+    #[test]
+    fn test_replace_capture_groups() {
+        let mut buffer = setup_buffer("a=1, b=2");
+        let pattern = Regex::new("([a-z])=([0-9])").unwrap();
+        let replacements = buffer.replacements(&pattern, "$2=$1 $$");
+        buffer.replace_ranges(&replacements);
+
+        assert_eq!(buffer.lines().next().unwrap(), b"1=a $, 2=b $");
+    }
+
+    #[test]
+    fn test_lines_range() {
+        let mut buffer = setup_buffer("one\ntwo\nthree");
+        let mark = Mark::Cursor(0);
+        buffer.set_mark(mark, 5);
+
+        assert_eq!(buffer.lines_range(Offset::Forward(0, mark)), Some((4, 8)));
+        assert_eq!(buffer.lines_range(Offset::Forward(5, mark)), Some((4, 13)));
+        assert_eq!(buffer.lines_range(Offset::Backward(5, mark)), Some((0, 8)));
+        assert_eq!(buffer.lines_range(Offset::Absolute(1)), Some((0, 4)));
+        assert_eq!(buffer.lines_range(Offset::Absolute(4)), None);
+    }
+
+    #[test]
+    fn test_undo_replace_ranges() {
+        let mut buffer = setup_buffer("foo bar foo");
+        let pattern = Regex::new("foo").unwrap();
+        let replacements = buffer.replacements(&pattern, "x");
+        buffer.replace_ranges(&replacements);
+        assert_eq!(buffer.lines().next().unwrap(), b"x bar x");
+
+        buffer.undo();
+        assert_eq!(buffer.lines().next().unwrap(), b"foo bar foo");
+        assert!(buffer.undo().is_none());
+    }
+
+    #[test]
+    fn test_highlight_after_edit() {
+        let mut buffer = setup_buffer("/* a\nb */ c");
+        buffer.set_file_path(PathBuf::from("test.rs"));
+        buffer.set_mark(Mark::DisplayMark(0), 5);
+
+        let spans = buffer.highlight_lines(Mark::DisplayMark(0), 1);
+        assert_eq!(spans[0][0].class, "comment");
+
+        // without the start of the comment, the second line isn't in one anymore
+        buffer.remove_range(0, 2);
+        buffer.set_mark(Mark::DisplayMark(0), 3);
+        let spans = buffer.highlight_lines(Mark::DisplayMark(0), 1);
+        assert!(spans[0].iter().all(|span| span.class != "comment"));
+    }
+
+    #[test]
+    fn test_open() {
+        let dir = TempDir::new("credits").unwrap();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "one\r\ntwo\r\n").unwrap();
+
+        let buffer = Buffer::open(path.clone()).unwrap();
+        assert_eq!(buffer.file_path, Some(path));
+        assert_eq!(buffer.slice(0, buffer.len() - 1), b"one\ntwo\n");
+
+        assert!(matches!(Buffer::open(dir.path().join("missing.txt")), Err(OpenError::NotFound)));
+        assert!(matches!(Buffer::open(dir.path().to_path_buf()), Err(OpenError::IsDirectory)));
+    }
+
 }
@@ -0,0 +1,212 @@
+//! Sharing cut and copied text with the system clipboard.
+//!
+//! Which provider is used is picked in the configuration. OSC 52 asks the
+//! terminal to set the clipboard, which also works over SSH, while a command
+//! provider pipes the text to a program such as `xclip` or `wl-copy`.
+
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+
+use rustc_serialize::base64::{ToBase64, STANDARD};
+
+/// Somewhere text can be copied to, and maybe pasted back from.
+pub trait Clipboard {
+    /// Put text on the clipboard
+    fn copy(&mut self, text: &str) -> Result<(), String>;
+
+    /// The text on the clipboard, None if it can't be read
+    fn paste(&mut self) -> Result<Option<String>, String>;
+}
+
+/// Sets the clipboard with the OSC 52 terminal escape sequence.
+///
+/// Terminals don't let programs read the clipboard this way, so this can
+/// only copy.
+pub struct Osc52;
+
+impl Clipboard for Osc52 {
+    fn copy(&mut self, text: &str) -> Result<(), String> {
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        out.write_all(osc52_sequence(text).as_bytes())
+            .and_then(|_| out.flush())
+            .map_err(|e| e.to_string())
+    }
+
+    fn paste(&mut self) -> Result<Option<String>, String> {
+        Ok(None)
+    }
+}
+
+/// The escape sequence which sets the clipboard to `text`
+fn osc52_sequence(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", text.as_bytes().to_base64(STANDARD))
+}
+
+/// Runs external programs to copy and paste.
+pub struct CommandClipboard {
+    copy_command: Vec<String>,
+    paste_command: Vec<String>,
+}
+
+impl CommandClipboard {
+    /// Commands are split on whitespace into the program and its arguments,
+    /// as a shell would: quotes keep spaces in an argument, and a backslash
+    /// takes the next char literally.
+    ///
+    /// The text to copy is written to the copy command's standard input, and
+    /// the paste command is expected to print the clipboard. Without a paste
+    /// command, the clipboard is never read.
+    pub fn new(copy_command: &str, paste_command: &str) -> CommandClipboard {
+        CommandClipboard {
+            copy_command: split_command(copy_command),
+            paste_command: split_command(paste_command),
+        }
+    }
+}
+
+/// Split a command line into words, keeping quoted spaces.
+///
+/// Inside single quotes everything is literal, inside double quotes only
+/// `\"` and `\\` are escapes. A missing closing quote ends at the end of the line.
+fn split_command(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                word.extend(chars.by_ref().take_while(|&c| c != '\''));
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => match chars.next() {
+                            Some(c @ '"') | Some(c @ '\\') => word.push(c),
+                            Some(c) => { word.push('\\'); word.push(c) }
+                            None => word.push('\\'),
+                        },
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => word.get_or_insert_with(String::new).extend(chars.next()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    words
+}
+
+impl Clipboard for CommandClipboard {
+    fn copy(&mut self, text: &str) -> Result<(), String> {
+        let (program, args) = match self.copy_command.split_first() {
+            Some(command) => command,
+            None => return Ok(()),
+        };
+        let error = |e: io::Error| format!("{}: {}", program, e);
+
+        let mut child = Command::new(program).args(args)
+            .stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::null())
+            .spawn().map_err(error)?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes()).map_err(error)?;
+        }
+        let status = child.wait().map_err(error)?;
+        if status.success() { Ok(()) } else { Err(format!("{} failed: {}", program, status)) }
+    }
+
+    fn paste(&mut self) -> Result<Option<String>, String> {
+        let (program, args) = match self.paste_command.split_first() {
+            Some(command) => command,
+            None => return Ok(None),
+        };
+        let error = |e: io::Error| format!("{}: {}", program, e);
+
+        let mut child = Command::new(program).args(args)
+            .stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::null())
+            .spawn().map_err(error)?;
+        let mut output = Vec::new();
+        if let Some(mut stdout) = child.stdout.take() {
+            stdout.read_to_end(&mut output).map_err(error)?;
+        }
+        let status = child.wait().map_err(error)?;
+        if !status.success() {
+            return Err(format!("{} failed: {}", program, status))
+        }
+        Ok(Some(String::from_utf8_lossy(&output).into_owned()))
+    }
+}
+
+/// Keeps the clipboard in memory, for when there is no system clipboard to use.
+#[derive(Default)]
+pub struct MemoryClipboard {
+    text: Option<String>,
+}
+
+impl MemoryClipboard {
+    pub fn new() -> MemoryClipboard {
+        MemoryClipboard { text: None }
+    }
+}
+
+impl Clipboard for MemoryClipboard {
+    fn copy(&mut self, text: &str) -> Result<(), String> {
+        self.text = Some(String::from(text));
+        Ok(())
+    }
+
+    fn paste(&mut self) -> Result<Option<String>, String> {
+        Ok(self.text.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{Clipboard, CommandClipboard, MemoryClipboard, osc52_sequence, split_command};
+
+    #[test]
+    fn test_osc52_sequence() {
+        assert_eq!(osc52_sequence("hello"), "\x1b]52;c;aGVsbG8=\x07");
+    }
+
+    #[test]
+    fn test_memory_clipboard() {
+        let mut clipboard = MemoryClipboard::new();
+        assert_eq!(clipboard.paste(), Ok(None));
+        clipboard.copy("text").unwrap();
+        assert_eq!(clipboard.paste(), Ok(Some(String::from("text"))));
+    }
+
+    #[test]
+    fn test_split_command() {
+        assert_eq!(split_command("  xclip -selection  clipboard "), vec!["xclip", "-selection", "clipboard"]);
+        assert_eq!(split_command(r#"sh -c 'printf "%s" x' "a \"b\" \\ \n""#), vec!["sh", "-c", r#"printf "%s" x"#, r#"a "b" \ \n"#]);
+        assert_eq!(split_command(r"a\ b '' c'd"), vec!["a b", "", "cd"]);
+        assert!(split_command("").is_empty());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_command_clipboard() {
+        let mut clipboard = CommandClipboard::new("cat", "echo pasted");
+        assert_eq!(clipboard.copy("text"), Ok(()));
+        assert_eq!(clipboard.paste(), Ok(Some(String::from("pasted\n"))));
+
+        let mut clipboard = CommandClipboard::new("sh -c 'cat >/dev/null'", "sh -c 'echo \"two words\"'");
+        assert_eq!(clipboard.copy("text"), Ok(()));
+        assert_eq!(clipboard.paste(), Ok(Some(String::from("two words\n"))));
+
+        let mut clipboard = CommandClipboard::new("false", "");
+        assert!(clipboard.copy("text").is_err());
+        assert_eq!(clipboard.paste(), Ok(None));
+
+        let mut clipboard = CommandClipboard::new("no-such-clipboard-program", "");
+        assert!(clipboard.copy("text").is_err());
+    }
+}
@@ -1,4 +1,8 @@
+use std::fmt;
+use std::path::PathBuf;
+
 use crate::buffer::Mark;
+use crate::fileformat::LineEnding;
 use crate::textobject::{ TextObject, Offset, Kind };
 use crate::overlay::OverlayType;
 use crate::modes::ModeType;
@@ -9,17 +13,76 @@ use crate::keymap::CommandInfo;
 #[derive(Debug, Clone)]
 pub enum Instruction {
     SaveBuffer,
-    //FindFile,
+    /// The new file name, and whether to write over a file already there
+    SaveBufferAs(PathBuf, bool),
+    FindFile(PathBuf),
     ExitEditor,
 
     SetMark(Mark),
+    SetSelectionMark,
+    ExtendSelection,
+    ClearSelection,
+    Copy,
+    Cut,
+    Paste,
+    YankPop,
+    Operate(Operator),
     SetOverlay(OverlayType),
     SetMode(ModeType),
     ShowMessage(String),
     SwitchToLastBuffer,
+    SwitchToBuffer(usize),
+    NextBuffer,
+    PrevBuffer,
+    CloseBuffer,
+    Search(String),
+    FindNext,
+    FindPrev,
+    EndSearch,
+    CancelSearch,
+    Replace(String, String),
+    ReplaceAll(String, String),
+    AnswerReplace(ReplaceAnswer),
+    SetTheme(String),
+    Ex(String),
+    SetLineEnding(Option<LineEnding>),
     None,
 }
 
+/// Answers to "replace this match?" while stepping through matches
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReplaceAnswer {
+    Yes,    // replace this match and go on to the next
+    No,     // skip this match and go on to the next
+    All,    // replace this match and all the remaining ones
+    Quit,   // stop, keeping the replacements made so far
+}
+
+/// Vi operators, which act on the text between the cursor and where a motion
+/// would move it to
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Operator {
+    Delete,     // cut the text into the kill ring
+    Change,     // cut the text and start inserting in its place
+    Yank,       // copy the text into the kill ring
+    Indent,     // indent the lines
+    Dedent,     // remove a level of indentation from the lines
+}
+
+impl Operator {
+    /// The operator bound to a key in Vi's Normal mode
+    pub fn from_char(c: char) -> Option<Operator> {
+        match c {
+            'd' => Some(Operator::Delete),
+            'c' => Some(Operator::Change),
+            'y' => Some(Operator::Yank),
+            '>' => Some(Operator::Indent),
+            '<' => Some(Operator::Dedent),
+            _ => None,
+        }
+    }
+}
+
 /// Operations on the Buffer.
 /// These DO alter the text, but otherwise may NOT change editor/view state
 /// Note that these differ from `log::Change` in that they are higher-level
@@ -28,11 +91,15 @@ pub enum Instruction {
 #[derive(Debug, Clone)]
 pub enum Operation {
     Insert(char), // insert text
+    InsertTab,    // insert a tab, or spaces up to the tab width
     DeleteObject,         // delete some object
     DeleteFromMark(Mark), // delete from some mark to an object
 
     Undo,         // rewind buffer transaction log
     Redo,         // replay buffer transaction log
+    UndoOlder,    // go to the previous state in time, on any branch of the log
+    UndoNewer,    // go to the next state in time, on any branch of the log
+    UndoTo(usize), // go to any state in the log
 }
 
 #[derive(Debug, Clone)]
@@ -41,6 +108,29 @@ pub enum Action {
     Instruction(Instruction),
 }
 
+/// Why a command could not be built.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandError {
+    Unknown(String),                        // no command has this name
+    MissingArgument(&'static str),          // the command needs an argument which wasn't given
+    InvalidArgument(&'static str, String),  // an argument the command can't use, and its value
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CommandError::Unknown(ref name) => write!(f, "Unknown command: {}", name),
+            CommandError::MissingArgument(argument) => write!(f, "no {} given", argument),
+            CommandError::InvalidArgument(argument, ref value) => write!(f, "invalid {}: {}", argument, value),
+        }
+    }
+}
+
+/// An argument the command needs, or an error naming it
+fn required<T>(value: Option<T>, argument: &'static str) -> Result<T, CommandError> {
+    value.ok_or(CommandError::MissingArgument(argument))
+}
+
 /// A complete, actionable command
 #[derive(Debug, Clone)]
 pub struct Command {
@@ -51,9 +141,7 @@ pub struct Command {
 
 impl Command {
     /// Display a message
-    pub fn show_message(args: Option<BuilderArgs>) -> Command {
-        let args = args.expect("no arguments given to show_message");
-        let message = args.str_args.expect("no message provided");
+    pub fn show_message(message: String) -> Command {
         Command {
             action: Action::Instruction(Instruction::ShowMessage(message)),
             number: 0,
@@ -62,136 +150,484 @@ impl Command {
     }
 
     /// Shortcut to create an ExitEditor command
-    pub fn exit_editor(_args: Option<BuilderArgs>) -> Command {
-        Command {
+    pub fn exit_editor(_args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        Ok(Command {
             action: Action::Instruction(Instruction::ExitEditor),
             number: 0,
             object: None,
-        }
+        })
     }
 
     /// Shortcut to create a SaveBuffer command
-    pub fn save_buffer(_args: Option<BuilderArgs>) -> Command {
-        Command {
+    pub fn save_buffer(_args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        Ok(Command {
             action: Action::Instruction(Instruction::SaveBuffer),
             number: 0,
             object: None,
-        }
+        })
+    }
+
+    /// Shortcut to create a NextBuffer command
+    pub fn next_buffer(_args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        Ok(Command {
+            action: Action::Instruction(Instruction::NextBuffer),
+            number: 0,
+            object: None,
+        })
+    }
+
+    /// Shortcut to create a PrevBuffer command
+    pub fn prev_buffer(_args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        Ok(Command {
+            action: Action::Instruction(Instruction::PrevBuffer),
+            number: 0,
+            object: None,
+        })
+    }
+
+    /// Shortcut to create a CloseBuffer command
+    pub fn close_buffer(_args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        Ok(Command {
+            action: Action::Instruction(Instruction::CloseBuffer),
+            number: 0,
+            object: None,
+        })
+    }
+
+    /// Shortcut to create a SwitchToBuffer command
+    pub fn switch_to_buffer(args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        let args = args.unwrap_or_else(BuilderArgs::new);
+        let index = required(args.number, "buffer index")?;
+        Ok(Command {
+            action: Action::Instruction(Instruction::SwitchToBuffer(index as usize)),
+            number: 0,
+            object: None,
+        })
+    }
+
+    /// Shortcut to open the buffer picker
+    pub fn list_buffers(_args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        Ok(Command {
+            action: Action::Instruction(Instruction::SetOverlay(OverlayType::BufferPicker)),
+            number: 0,
+            object: None,
+        })
+    }
+
+    /// Shortcut to switch to another colour theme
+    ///
+    /// Without a theme name, this opens the theme picker to choose one.
+    pub fn set_theme(args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        let action = match args.and_then(|args| args.str_args) {
+            Some(name) => Instruction::SetTheme(name),
+            None => Instruction::SetOverlay(OverlayType::ThemePicker),
+        };
+        Ok(Command {
+            action: Action::Instruction(action),
+            number: 0,
+            object: None,
+        })
+    }
+
+    /// Shortcut to save the buffer under a new file name
+    ///
+    /// Without a path, this opens the file prompt to ask for one. Writing
+    /// over another file is confirmed first, unless the char `!` is given.
+    pub fn save_buffer_as(args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        let (path, overwrite) = match args {
+            Some(args) => (args.str_args, args.char_args == Some('!')),
+            None => (None, false),
+        };
+        let action = match path {
+            Some(path) => Instruction::SaveBufferAs(PathBuf::from(path), overwrite),
+            None => Instruction::SetOverlay(OverlayType::SaveBufferAs),
+        };
+        Ok(Command {
+            action: Action::Instruction(action),
+            number: 0,
+            object: None,
+        })
+    }
+
+    /// Shortcut to open a file
+    ///
+    /// Without a path, this opens the file prompt to ask for one.
+    pub fn find_file(args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        let action = match args.and_then(|args| args.str_args) {
+            Some(path) => Instruction::FindFile(PathBuf::from(path)),
+            None => Instruction::SetOverlay(OverlayType::FindFile),
+        };
+        Ok(Command {
+            action: Action::Instruction(action),
+            number: 0,
+            object: None,
+        })
+    }
+
+    /// Shortcut to search for a pattern, which is a regex
+    ///
+    /// Without a pattern, this opens the search prompt to ask for one.
+    pub fn search(args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        let action = match args.and_then(|args| args.str_args) {
+            Some(pattern) => Instruction::Search(pattern),
+            None => Instruction::SetOverlay(OverlayType::Search),
+        };
+        Ok(Command {
+            action: Action::Instruction(action),
+            number: 0,
+            object: None,
+        })
+    }
+
+    /// Shortcut to open the search prompt with regex search turned on
+    pub fn search_regex(_args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        Ok(Command {
+            action: Action::Instruction(Instruction::SetOverlay(OverlayType::RegexSearch)),
+            number: 0,
+            object: None,
+        })
+    }
+
+    /// Shortcut to create a FindNext command
+    pub fn find_next(_args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        Ok(Command {
+            action: Action::Instruction(Instruction::FindNext),
+            number: 0,
+            object: None,
+        })
+    }
+
+    /// Shortcut to create a FindPrev command
+    pub fn find_prev(_args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        Ok(Command {
+            action: Action::Instruction(Instruction::FindPrev),
+            number: 0,
+            object: None,
+        })
+    }
+
+    /// Shortcut to create an EndSearch command
+    pub fn end_search(_args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        Ok(Command {
+            action: Action::Instruction(Instruction::EndSearch),
+            number: 0,
+            object: None,
+        })
+    }
+
+    /// Shortcut to create a CancelSearch command
+    pub fn cancel_search(_args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        Ok(Command {
+            action: Action::Instruction(Instruction::CancelSearch),
+            number: 0,
+            object: None,
+        })
+    }
+
+    /// Shortcut to replace matches of a pattern, asking about each one
+    ///
+    /// Without a pattern and replacement, this opens the replace prompt to ask for them.
+    pub fn replace(args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        let action = match args.map(|args| (args.str_args, args.replacement)) {
+            Some((Some(pattern), Some(replacement))) => Instruction::Replace(pattern, replacement),
+            _ => Instruction::SetOverlay(OverlayType::Replace),
+        };
+        Ok(Command {
+            action: Action::Instruction(action),
+            number: 0,
+            object: None,
+        })
+    }
+
+    /// Shortcut to replace all matches of a pattern
+    ///
+    /// Without a pattern and replacement, this opens the replace prompt to ask for them.
+    pub fn replace_all(args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        let action = match args.map(|args| (args.str_args, args.replacement)) {
+            Some((Some(pattern), Some(replacement))) => Instruction::ReplaceAll(pattern, replacement),
+            _ => Instruction::SetOverlay(OverlayType::ReplaceAll),
+        };
+        Ok(Command {
+            action: Action::Instruction(action),
+            number: 0,
+            object: None,
+        })
+    }
+
+    /// Shortcut to answer whether to replace the current match, given as one of y/n/a/q
+    pub fn answer_replace(args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        let args = args.unwrap_or_else(BuilderArgs::new);
+        let answer = match required(args.char_args, "answer")? {
+            'y' => ReplaceAnswer::Yes,
+            'n' => ReplaceAnswer::No,
+            'a' => ReplaceAnswer::All,
+            _   => ReplaceAnswer::Quit,
+        };
+        Ok(Command {
+            action: Action::Instruction(Instruction::AnswerReplace(answer)),
+            number: 0,
+            object: None,
+        })
     }
 
     /// Shortcut to create SetMode command
-    pub fn set_mode(args: Option<BuilderArgs>) -> Command {
-        let args = args.expect("no arguments given to set_mode");
-        let mode_type = args.mode_args.expect("no mode type given");
-        Command {
+    pub fn set_mode(args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        let args = args.unwrap_or_else(BuilderArgs::new);
+        let mode_type = required(args.mode_args, "mode")?;
+        Ok(Command {
             action: Action::Instruction(Instruction::SetMode(mode_type)),
             number: 0,
             object: None,
-        }
+        })
     }
 
     /// Shortcut to change the active overlay
-    pub fn set_overlay(args: Option<BuilderArgs>) -> Command {
-        let args = args.expect("no arguments given to set_overlay");
-        let overlay = args.overlay_args.expect("no overlay type given");
-        Command {
+    pub fn set_overlay(args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        let args = args.unwrap_or_else(BuilderArgs::new);
+        let overlay = required(args.overlay_args, "overlay")?;
+        Ok(Command {
             action: Action::Instruction(Instruction::SetOverlay(overlay)),
             number: 0,
             object: None,
-        }
+        })
     }
 
     /// Shortcut to create a Delete command
-    pub fn delete_char(args: Option<BuilderArgs>) -> Command {
-        let args = args.expect("no arguments given to delete_char");
-        let kind = args.kind.expect("no kind provided");
-        let offset = args.offset.expect("no offset provided");
-        Command {
+    pub fn delete_char(args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        let args = args.unwrap_or_else(BuilderArgs::new);
+        let kind = required(args.kind, "kind")?;
+        let offset = required(args.offset, "offset")?;
+        Ok(Command {
             number: 1,
             action: Action::Operation(Operation::DeleteFromMark(Mark::Cursor(0))),
             object: Some(TextObject {
                 kind: kind,
                 offset: offset
             })
-        }
+        })
     }
 
     /// Shortcut to create an Insert command
-    pub fn insert_char(args: Option<BuilderArgs>) -> Command {
-        let args = args.expect("no arguments given to insert_char");
-        let c = args.char_args.expect("no char given");
-        Command {
+    pub fn insert_char(args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        let args = args.unwrap_or_else(BuilderArgs::new);
+        let c = required(args.char_args, "char")?;
+        Ok(Command {
             number: 1,
             action: Action::Operation(Operation::Insert(c)),
             object: None,
-        }
+        })
     }
 
-    /// Shortcut to create a Delete command
-    pub fn delete(args: Option<BuilderArgs>) -> Command {
-        let args = args.expect("no arguments given to insert_char");
-        let kind = args.kind.expect("no kind provided");
-        let offset = args.offset.expect("no offset provided");
-        let repeat = args.number.unwrap_or(1);
-        Command {
-            number: repeat,
-            action: Action::Operation(Operation::DeleteFromMark(Mark::Cursor(0))),
-            object: Some(TextObject {
-                kind: kind,
-                offset: offset,
-            }),
-        }
-    }
-
-    /// Shortcut to create an Insert command
-    // FIXME: shouldn't need this method
-    pub fn insert_tab(_args: Option<BuilderArgs>) -> Command {
-        Command {
-            number: 4,
-            action: Action::Operation(Operation::Insert(' ')),
+    /// Shortcut to create an InsertTab command
+    pub fn insert_tab(_args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        Ok(Command {
+            number: 1,
+            action: Action::Operation(Operation::InsertTab),
             object: None,
-        }
+        })
     }
 
     /// Shortcut to create Undo command
-    pub fn undo(_args: Option<BuilderArgs>) -> Command {
-        Command {
+    pub fn undo(_args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        Ok(Command {
             number: 1,
             action: Action::Operation(Operation::Undo),
             object: None
-        }
+        })
+    }
+
+    /// Shortcut to go back to the state of the text made before the current one, like Vi's `g-`
+    pub fn undo_older(_args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        Ok(Command {
+            number: 1,
+            action: Action::Operation(Operation::UndoOlder),
+            object: None
+        })
+    }
+
+    /// Shortcut to go forward to the state of the text made after the current one, like Vi's `g+`
+    pub fn undo_newer(_args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        Ok(Command {
+            number: 1,
+            action: Action::Operation(Operation::UndoNewer),
+            object: None
+        })
+    }
+
+    /// Shortcut to go to a state of the text, numbered in the order they were made
+    pub fn undo_to(args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        let args = args.unwrap_or_else(BuilderArgs::new);
+        let state = required(args.number, "undo state")?;
+        Ok(Command {
+            number: 1,
+            action: Action::Operation(Operation::UndoTo(state as usize)),
+            object: None
+        })
+    }
+
+    /// Shortcut to open the undo tree, to pick a state of the text to go to
+    pub fn undo_tree(_args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        Ok(Command {
+            action: Action::Instruction(Instruction::SetOverlay(OverlayType::UndoTree)),
+            number: 0,
+            object: None,
+        })
     }
 
     /// Shortcut to create Redo command
-    pub fn redo(_args: Option<BuilderArgs>) -> Command {
-        Command {
+    pub fn redo(_args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        Ok(Command {
             number: 1,
             action: Action::Operation(Operation::Redo),
             object: None
-        }
+        })
     }
 
-    pub fn move_cursor(args: Option<BuilderArgs>) -> Command {
-        let args = args.expect("no args given to movement");
-        let kind = args.kind.expect("no kind provided");
-        let offset = args.offset.expect("no offset provided");
+    pub fn move_cursor(args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        let args = args.unwrap_or_else(BuilderArgs::new);
+        let kind = required(args.kind, "kind")?;
+        let offset = required(args.offset, "offset")?;
         let repeat = args.number.unwrap_or(1);
-        Command {
+        Ok(Command {
             number: repeat,
             action: Action::Instruction(Instruction::SetMark(Mark::Cursor(0))),
             object: Some(TextObject {
                 kind: kind,
                 offset: offset
             })
-        }
+        })
     }
 
-    pub fn noop(_args: Option<BuilderArgs>) -> Command {
-        Command {
+    /// Shortcut to start a selection at the cursor
+    pub fn set_selection_mark(_args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        Ok(Command {
+            number: 0,
+            action: Action::Instruction(Instruction::SetSelectionMark),
+            object: None,
+        })
+    }
+
+    /// Shortcut to move the cursor, selecting the text it moves over
+    pub fn extend_selection(args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        let args = args.unwrap_or_else(BuilderArgs::new);
+        let kind = required(args.kind, "kind")?;
+        let offset = required(args.offset, "offset")?;
+        Ok(Command {
+            number: args.number.unwrap_or(1),
+            action: Action::Instruction(Instruction::ExtendSelection),
+            object: Some(TextObject { kind, offset }),
+        })
+    }
+
+    /// Shortcut to clear the selection
+    pub fn clear_selection(_args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        Ok(Command {
+            number: 0,
+            action: Action::Instruction(Instruction::ClearSelection),
+            object: None,
+        })
+    }
+
+    /// Shortcut to copy the selection into the kill ring
+    pub fn copy(_args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        Ok(Command {
+            number: 0,
+            action: Action::Instruction(Instruction::Copy),
+            object: None,
+        })
+    }
+
+    /// Shortcut to cut the selection into the kill ring
+    pub fn cut(_args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        Ok(Command {
+            number: 0,
+            action: Action::Instruction(Instruction::Cut),
+            object: None,
+        })
+    }
+
+    /// Shortcut to paste from the kill ring
+    pub fn paste(_args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        Ok(Command {
+            number: 0,
+            action: Action::Instruction(Instruction::Paste),
+            object: None,
+        })
+    }
+
+    /// Shortcut to replace the text just pasted with the next older kill
+    pub fn yank_pop(_args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        Ok(Command {
+            number: 0,
+            action: Action::Instruction(Instruction::YankPop),
+            object: None,
+        })
+    }
+
+    /// Shortcut to apply an operator to the text up to an object, or to the
+    /// selection when no object is given
+    ///
+    /// The operator is given by its Vi key, as the char argument.
+    pub fn operate(args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        let args = args.unwrap_or_else(BuilderArgs::new);
+        let c = required(args.char_args, "operator")?;
+        let operator = Operator::from_char(c).ok_or_else(|| CommandError::InvalidArgument("operator", c.to_string()))?;
+        let object = match (args.kind, args.offset) {
+            (Some(kind), Some(offset)) => Some(TextObject { kind, offset }),
+            _ => None,
+        };
+        Ok(Command {
+            number: 0,
+            action: Action::Instruction(Instruction::Operate(operator)),
+            object,
+        })
+    }
+
+    /// Shortcut for an operator key, which waits for a motion in Normal mode
+    ///
+    /// Anywhere else, the operator acts on the selection. The operator is
+    /// given by its Vi key, as the char argument.
+    pub fn operator_pending(args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        let args = args.unwrap_or_else(BuilderArgs::new);
+        let c = required(args.char_args, "operator")?;
+        Command::operate(Some(BuilderArgs::new().with_char_arg(c)))
+    }
+
+    /// Shortcut to change the line endings the buffer is saved with
+    ///
+    /// The argument is `lf` or `crlf`. Without one, this switches to the other.
+    pub fn set_line_ending(args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        let line_ending = match args.and_then(|args| args.str_args) {
+            Some(name) => match &*name.to_lowercase() {
+                "lf" | "unix" => Some(LineEnding::Lf),
+                "crlf" | "dos" => Some(LineEnding::CrLf),
+                _ => return Err(CommandError::InvalidArgument("line ending", name)),
+            },
+            None => None,
+        };
+        Ok(Command {
+            number: 0,
+            action: Action::Instruction(Instruction::SetLineEnding(line_ending)),
+            object: None,
+        })
+    }
+
+    /// Shortcut to run a Vi ex command, such as `w` or `%s/a/b/g`
+    pub fn ex(args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        let line = args.and_then(|args| args.str_args).unwrap_or_default();
+        Ok(Command {
+            number: 0,
+            action: Action::Instruction(Instruction::Ex(line)),
+            object: None,
+        })
+    }
+
+    pub fn noop(_args: Option<BuilderArgs>) -> Result<Command, CommandError> {
+        Ok(Command {
             number: 0,
             action: Action::Instruction(Instruction::None),
             object: None,
-        }
+        })
     }
 }
 
@@ -200,6 +636,7 @@ pub struct BuilderArgs {
     pub char_args: Option<char>,
     pub number: Option<i32>,
     pub str_args: Option<String>,
+    pub replacement: Option<String>,
     pub mode_args: Option<ModeType>,
     pub overlay_args: Option<OverlayType>,
     pub kind: Option<Kind>,
@@ -212,6 +649,7 @@ impl BuilderArgs {
             char_args: None,
             number: None,
             str_args: None,
+            replacement: None,
             mode_args: None,
             overlay_args: None,
             kind: None,
@@ -231,6 +669,12 @@ impl BuilderArgs {
         self
     }
 
+    pub fn with_replacement(mut self, s: String) -> BuilderArgs {
+        self.replacement = Some(s);
+
+        self
+    }
+
     pub fn with_number(mut self, n: i32) -> BuilderArgs {
         self.number = Some(n);
 
@@ -267,4 +711,5 @@ pub enum BuilderEvent {
     Invalid,            // cannot find a valid interpretation
     Incomplete,         // needs more information
     Complete(CommandInfo),  // command is finished
+    Preview(CommandInfo),   // command to run now, while more input is given
 }
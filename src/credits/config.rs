@@ -0,0 +1,535 @@
+//! User configuration.
+//!
+//! The configuration is read from `~/.config/credits/config.toml`, which is
+//! written in a small subset of TOML:
+//!
+//! ```toml
+//! mode = "emacs"        # "standard", "emacs" or "vi"
+//! tab_width = 8
+//! soft_tabs = false     # insert a tab character rather than spaces
+//! theme = "light"
+//! clipboard = "command" # "none", "osc52", "memory" or "command"
+//! clipboard_copy = "xclip -selection clipboard"
+//! clipboard_paste = "xclip -selection clipboard -o"
+//! persistent_undo = true # keep the undo history of saved files
+//!
+//! [keys.emacs]
+//! "ctrl-x ctrl-t" = "editor::set_theme"
+//! "ctrl-x t" = { command = "editor::set_theme", str = "light" }
+//! "ctrl-r" = false      # unbind the key
+//! ```
+//!
+//! Key bindings go in a `keys.<mode>` table for the `standard`, `emacs`,
+//! `normal`, `insert` or `visual` mode. They bind a sequence of keys to a command,
+//! optionally with `char`, `number`, `str` and `replacement` arguments.
+//!
+//! Cut and copied text is shared with the system clipboard, through the
+//! terminal with `osc52`, or by running the `clipboard_copy` and
+//! `clipboard_paste` commands with `command`.
+//!
+//! With `persistent_undo`, the undo history of a file is kept when it is
+//! saved, and can still be undone the next time the file is opened.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crossterm::KeyEvent;
+
+use crate::clipboard::{Clipboard, CommandClipboard, MemoryClipboard, Osc52};
+use crate::command::{BuilderArgs, CommandError};
+use crate::editor::build_command;
+use crate::keymap::{CommandInfo, KeyMap};
+
+/// The set of modes the editor starts out in
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StartMode {
+    /// Standard mode, with common editor key bindings
+    Standard,
+    /// Emacs mode
+    Emacs,
+    /// Vi's normal and insert modes
+    Vi,
+}
+
+/// How cut and copied text reaches the system clipboard
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ClipboardKind {
+    /// It doesn't, it is only kept in the kill ring
+    None,
+    /// Through the terminal, with the OSC 52 escape sequence
+    Osc52,
+    /// A clipboard kept in memory
+    Memory,
+    /// By running the `clipboard_copy` and `clipboard_paste` commands
+    Command,
+}
+
+/// A key binding from the configuration
+pub struct Binding {
+    /// Name of the mode the binding is for
+    pub mode: String,
+    /// The sequence of keys to press
+    pub keys: Vec<KeyEvent>,
+    /// The command to run, None to unbind the keys
+    pub command: Option<CommandInfo>,
+}
+
+/// Settings read from the configuration file
+pub struct Config {
+    /// The mode the editor starts out in
+    pub mode: StartMode,
+    /// Number of columns between tab stops
+    pub tab_width: usize,
+    /// Whether tab inserts spaces up to the next tab stop rather than a tab character
+    pub soft_tabs: bool,
+    /// Name of the colour theme
+    pub theme: String,
+    /// Which clipboard cut and copied text goes to
+    pub clipboard: ClipboardKind,
+    /// The command which copies its input to the clipboard
+    pub clipboard_copy: String,
+    /// The command which prints the clipboard
+    pub clipboard_paste: String,
+    /// Whether the undo history of files is kept between sessions
+    pub persistent_undo: bool,
+    /// Key bindings added to, or removed from, the modes' defaults
+    pub bindings: Vec<Binding>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            mode: StartMode::Standard,
+            tab_width: 4,
+            soft_tabs: true,
+            theme: String::from("default"),
+            clipboard: ClipboardKind::None,
+            clipboard_copy: String::new(),
+            clipboard_paste: String::new(),
+            persistent_undo: false,
+            bindings: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Read the configuration from `path`, or from the default location.
+    ///
+    /// It is not an error for the file in the default location to be
+    /// missing, the default configuration is used instead.
+    pub fn load(path: Option<&Path>) -> Result<Config, String> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Config::default()),
+            },
+        };
+        if !required && !path.is_file() {
+            return Ok(Config::default())
+        }
+
+        let source = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Config::parse(&source).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Parse a configuration, see the module documentation for the format.
+    ///
+    /// The error names the line which could not be parsed.
+    pub fn parse(source: &str) -> Result<Config, String> {
+        let mut config = Config::default();
+        let mut table = String::new();
+
+        for (number, line) in source.lines().enumerate() {
+            let error = |message: String| format!("line {}: {}", number + 1, message);
+            let line = strip_comment(line).trim();
+            if line.is_empty() { continue }
+
+            if line.starts_with('[') {
+                if !line.ends_with(']') {
+                    return Err(error(String::from("expected `]`")))
+                }
+                table = line[1..line.len() - 1].trim().to_string();
+                if table.starts_with("keys.") && !is_mode_name(&table[5..]) {
+                    return Err(error(format!("unknown mode `{}`", &table[5..])))
+                } else if !table.starts_with("keys.") {
+                    return Err(error(format!("unknown table `{}`", table)))
+                }
+                continue
+            }
+
+            let (key, rest) = parse_key(line).map_err(&error)?;
+            let rest = rest.trim_start();
+            if !rest.starts_with('=') {
+                return Err(error(format!("expected `=` after `{}`", key)))
+            }
+            let (value, rest) = parse_value(rest[1..].trim_start()).map_err(&error)?;
+            if !rest.trim().is_empty() {
+                return Err(error(format!("unexpected `{}`", rest.trim())))
+            }
+
+            if table.is_empty() {
+                config.set(&key, value).map_err(&error)?;
+            } else {
+                let binding = parse_binding(&table[5..], &key, value).map_err(&error)?;
+                config.bindings.push(binding);
+            }
+        }
+
+        if config.clipboard == ClipboardKind::Command && config.clipboard_copy.is_empty() {
+            return Err(String::from("a `command` clipboard needs `clipboard_copy`"))
+        }
+        Ok(config)
+    }
+
+    /// Set one of the top level options
+    fn set(&mut self, key: &str, value: Value) -> Result<(), String> {
+        match (key, value) {
+            ("mode", Value::Str(mode)) => {
+                self.mode = match mode.as_str() {
+                    "standard" => StartMode::Standard,
+                    "emacs" => StartMode::Emacs,
+                    "vi" => StartMode::Vi,
+                    _ => return Err(format!("unknown mode `{}`", mode)),
+                }
+            }
+            ("tab_width", Value::Int(width)) if width > 0 => self.tab_width = width as usize,
+            ("soft_tabs", Value::Bool(soft_tabs)) => self.soft_tabs = soft_tabs,
+            ("theme", Value::Str(theme)) => self.theme = theme,
+            ("clipboard", Value::Str(clipboard)) => {
+                self.clipboard = match clipboard.as_str() {
+                    "none" => ClipboardKind::None,
+                    "osc52" => ClipboardKind::Osc52,
+                    "memory" => ClipboardKind::Memory,
+                    "command" => ClipboardKind::Command,
+                    _ => return Err(format!("unknown clipboard `{}`", clipboard)),
+                }
+            }
+            ("clipboard_copy", Value::Str(command)) => self.clipboard_copy = command,
+            ("clipboard_paste", Value::Str(command)) => self.clipboard_paste = command,
+            ("persistent_undo", Value::Bool(persistent_undo)) => self.persistent_undo = persistent_undo,
+            ("mode", _) | ("tab_width", _) | ("soft_tabs", _) | ("theme", _) |
+            ("clipboard", _) | ("clipboard_copy", _) | ("clipboard_paste", _) |
+            ("persistent_undo", _) => {
+                return Err(format!("bad value for `{}`", key))
+            }
+            _ => return Err(format!("unknown option `{}`", key)),
+        }
+        Ok(())
+    }
+
+    /// The configured clipboard, None when it is only the kill ring
+    pub fn clipboard(&self) -> Option<Box<dyn Clipboard>> {
+        match self.clipboard {
+            ClipboardKind::None => None,
+            ClipboardKind::Osc52 => Some(Box::new(Osc52)),
+            ClipboardKind::Memory => Some(Box::new(MemoryClipboard::new())),
+            ClipboardKind::Command => {
+                Some(Box::new(CommandClipboard::new(&self.clipboard_copy, &self.clipboard_paste)))
+            }
+        }
+    }
+
+    /// Apply the bindings for the named mode to its KeyMap
+    pub fn bind_keys(&self, mode: &str, keymap: &mut KeyMap) {
+        for binding in self.bindings.iter().filter(|b| b.mode == mode) {
+            match binding.command {
+                Some(ref command) => keymap.bind_keys(&binding.keys, command.clone()),
+                None => keymap.unbind_keys(&binding.keys),
+            }
+        }
+    }
+}
+
+/// `~/.config/credits/config.toml`
+fn default_path() -> Option<PathBuf> {
+    let home = env::var_os("HOME")?;
+    Some(PathBuf::from(home).join(".config/credits/config.toml"))
+}
+
+/// Whether `name` is one of the modes which can have key bindings
+fn is_mode_name(name: &str) -> bool {
+    matches!(name, "standard" | "emacs" | "normal" | "insert" | "visual")
+}
+
+/// A value on the right of an `=`
+#[derive(Debug, PartialEq)]
+enum Value {
+    Str(String),
+    Int(i64),
+    Bool(bool),
+    Table(Vec<(String, Value)>),
+}
+
+/// Everything before a `#` which isn't in a string
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (idx, ch) in line.char_indices() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..idx],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Parse a bare or quoted key, returning it and the rest of the text
+fn parse_key(text: &str) -> Result<(String, &str), String> {
+    if text.starts_with('"') {
+        return parse_string(text)
+    }
+    let end = text.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-')).unwrap_or(text.len());
+    if end == 0 {
+        return Err(format!("expected a key at `{}`", text))
+    }
+    Ok((text[..end].to_string(), &text[end..]))
+}
+
+/// Parse a quoted string, returning it and the rest of the text
+fn parse_string(text: &str) -> Result<(String, &str), String> {
+    let mut string = String::new();
+    let mut chars = text.char_indices().skip(1);
+    while let Some((idx, ch)) = chars.next() {
+        match ch {
+            '"' => return Ok((string, &text[idx + 1..])),
+            '\\' => {
+                let escaped = match chars.next() {
+                    Some((_, 'n')) => '\n',
+                    Some((_, 't')) => '\t',
+                    Some((_, '"')) => '"',
+                    Some((_, '\\')) => '\\',
+                    _ => return Err(String::from("bad escape in string")),
+                };
+                string.push(escaped);
+            }
+            _ => string.push(ch),
+        }
+    }
+    Err(String::from("unterminated string"))
+}
+
+/// Parse a value, returning it and the rest of the text
+fn parse_value(text: &str) -> Result<(Value, &str), String> {
+    if text.starts_with('"') {
+        let (string, rest) = parse_string(text)?;
+        return Ok((Value::Str(string), rest))
+    }
+    if let Some(rest) = text.strip_prefix('{') {
+        let mut entries = Vec::new();
+        let mut rest = rest.trim_start();
+        while !rest.starts_with('}') {
+            let (key, after_key) = parse_key(rest)?;
+            let after_key = after_key.trim_start();
+            if !after_key.starts_with('=') {
+                return Err(format!("expected `=` after `{}`", key))
+            }
+            let (value, after_value) = parse_value(after_key[1..].trim_start())?;
+            entries.push((key, value));
+
+            rest = after_value.trim_start();
+            if rest.starts_with(',') {
+                rest = rest[1..].trim_start();
+            } else if !rest.starts_with('}') {
+                return Err(String::from("expected `,` or `}` in table"))
+            }
+        }
+        return Ok((Value::Table(entries), &rest[1..]))
+    }
+
+    let end = text.find(|c: char| c == ',' || c == '}' || c.is_whitespace()).unwrap_or(text.len());
+    let value = match &text[..end] {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        word => match word.parse() {
+            Ok(int) => Value::Int(int),
+            Err(_) => return Err(format!("bad value `{}`", word)),
+        },
+    };
+    Ok((value, &text[end..]))
+}
+
+/// Make a key binding for `mode` from the keys and the command they run
+fn parse_binding(mode: &str, keys: &str, value: Value) -> Result<Binding, String> {
+    let keys = keys.split_whitespace().map(parse_key_event).collect::<Result<Vec<_>, _>>()?;
+    if keys.is_empty() {
+        return Err(String::from("no keys to bind"))
+    }
+
+    let command = match value {
+        Value::Bool(false) => None,
+        Value::Str(command_name) => Some(CommandInfo { command_name, args: None }),
+        Value::Table(entries) => {
+            let mut command_name = None;
+            let mut args = BuilderArgs::new();
+            for (key, value) in entries {
+                match (key.as_str(), value) {
+                    ("command", Value::Str(name)) => command_name = Some(name),
+                    ("char", Value::Str(ref c)) if c.chars().count() == 1 => {
+                        args = args.with_char_arg(c.chars().next().unwrap())
+                    }
+                    ("number", Value::Int(n)) => args = args.with_number(n as i32),
+                    ("str", Value::Str(s)) => args = args.with_str(s),
+                    ("replacement", Value::Str(s)) => args = args.with_replacement(s),
+                    (key, _) => return Err(format!("bad argument `{}`", key)),
+                }
+            }
+            let command_name = command_name.ok_or_else(|| String::from("no command given"))?;
+            Some(CommandInfo { command_name, args: Some(args) })
+        }
+        _ => return Err(String::from("expected a command name, a table or false")),
+    };
+
+    // building the command checks it exists and has the arguments it needs
+    if let Some(ref command) = command {
+        match build_command(command.clone()) {
+            Ok(_) => {}
+            Err(CommandError::Unknown(name)) => return Err(format!("unknown command `{}`", name)),
+            Err(e) => return Err(format!("`{}`, {}", command.command_name, e)),
+        }
+    }
+
+    Ok(Binding { mode: mode.to_string(), keys, command })
+}
+
+/// Parse a key such as `a`, `ctrl-x`, `alt-%`, `enter` or `f5`
+fn parse_key_event(key: &str) -> Result<KeyEvent, String> {
+    let single = |name: &str| {
+        let mut chars = name.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    };
+
+    let event = if key.starts_with("ctrl-") && single(&key[5..]).is_some() {
+        KeyEvent::Ctrl(single(&key[5..]).unwrap())
+    } else if key.starts_with("alt-") && single(&key[4..]).is_some() {
+        KeyEvent::Alt(single(&key[4..]).unwrap())
+    } else if let Some(c) = single(key) {
+        KeyEvent::Char(c)
+    } else {
+        match key {
+            "enter" => KeyEvent::Char('\n'),
+            "tab" => KeyEvent::Char('\t'),
+            "space" => KeyEvent::Char(' '),
+            "esc" => KeyEvent::Esc,
+            "backspace" => KeyEvent::Backspace,
+            "delete" => KeyEvent::Delete,
+            "insert" => KeyEvent::Insert,
+            "up" => KeyEvent::Up,
+            "down" => KeyEvent::Down,
+            "left" => KeyEvent::Left,
+            "right" => KeyEvent::Right,
+            "home" => KeyEvent::Home,
+            "end" => KeyEvent::End,
+            "pageup" => KeyEvent::PageUp,
+            "pagedown" => KeyEvent::PageDown,
+            "backtab" => KeyEvent::BackTab,
+            "ctrl-up" => KeyEvent::CtrlUp,
+            "ctrl-down" => KeyEvent::CtrlDown,
+            "ctrl-left" => KeyEvent::CtrlLeft,
+            "ctrl-right" => KeyEvent::CtrlRight,
+            "shift-up" => KeyEvent::ShiftUp,
+            "shift-down" => KeyEvent::ShiftDown,
+            "shift-left" => KeyEvent::ShiftLeft,
+            "shift-right" => KeyEvent::ShiftRight,
+            _ if key.starts_with('f') => match key[1..].parse() {
+                Ok(n) if (1..=12).contains(&n) => KeyEvent::F(n),
+                _ => return Err(format!("unknown key `{}`", key)),
+            },
+            _ => return Err(format!("unknown key `{}`", key)),
+        }
+    };
+    Ok(event)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crossterm::KeyEvent;
+
+    use super::{ClipboardKind, Config, StartMode, parse_key_event};
+
+    #[test]
+    fn test_parse_options() {
+        let config = Config::parse("
+            # comments are ignored
+            mode = \"vi\"
+            tab_width = 8   # so are trailing ones
+            soft_tabs = false
+            theme = \"light\"
+            persistent_undo = true
+        ").unwrap();
+
+        assert_eq!(config.mode, StartMode::Vi);
+        assert_eq!(config.tab_width, 8);
+        assert!(!config.soft_tabs);
+        assert_eq!(config.theme, "light");
+        assert!(config.persistent_undo);
+        assert!(config.bindings.is_empty());
+        assert!(config.clipboard().is_none());
+    }
+
+    #[test]
+    fn test_parse_clipboard() {
+        let config = Config::parse("
+            clipboard_copy = \"wl-copy\"
+            clipboard = \"command\"
+        ").unwrap();
+        assert_eq!(config.clipboard, ClipboardKind::Command);
+        assert_eq!(config.clipboard_copy, "wl-copy");
+        assert!(config.clipboard().is_some());
+
+        assert_eq!(Config::parse("clipboard = \"osc52\"").unwrap().clipboard, ClipboardKind::Osc52);
+        assert!(Config::parse("clipboard = \"command\"").is_err());
+        assert!(Config::parse("clipboard = \"x11\"").is_err());
+    }
+
+    #[test]
+    fn test_parse_bindings() {
+        let config = Config::parse("
+            [keys.emacs]
+            \"ctrl-x t\" = { command = \"editor::set_theme\", str = \"light\" }
+            ctrl-r = false
+            \"#\" = \"editor::search\"
+        ").unwrap();
+
+        assert_eq!(config.bindings.len(), 3);
+        let binding = &config.bindings[0];
+        assert_eq!(binding.mode, "emacs");
+        assert_eq!(binding.keys, vec![KeyEvent::Ctrl('x'), KeyEvent::Char('t')]);
+        let command = binding.command.as_ref().unwrap();
+        assert_eq!(command.command_name, "editor::set_theme");
+        assert_eq!(command.args.as_ref().unwrap().str_args, Some(String::from("light")));
+
+        assert_eq!(config.bindings[1].keys, vec![KeyEvent::Ctrl('r')]);
+        assert!(config.bindings[1].command.is_none());
+        assert_eq!(config.bindings[2].keys, vec![KeyEvent::Char('#')]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Config::parse("tab_width = \"wide\"").is_err());
+        assert!(Config::parse("colour = 1").is_err());
+        assert!(Config::parse("mode = \"nano\"").is_err());
+        assert!(Config::parse("[keys.replace]").is_err());
+        assert!(Config::parse("[keys.standard]\nctrl-q = \"editor::nothing\"").err().unwrap().starts_with("line 2"));
+        assert!(Config::parse("[keys.normal]\nx = \"editor::set_mode\"").err().unwrap().contains("no mode given"));
+        assert!(Config::parse("[keys.standard]\nhyper-q = \"editor::quit\"").is_err());
+        assert!(Config::parse("theme = \"light").is_err());
+    }
+
+    #[test]
+    fn test_parse_key_event() {
+        assert_eq!(parse_key_event("alt-%"), Ok(KeyEvent::Alt('%')));
+        assert_eq!(parse_key_event("ctrl-up"), Ok(KeyEvent::CtrlUp));
+        assert_eq!(parse_key_event("enter"), Ok(KeyEvent::Char('\n')));
+        assert_eq!(parse_key_event("f12"), Ok(KeyEvent::F(12)));
+        assert!(parse_key_event("f13").is_err());
+    }
+}
@@ -1,4 +1,5 @@
-use std::path::PathBuf;
+use std::cmp;
+use std::path::{Path, PathBuf};
 use std::sync::{Mutex, Arc};
 use std::sync::mpsc::{Sender, Receiver};
 use std::sync::mpsc::channel;
@@ -6,31 +7,78 @@ use std::collections::HashMap;
 
 // use rustbox::{RustBox, Event};
 use crossterm::{InputEvent, KeyEvent, RawScreen, Crossterm};
+use regex::Regex;
 
 use crate::input::Input;
-use crate::view::View;
-use crate::modes::{Mode, ModeType, InsertMode, NormalMode};
-use crate::buffer::Buffer;
+use crate::view::{View, SelectionKind};
+use crate::overlay::{Overlay, OverlayType, CommandPrompt, Picker, FilePrompt, SearchPrompt};
+use crate::overlay::{ReplacePrompt, ReplaceQuery, ConfirmPrompt};
+use crate::modes::{Mode, ModeType, StandardMode, InsertMode, NormalMode, VisualMode, EmacsMode};
+use crate::buffer::{Buffer, OpenError};
 use crate::command::Command;
-use crate::command::{Action, BuilderEvent, BuilderArgs, Operation, Instruction};
-
-
-type EditorCommand = fn(Option<BuilderArgs>) -> Command;
+use crate::command::{Action, BuilderEvent, BuilderArgs, CommandError, Operation, Instruction, Operator};
+use crate::textobject::{TextObject, Kind, Anchor};
+use crate::keymap::CommandInfo;
+use crate::theme;
+use crate::ex::{self, ExCommand};
+use crate::killring::KillRing;
+use crate::history;
+use crate::utils;
+use crate::log::Grouping;
+use crate::clipboard::Clipboard;
+use crate::config::{Config, StartMode};
+
+
+type EditorCommand = fn(Option<BuilderArgs>) -> Result<Command, CommandError>;
 lazy_static! {
     pub static ref ALL_COMMANDS: HashMap<&'static str, EditorCommand> = {
         let mut map: HashMap<&'static str, EditorCommand> = HashMap::new();
 
         map.insert("editor::quit", Command::exit_editor);
         map.insert("editor::save_buffer", Command::save_buffer);
+        map.insert("editor::save_buffer_as", Command::save_buffer_as);
+        map.insert("editor::find_file", Command::find_file);
         map.insert("editor::noop", Command::noop);
+        map.insert("editor::ex", Command::ex);
+        map.insert("editor::set_line_ending", Command::set_line_ending);
 
         map.insert("editor::undo", Command::undo);
         map.insert("editor::redo", Command::redo);
+        map.insert("editor::undo_older", Command::undo_older);
+        map.insert("editor::undo_newer", Command::undo_newer);
+        map.insert("editor::undo_to", Command::undo_to);
+        map.insert("editor::undo_tree", Command::undo_tree);
         map.insert("editor::set_mode", Command::set_mode);
 
         map.insert("editor::set_overlay", Command::set_overlay);
+        map.insert("editor::set_theme", Command::set_theme);
+
+        map.insert("editor::next_buffer", Command::next_buffer);
+        map.insert("editor::prev_buffer", Command::prev_buffer);
+        map.insert("editor::close_buffer", Command::close_buffer);
+        map.insert("editor::switch_to_buffer", Command::switch_to_buffer);
+        map.insert("editor::list_buffers", Command::list_buffers);
+
+        map.insert("editor::search", Command::search);
+        map.insert("editor::search_regex", Command::search_regex);
+        map.insert("editor::find_next", Command::find_next);
+        map.insert("editor::find_prev", Command::find_prev);
+        map.insert("editor::end_search", Command::end_search);
+        map.insert("editor::cancel_search", Command::cancel_search);
+        map.insert("editor::replace", Command::replace);
+        map.insert("editor::replace_all", Command::replace_all);
+        map.insert("editor::answer_replace", Command::answer_replace);
 
         map.insert("buffer::move_cursor", Command::move_cursor);
+        map.insert("buffer::set_selection_mark", Command::set_selection_mark);
+        map.insert("buffer::extend_selection", Command::extend_selection);
+        map.insert("buffer::clear_selection", Command::clear_selection);
+        map.insert("buffer::copy", Command::copy);
+        map.insert("buffer::cut", Command::cut);
+        map.insert("buffer::paste", Command::paste);
+        map.insert("buffer::yank_pop", Command::yank_pop);
+        map.insert("buffer::operate", Command::operate);
+        map.insert("buffer::operator_pending", Command::operator_pending);
         map.insert("buffer::insert_char", Command::insert_char);
         map.insert("buffer::insert_tab", Command::insert_tab);
         map.insert("buffer::delete_char", Command::delete_char);
@@ -44,54 +92,84 @@ lazy_static! {
 ///
 /// This is the top-most structure in Iota.
 pub struct Editor<'e> {
-    // buffers: Vec<Arc<Mutex<Buffer>>>,
+    buffers: Vec<Arc<Mutex<Buffer>>>,
     view: View<'e>,
     running: bool,
     rb: Crossterm,
     mode: Box<dyn Mode + 'e>,
+    config: Config,
+
+    /// Text which was cut or copied
+    kill_ring: KillRing,
+    /// Where the last command pasted text, so it can be replaced by older kills
+    last_paste: Option<(usize, usize)>,
+    /// The system clipboard which kills are shared with, if any
+    clipboard: Option<Box<dyn Clipboard>>,
 
     command_queue: Receiver<Command>,
     command_sender: Sender<Command>,
     
     just_attempted_exit: bool,
+    just_attempted_close: bool,
 }
 
 impl<'e> Editor<'e> {
 
-    /// Create a new Editor instance from the given source
-    pub fn new(source: Input, mode: Box<dyn Mode + 'e>, rb: Crossterm) -> Editor<'e> {
+    /// Create a new Editor instance, with a buffer opened from each of the given sources
+    pub fn new(sources: Vec<Input>, config: Config, rb: Crossterm) -> Editor<'e> {
         let (width, height) = rb.terminal().terminal_size();
 
         let (snd, recv) = channel();
 
         let mut buffers = Vec::new();
-
-        let buffer = match source {
-            Input::Filename(path) => {
-                match path {
-                    Some(path) => Buffer::from(PathBuf::from(path)),
-                    None       => Buffer::new(),
+        let mut messages = Vec::new();
+        for source in sources {
+            match open_input(source) {
+                Ok((buffer, message)) => {
+                    buffers.push(share_buffer(&config, buffer));
+                    messages.extend(message);
                 }
-            },
-            Input::Stdin(reader) => {
-                Buffer::from(reader)
+                Err(message) => messages.push(message),
             }
-        };
-        buffers.push(Arc::new(Mutex::new(buffer)));
+        }
+        if buffers.is_empty() {
+            buffers.push(share_buffer(&config, Buffer::new()));
+        }
 
-        let view = View::new(buffers[0].clone(), width, height);
+        let mut view = View::new(buffers[0].clone(), width, height);
+        match theme::load(&config.theme) {
+            Ok(theme) => view.theme = theme,
+            Err(e) => messages.push(format!("Could not load {}", e)),
+        }
+        if !messages.is_empty() {
+            view.show_message(messages.join("; "));
+        }
+
+        let clipboard = config.clipboard();
+
+        let mode = match config.mode {
+            StartMode::Standard => new_mode(&config, "standard"),
+            StartMode::Emacs => new_mode(&config, "emacs"),
+            StartMode::Vi => new_mode(&config, "normal"),
+        };
 
         Editor {
-            // buffers: buffers,
+            buffers,
             view: view,
             running: true,
             rb: rb,
             mode: mode,
+            config,
+
+            kill_ring: KillRing::new(),
+            last_paste: None,
+            clipboard,
 
             command_queue: recv,
             command_sender: snd,
             
             just_attempted_exit: false,
+            just_attempted_close: false,
         }
     }
 
@@ -103,6 +181,7 @@ impl<'e> Editor<'e> {
     /// back an OverlayEvent. We then parse this OverlayEvent and determine if
     /// the Overlay is finished and can be cleared. The response from the
     /// Overlay is then converted to a Command and sent off to be handled.
+    /// An Overlay may also send a Command off while it stays open.
     ///
     /// If there is no active Overlay, the key event is sent to the current
     /// Mode, which returns a Command which we dispatch to handle_command.
@@ -112,24 +191,30 @@ impl<'e> Editor<'e> {
             Some(ref mut overlay) => overlay.handle_key_event(event),
         };
 
-        if let BuilderEvent::Complete(c) = command {
-            self.view.overlay = None;
-            self.view.clear(&mut self.rb);
-
-            match ALL_COMMANDS.get(&*c.command_name) {
-                Some(cmd) => {
-                    let cmd = cmd(c.args);
-                    let _ = self.command_sender.send(cmd);
-                }
-                None => {
-                    panic!("Unknown command: {}", c.command_name);
-                }
+        match command {
+            BuilderEvent::Complete(c) => {
+                self.view.overlay = None;
+                self.view.clear(&mut self.rb);
+                self.send_command(c);
             }
-
-            // let _ = self.command_sender.send(c);
+            BuilderEvent::Preview(c) => self.send_command(c),
+            _ => {}
         }
     }
 
+    /// Convert the CommandInfo to a Command and send it off to be handled
+    ///
+    /// A command which can't be built is reported in a message instead.
+    fn send_command(&mut self, c: CommandInfo) {
+        let name = c.command_name.clone();
+        let command = match build_command(c) {
+            Ok(command) => command,
+            Err(e @ CommandError::Unknown(_)) => Command::show_message(e.to_string()),
+            Err(e) => Command::show_message(format!("{}: {}", name, e)),
+        };
+        let _ = self.command_sender.send(command);
+    }
+
     /// Handle resize events
     ///
     /// width and height represent the new height of the window.
@@ -148,39 +233,79 @@ impl<'e> Editor<'e> {
         let repeat = if command.number > 0 {
             command.number
         } else { 1 };
+        let pasting = matches!(command.action, Action::Instruction(Instruction::Paste) | Action::Instruction(Instruction::YankPop));
+        // however many edits a command makes, they are undone at once
+        let buffer = self.view.buffer.clone();
+        buffer.lock().unwrap().log.begin_group();
         for _ in 0..repeat {
             match command.action {
-            	Action::Instruction(Instruction::ExitEditor) => self.handle_instruction(command.clone()),
+            	Action::Instruction(Instruction::ExitEditor) => {
+                	self.just_attempted_close = false;
+                	self.handle_instruction(command.clone());
+                }
+            	Action::Instruction(Instruction::CloseBuffer) => {
+                	self.just_attempted_exit = false;
+                	self.handle_instruction(command.clone());
+                }
                 Action::Instruction(_) => {
                 	self.handle_instruction(command.clone());
                 	// To keep the "Unsaved changes" message from preventing force quit:
                 	match command.action {
                 		Action::Instruction(Instruction::ShowMessage(_)) => {},
-                		_ => self.just_attempted_exit = false,
+                		_ => {
+                			self.just_attempted_exit = false;
+                			self.just_attempted_close = false;
+                		}
                 	}
                 }
                 Action::Operation(_) => {
                 	self.handle_operation(command.clone());
                 	self.just_attempted_exit = false;
+                	self.just_attempted_close = false;
                 }
             }
         }
+        buffer.lock().unwrap().log.end_group();
+        if !pasting {
+            self.last_paste = None;
+        }
         self.draw(); // Redraw after updating
     }
 
 
     fn handle_instruction(&mut self, command: Command) {
         match command.action {
-            Action::Instruction(Instruction::SaveBuffer) => { self.view.try_save_buffer() }
+            Action::Instruction(Instruction::SaveBuffer) => {
+                // an untitled buffer needs a file name first
+                if self.view.buffer.lock().unwrap().file_path.is_none() {
+                    self.set_overlay(OverlayType::SaveBufferAs)
+                } else {
+                    self.view.try_save_buffer();
+                }
+            }
+            Action::Instruction(Instruction::SaveBufferAs(path, overwrite)) => {
+                if !overwrite && self.view.would_overwrite(&path) {
+                    let question = format!("{} exists, overwrite it?", path.display());
+                    let command = CommandInfo {
+                        command_name: String::from("editor::save_buffer_as"),
+                        args: Some(BuilderArgs::new().with_str(path.to_string_lossy().into_owned()).with_char_arg('!')),
+                    };
+                    self.view.set_overlay(Box::new(ConfirmPrompt::new(&question, command)));
+                } else {
+                    self.view.save_buffer_as(path)
+                }
+            }
+            Action::Instruction(Instruction::FindFile(path)) => { self.find_file(path) }
             Action::Instruction(Instruction::ExitEditor) => {
-                if self.view.buffer_is_dirty() {
+                let unsaved = self.unsaved_buffer_names();
+                if !unsaved.is_empty() {
                 	if self.just_attempted_exit {
                 		self.running = false; // Allow "force quit"
                 	} else {
                 		self.just_attempted_exit = true;
-                		
-                		let args = BuilderArgs::new().with_str("Warning: unsaved changes (twice to force quit)".into());
-                		let _ = self.command_sender.send(Command::show_message(Some(args)));
+
+                		let message = format!("Warning: unsaved changes in {} (twice to force quit)", unsaved.join(", "));
+                		let _ = self.command_sender.send(Command::show_message(message));
                 	}
                 } else {
                     self.running = false;
@@ -188,37 +313,408 @@ impl<'e> Editor<'e> {
             }
             Action::Instruction(Instruction::SetMark(mark)) => {
                 if let Some(object) = command.object {
+                    self.view.buffer.lock().unwrap().log.break_group();
                     self.view.move_mark(mark, object)
                 }
             }
+            Action::Instruction(Instruction::SetSelectionMark) => { self.view.set_selection_mark() }
+            Action::Instruction(Instruction::ExtendSelection) => {
+                if let Some(object) = command.object {
+                    self.view.buffer.lock().unwrap().log.break_group();
+                    self.view.extend_selection(object)
+                }
+            }
+            Action::Instruction(Instruction::ClearSelection) => { self.view.clear_selection() }
+            Action::Instruction(Instruction::Copy) => {
+                let text = self.view.copy_selection();
+                self.kill(String::from_utf8_lossy(&text).into_owned());
+            }
+            Action::Instruction(Instruction::Cut) => {
+                let text = self.view.cut_selection();
+                self.kill(String::from_utf8_lossy(&text).into_owned());
+            }
+            Action::Instruction(Instruction::Paste) => {
+                // text copied in other programs is pasted before older kills
+                let copied = self.clipboard.as_mut().map(|clipboard| clipboard.paste());
+                match copied {
+                    Some(Ok(Some(text))) if self.kill_ring.current() != Some(text.as_str()) => {
+                        self.kill_ring.push(text)
+                    }
+                    Some(Err(e)) => self.view.show_message(format!("Could not paste from the clipboard, {}", e)),
+                    _ => {}
+                }
+                match self.kill_ring.current().map(String::from) {
+                    Some(text) => self.last_paste = Some(self.view.paste(&text)),
+                    None => self.view.show_message(String::from("Nothing to paste")),
+                }
+            }
+            // cycle through older kills in place of the text just pasted
+            Action::Instruction(Instruction::YankPop) => {
+                match self.last_paste {
+                    Some(range) => {
+                        if let Some(text) = self.kill_ring.rotate().map(String::from) {
+                            self.last_paste = Some(self.view.replace_paste(range, &text));
+                        }
+                    }
+                    None => self.view.show_message(String::from("Nothing was just pasted")),
+                }
+            }
+            Action::Instruction(Instruction::Operate(operator)) => {
+                self.operate(operator, command.object)
+            }
             Action::Instruction(Instruction::SetOverlay(overlay_type)) => {
-                self.view.set_overlay(overlay_type)
+                self.set_overlay(overlay_type)
             }
             Action::Instruction(Instruction::SetMode(mode)) => {
+                // everything typed in insert mode is undone at once
+                let grouping = if let ModeType::Insert = mode { Grouping::Session } else { Grouping::Words };
+                self.view.buffer.lock().unwrap().log.set_grouping(grouping);
                 match mode {
-                    ModeType::Insert => { self.mode = Box::new(InsertMode::new()) }
-                    ModeType::Normal => { self.mode = Box::new(NormalMode::new()) }
+                    ModeType::Insert => { self.mode = new_mode(&self.config, "insert") }
+                    ModeType::Normal => {
+                        self.view.clear_selection();
+                        self.mode = new_mode(&self.config, "normal")
+                    }
+                    ModeType::Visual | ModeType::VisualLine => {
+                        let kind = if let ModeType::Visual = mode { SelectionKind::Inclusive } else { SelectionKind::Lines };
+                        if self.view.selection_kind() == Some(kind) {
+                            self.view.clear_selection();
+                            self.mode = new_mode(&self.config, "normal");
+                        } else {
+                            self.view.start_selection(kind);
+                            self.mode = new_mode(&self.config, "visual");
+                        }
+                    }
                 }
             }
             Action::Instruction(Instruction::SwitchToLastBuffer) => {
                 self.view.switch_last_buffer();
                 self.view.clear(&mut self.rb);
             }
+            Action::Instruction(Instruction::SwitchToBuffer(index)) => {
+                self.switch_to_buffer(index)
+            }
+            Action::Instruction(Instruction::NextBuffer) => {
+                let index = (self.current_buffer_index() + 1) % self.buffers.len();
+                self.switch_to_buffer(index)
+            }
+            Action::Instruction(Instruction::PrevBuffer) => {
+                let index = (self.current_buffer_index() + self.buffers.len() - 1) % self.buffers.len();
+                self.switch_to_buffer(index)
+            }
+            Action::Instruction(Instruction::CloseBuffer) => {
+                if self.view.buffer_is_dirty() && !self.just_attempted_close {
+                    self.just_attempted_close = true;
+
+                    let message = String::from("Warning: unsaved changes (twice to force close)");
+                    let _ = self.command_sender.send(Command::show_message(message));
+                } else {
+                    self.just_attempted_close = false;
+                    self.close_buffer();
+                }
+            }
             Action::Instruction(Instruction::ShowMessage(msg)) => {
                 self.view.show_message(msg)
             }
+            Action::Instruction(Instruction::Search(pattern)) => {
+                if pattern.is_empty() {
+                    self.view.search(None)
+                } else {
+                    match Regex::new(&pattern) {
+                        Ok(regex) => self.view.search(Some(regex)),
+                        Err(e) => self.view.show_message(format!("Invalid pattern: {}", e)),
+                    }
+                }
+            }
+            // without an earlier search, ask for something to search for
+            Action::Instruction(Instruction::FindNext) => {
+                if !self.view.find_next() && self.view.overlay.is_none() {
+                    self.set_overlay(OverlayType::Search)
+                }
+            }
+            Action::Instruction(Instruction::FindPrev) => {
+                if !self.view.find_prev() && self.view.overlay.is_none() {
+                    self.set_overlay(OverlayType::Search)
+                }
+            }
+            Action::Instruction(Instruction::EndSearch) => { self.view.end_search() }
+            Action::Instruction(Instruction::CancelSearch) => { self.view.cancel_search() }
+            Action::Instruction(Instruction::Replace(pattern, replacement)) => {
+                match Regex::new(&pattern) {
+                    Ok(regex) => {
+                        if self.view.start_replace(regex, &replacement) {
+                            self.view.set_overlay(Box::new(ReplaceQuery::new(&replacement)));
+                        }
+                    }
+                    Err(e) => self.view.show_message(format!("Invalid pattern: {}", e)),
+                }
+            }
+            Action::Instruction(Instruction::ReplaceAll(pattern, replacement)) => {
+                match Regex::new(&pattern) {
+                    Ok(regex) => self.view.replace_all(regex, &replacement),
+                    Err(e) => self.view.show_message(format!("Invalid pattern: {}", e)),
+                }
+            }
+            Action::Instruction(Instruction::AnswerReplace(answer)) => {
+                let asking = self.view.answer_replace(answer);
+                if !asking {
+                    self.view.overlay = None;
+                    self.view.clear(&mut self.rb);
+                }
+            }
+            Action::Instruction(Instruction::SetLineEnding(line_ending)) => self.view.set_line_ending(line_ending),
+            Action::Instruction(Instruction::SetTheme(name)) => {
+                match theme::load(&name) {
+                    Ok(theme) => self.view.theme = theme,
+                    Err(e) => self.view.show_message(format!("Could not load {}", e)),
+                }
+            }
+            Action::Instruction(Instruction::Ex(line)) => {
+                match ex::parse(&line) {
+                    Ok(command) => self.run_ex(command),
+                    Err(e) => self.view.show_message(e),
+                }
+            }
 
             _ => {}
         }
     }
 
+    /// Create the overlay for the given type and show it in the view
+    fn set_overlay(&mut self, overlay_type: OverlayType) {
+        let overlay: Box<dyn Overlay> = match overlay_type {
+            OverlayType::CommandPrompt => Box::new(CommandPrompt::new()),
+            OverlayType::BufferPicker => {
+                let names = self.buffers.iter().map(|b| b.lock().unwrap().file_name()).collect();
+                Box::new(Picker::new("buffer: ", "editor::switch_to_buffer", names, self.current_buffer_index()))
+            }
+            OverlayType::UndoTree => {
+                let (states, current) = {
+                    let buffer = self.view.buffer.lock().unwrap();
+                    (buffer.log.describe_states(), buffer.log.current())
+                };
+                Box::new(Picker::new("undo: ", "editor::undo_to", states, current))
+            }
+            OverlayType::ThemePicker => {
+                let names = theme::theme_names();
+                let current = names.iter().position(|name| *name == self.view.theme.name).unwrap_or(0);
+                Box::new(Picker::new("theme: ", "editor::set_theme", names, current))
+            }
+            OverlayType::FindFile => {
+                // start from the directory of the current buffer's file
+                let dir = self.view.buffer.lock().unwrap().file_path.as_ref()
+                    .and_then(|p| p.parent())
+                    .map(|p| p.to_string_lossy().into_owned())
+                    .filter(|p| !p.is_empty())
+                    .map(|p| if p.ends_with('/') { p } else { p + "/" })
+                    .unwrap_or_default();
+                Box::new(FilePrompt::new("find file: ", "editor::find_file", dir))
+            }
+            OverlayType::SaveBufferAs => {
+                let path = self.view.buffer.lock().unwrap().file_path.as_ref()
+                    .map(|p| p.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                Box::new(FilePrompt::new("save as: ", "editor::save_buffer_as", path))
+            }
+            OverlayType::Search | OverlayType::RegexSearch => {
+                self.view.begin_search();
+                Box::new(SearchPrompt::new(overlay_type == OverlayType::RegexSearch))
+            }
+            OverlayType::Replace => Box::new(ReplacePrompt::new("editor::replace")),
+            OverlayType::ReplaceAll => Box::new(ReplacePrompt::new("editor::replace_all")),
+        };
+        self.view.set_overlay(overlay);
+    }
+
+    /// Index into `buffers` of the buffer shown in the view
+    fn current_buffer_index(&self) -> usize {
+        buffer_index(&self.buffers, &self.view.buffer)
+    }
+
+    /// Show the buffer at the given index in the view, if it exists
+    fn switch_to_buffer(&mut self, index: usize) {
+        if index == self.current_buffer_index() { return }
+
+        if let Some(buffer) = self.buffers.get(index).cloned() {
+            self.view.set_buffer(buffer);
+            self.view.clear(&mut self.rb);
+        }
+    }
+
+    /// Close the buffer shown in the view, regardless of unsaved changes
+    ///
+    /// The view moves on to the next buffer, or to a new empty buffer if
+    /// this was the last one.
+    fn close_buffer(&mut self) {
+        let index = self.current_buffer_index();
+        let next = remove_buffer(&mut self.buffers, index, &self.config);
+        self.view.set_buffer(next);
+        // the buffer we just left is gone, so don't offer to switch back to it
+        let last_is_open = match self.view.last_buffer {
+            Some(ref last) => self.buffers.iter().any(|b| Arc::ptr_eq(b, last)),
+            None => false,
+        };
+        if !last_is_open {
+            self.view.last_buffer = None;
+        }
+        self.view.clear(&mut self.rb);
+    }
+
+    /// Show the buffer for the file at `path`, opening it if it isn't open already
+    fn find_file(&mut self, path: PathBuf) {
+        let index = match find_buffer(&self.buffers, &path) {
+            Some(index) => index,
+            None => match open_file(path) {
+                Ok((buffer, message)) => {
+                    self.buffers.push(share_buffer(&self.config, buffer));
+                    if let Some(message) = message {
+                        self.view.show_message(message);
+                    }
+                    self.buffers.len() - 1
+                }
+                Err(message) => return self.view.show_message(message),
+            }
+        };
+        self.switch_to_buffer(index);
+    }
+
+    /// Run a parsed ex command
+    fn run_ex(&mut self, command: ExCommand) {
+        match command {
+            ExCommand::Write(None) => { self.view.try_save_buffer(); }
+            ExCommand::Write(Some(ref path)) if self.view.would_overwrite(path) => {
+                self.view.show_message(format!("{} exists (add ! to overwrite)", path.display()))
+            }
+            ExCommand::Write(Some(path)) | ExCommand::Overwrite(path) => self.view.save_buffer_as(path),
+            ExCommand::Quit { force: true } => self.running = false,
+            ExCommand::Quit { force: false } => self.quit_if_saved(),
+            ExCommand::WriteQuit => self.save_and_quit(),
+            ExCommand::Exit => {
+                if self.view.buffer_is_dirty() {
+                    self.save_and_quit()
+                } else {
+                    self.quit_if_saved()
+                }
+            }
+            ExCommand::Edit(path) => self.find_file(path),
+            ExCommand::GotoLine(line) => self.view.goto_line(line),
+            ExCommand::SetLineEnding(line_ending) => self.view.set_line_ending(Some(line_ending)),
+            ExCommand::Substitute { pattern, replacement, whole_file, global } => {
+                match Regex::new(&pattern) {
+                    Ok(regex) => self.view.substitute(regex, &replacement, whole_file, global),
+                    Err(e) => self.view.show_message(format!("Invalid pattern: {}", e)),
+                }
+            }
+        }
+    }
+
+    /// Save the buffer, then quit unless the save failed
+    ///
+    /// An untitled buffer asks for a file name instead. A failed save leaves
+    /// its reason in the status bar.
+    fn save_and_quit(&mut self) {
+        if self.view.buffer.lock().unwrap().file_path.is_none() {
+            return self.set_overlay(OverlayType::SaveBufferAs)
+        }
+        if self.view.try_save_buffer() {
+            self.quit_if_saved();
+        }
+    }
+
+    /// Quit, unless some buffer has unsaved changes
+    fn quit_if_saved(&mut self) {
+        let unsaved = self.unsaved_buffer_names();
+        if unsaved.is_empty() {
+            self.running = false;
+        } else {
+            self.view.show_message(format!("No write since last change in {} (add ! to override)", unsaved.join(", ")));
+        }
+    }
+
+    /// Keep cut or copied text, in the kill ring and on the system clipboard
+    fn kill(&mut self, text: String) {
+        if let Some(ref mut clipboard) = self.clipboard {
+            if let Err(e) = clipboard.copy(&text) {
+                self.view.show_message(format!("Could not copy to the clipboard, {}", e));
+            }
+        }
+        self.kill_ring.push(text);
+    }
+
+    /// Apply a Vi operator to the text between the cursor and an object
+    ///
+    /// Without an object, the operator acts on the selection and leaves the
+    /// visual mode it was given in.
+    fn operate(&mut self, operator: Operator, object: Option<TextObject>) {
+        let (range, linewise) = match object {
+            Some(object) => (self.view.object_range(object), matches!(object.kind, Kind::Line(Anchor::Same))),
+            None => {
+                let selected = (self.view.selected_range(), self.view.selection_kind() == Some(SelectionKind::Lines));
+                self.view.clear_selection();
+                self.mode = new_mode(&self.config, "normal");
+                selected
+            }
+        };
+        let (start, end) = match range {
+            Some(range) => range,
+            None => return,
+        };
+
+        match operator {
+            Operator::Delete => {
+                let text = self.view.cut_range(start, end);
+                self.kill(String::from_utf8_lossy(&text).into_owned());
+            }
+            Operator::Change => {
+                // changing whole lines leaves an empty line to type on
+                let end = if linewise && self.view.copy_range(start, end).ends_with(b"\n") { end - 1 } else { end };
+                // the text typed in its place is undone along with the cut
+                self.view.buffer.lock().unwrap().log.set_grouping(Grouping::Session);
+                let text = self.view.cut_range(start, end);
+                self.kill(String::from_utf8_lossy(&text).into_owned());
+                self.mode = new_mode(&self.config, "insert");
+            }
+            Operator::Yank => {
+                let text = self.view.copy_range(start, end);
+                self.kill(String::from_utf8_lossy(&text).into_owned());
+            }
+            Operator::Indent => {
+                let indent = if self.config.soft_tabs {
+                    " ".repeat(self.config.tab_width)
+                } else {
+                    String::from("\t")
+                };
+                self.view.indent_lines(start, end, &indent);
+            }
+            Operator::Dedent => self.view.dedent_lines(start, end),
+        }
+    }
+
+    /// Names of all buffers with unsaved changes
+    fn unsaved_buffer_names(&self) -> Vec<String> {
+        unsaved_buffer_names(&self.buffers)
+    }
+
     fn handle_operation(&mut self, command: Command) {
+        if self.view.buffer.lock().unwrap().read_only {
+            if let Action::Operation(Operation::Insert(_)) | Action::Operation(Operation::InsertTab)
+                 | Action::Operation(Operation::DeleteObject) | Action::Operation(Operation::DeleteFromMark(_)) = command.action {
+                let name = self.view.buffer.lock().unwrap().file_name();
+                self.view.show_message(format!("{} is a binary file and can't be changed", name));
+            }
+            return
+        }
         match command.action {
             Action::Operation(Operation::Insert(c)) => {
                 for _ in 0..command.number {
                     self.view.insert_char(c)
                 }
             }
+            Action::Operation(Operation::InsertTab) => {
+                for _ in 0..command.number {
+                    self.view.insert_tab(self.config.soft_tabs)
+                }
+            }
             Action::Operation(Operation::DeleteObject) => {
                 if let Some(obj) = command.object {
                     self.view.delete_object(obj);
@@ -231,6 +727,9 @@ impl<'e> Editor<'e> {
             }
             Action::Operation(Operation::Undo) => { self.view.undo() }
             Action::Operation(Operation::Redo) => { self.view.redo() }
+            Action::Operation(Operation::UndoOlder) => { self.view.undo_older() }
+            Action::Operation(Operation::UndoNewer) => { self.view.undo_newer() }
+            Action::Operation(Operation::UndoTo(state)) => { self.view.undo_to(state) }
 
             Action::Instruction(_) => {}
         }
@@ -274,3 +773,204 @@ impl<'e> Editor<'e> {
         }
     }
 }
+
+/// Build the command a CommandInfo names, with its arguments
+pub fn build_command(c: CommandInfo) -> Result<Command, CommandError> {
+    match ALL_COMMANDS.get(&*c.command_name) {
+        Some(build) => build(c.args),
+        None => Err(CommandError::Unknown(c.command_name)),
+    }
+}
+
+/// Create the named mode, with the user's key bindings added to its defaults
+fn new_mode<'e>(config: &Config, name: &str) -> Box<dyn Mode + 'e> {
+    let mut mode: Box<dyn Mode> = match name {
+        "emacs" => Box::new(EmacsMode::new()),
+        "normal" => Box::new(NormalMode::new()),
+        "insert" => Box::new(InsertMode::new()),
+        "visual" => Box::new(VisualMode::new()),
+        _ => Box::new(StandardMode::new()),
+    };
+    config.bind_keys(name, mode.keymap_mut());
+    mode
+}
+
+/// Load a buffer from one of the sources given at startup, along with anything
+/// the user should be told about it. The error is a message to show.
+fn open_input(source: Input) -> Result<(Buffer, Option<String>), String> {
+    match source {
+        Input::Filename(Some(path)) => open_file(PathBuf::from(path)),
+        Input::Filename(None) => Ok((Buffer::new(), None)),
+        Input::Stdin(mut reader) => {
+            let buffer = Buffer::read_from(&mut reader).map_err(|e| format!("Could not read stdin: {}", e))?;
+            let message = binary_message(&buffer);
+            Ok((buffer, message))
+        }
+    }
+}
+
+/// Load a file into a new buffer, along with anything the user should be told
+/// about it. The error is a message to show.
+///
+/// A file which doesn't exist yet gives an empty buffer with its name, so
+/// that saving creates it.
+fn open_file(path: PathBuf) -> Result<(Buffer, Option<String>), String> {
+    match Buffer::open(path.clone()) {
+        Ok(buffer) => {
+            let message = binary_message(&buffer);
+            Ok((buffer, message))
+        }
+        Err(OpenError::NotFound) => {
+            let mut buffer = Buffer::new();
+            buffer.set_file_path(path);
+            let message = format!("{} [New file]", buffer.file_name());
+            Ok((buffer, Some(message)))
+        }
+        Err(e) => Err(format!("Could not open {}: {}", path.display(), e)),
+    }
+}
+
+fn binary_message(buffer: &Buffer) -> Option<String> {
+    if buffer.read_only {
+        Some(format!("{} is a binary file, showing it read only", buffer.file_name()))
+    } else {
+        None
+    }
+}
+
+/// Wrap a buffer up to be shared with the view, set up as configured
+fn share_buffer(config: &Config, mut buffer: Buffer) -> Arc<Mutex<Buffer>> {
+    buffer.tab_width = config.tab_width;
+    if config.persistent_undo && !buffer.read_only {
+        buffer.persistent_undo = true;
+        history::restore(&mut buffer);
+    }
+    Arc::new(Mutex::new(buffer))
+}
+
+
+/// Index of `buffer` in the list of open buffers, or 0 if it isn't open
+fn buffer_index(buffers: &[Arc<Mutex<Buffer>>], buffer: &Arc<Mutex<Buffer>>) -> usize {
+    buffers.iter()
+        .position(|b| Arc::ptr_eq(b, buffer))
+        .unwrap_or(0)
+}
+
+/// Index of the open buffer for the file at `path`, however the path is written
+fn find_buffer(buffers: &[Arc<Mutex<Buffer>>], path: &Path) -> Option<usize> {
+    let path = utils::canonical(path);
+    buffers.iter().position(|b| match b.lock().unwrap().file_path {
+        Some(ref file) => utils::canonical(file) == path,
+        None => false,
+    })
+}
+
+/// Remove the buffer at `index`, returning the buffer to show in its place:
+/// the one which took its index, or the new last one.
+///
+/// Closing the only buffer leaves a new empty buffer open.
+fn remove_buffer(buffers: &mut Vec<Arc<Mutex<Buffer>>>, index: usize, config: &Config) -> Arc<Mutex<Buffer>> {
+    buffers.remove(index);
+    if buffers.is_empty() {
+        buffers.push(share_buffer(config, Buffer::new()));
+    }
+    buffers[cmp::min(index, buffers.len() - 1)].clone()
+}
+
+/// Names of the buffers with unsaved changes
+fn unsaved_buffer_names(buffers: &[Arc<Mutex<Buffer>>]) -> Vec<String> {
+    buffers.iter()
+        .map(|b| b.lock().unwrap())
+        .filter(|b| b.dirty)
+        .map(|b| b.file_name())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+
+    use tempdir::TempDir;
+
+    use crate::buffer::Buffer;
+    use crate::config::Config;
+
+    use super::{buffer_index, find_buffer, remove_buffer, share_buffer, unsaved_buffer_names};
+
+    fn open_buffers(names: &[&str]) -> Vec<Arc<Mutex<Buffer>>> {
+        names.iter().map(|name| {
+            let mut buffer = Buffer::new();
+            buffer.set_file_path(PathBuf::from(name));
+            share_buffer(&Config::default(), buffer)
+        }).collect()
+    }
+
+    fn name(buffer: &Arc<Mutex<Buffer>>) -> String {
+        buffer.lock().unwrap().file_name()
+    }
+
+    #[test]
+    fn test_buffer_index() {
+        let buffers = open_buffers(&["a", "b", "c"]);
+        assert_eq!(buffer_index(&buffers, &buffers[2]), 2);
+        // a buffer which has been closed isn't found
+        let closed = open_buffers(&["b"]).remove(0);
+        assert_eq!(buffer_index(&buffers, &closed), 0);
+    }
+
+    #[test]
+    fn test_find_buffer() {
+        let dir = TempDir::new("credits").unwrap();
+        let file = dir.path().join("foo.txt");
+        fs::write(&file, "foo").unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        let new_file = dir.path().join("new.txt");
+        let buffers = open_buffers(&[file.to_str().unwrap(), new_file.to_str().unwrap()]);
+
+        // the same file by another name is found
+        assert_eq!(find_buffer(&buffers, &dir.path().join("sub/../foo.txt")), Some(0));
+        assert_eq!(find_buffer(&buffers, &dir.path().join("./foo.txt")), Some(0));
+        // relative to the working directory, by climbing up to the root
+        let up = env::current_dir().unwrap().components().skip(1).map(|_| "..").collect::<PathBuf>();
+        let relative = up.join(file.strip_prefix("/").unwrap());
+        assert_eq!(find_buffer(&buffers, &relative), Some(0));
+
+        // a new file, which isn't on disk yet, by the path it was given
+        assert_eq!(find_buffer(&buffers, &new_file), Some(1));
+        assert_eq!(find_buffer(&buffers, &dir.path().join("other.txt")), None);
+    }
+
+    #[test]
+    fn test_remove_buffer() {
+        let config = Config::default();
+        let mut buffers = open_buffers(&["a", "b", "c"]);
+
+        // the next buffer takes the place of a closed one
+        let next = remove_buffer(&mut buffers, 1, &config);
+        assert_eq!(name(&next), "c");
+        assert_eq!(buffers.len(), 2);
+
+        // closing the last one moves back to the one before
+        let next = remove_buffer(&mut buffers, 1, &config);
+        assert_eq!(name(&next), "a");
+
+        // and closing the only one leaves an empty buffer
+        let next = remove_buffer(&mut buffers, 0, &config);
+        assert_eq!(buffers.len(), 1);
+        assert!(Arc::ptr_eq(&next, &buffers[0]));
+        assert!(next.lock().unwrap().file_path.is_none());
+    }
+
+    #[test]
+    fn test_unsaved_buffer_names() {
+        let buffers = open_buffers(&["a", "b", "c"]);
+        assert!(unsaved_buffer_names(&buffers).is_empty());
+        buffers[0].lock().unwrap().dirty = true;
+        buffers[2].lock().unwrap().dirty = true;
+        assert_eq!(unsaved_buffer_names(&buffers), vec!["a", "c"]);
+    }
+}
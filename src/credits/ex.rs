@@ -0,0 +1,204 @@
+//! Vi's ex commands, typed at the `:` prompt.
+//!
+//! Supported are `:w [file]`, `:q`, `:q!`, `:wq`, `:x`, `:e file`, a line
+//! number to jump to, `:set ff=unix` or `:set ff=dos` to change the line
+//! endings, and substitution with `:s/pattern/replacement/` on the
+//! cursor's line or `:%s/pattern/replacement/` on every line. Substitution
+//! replaces the first match on each line, or all of them with the `g` flag.
+//! The pattern is a regex, and the replacement refers to capture groups as
+//! `$1`.
+
+use std::path::PathBuf;
+
+use crate::fileformat::LineEnding;
+
+/// A parsed ex command
+#[derive(Debug, PartialEq)]
+pub enum ExCommand {
+    /// Save the buffer, under a new file name if one is given
+    Write(Option<PathBuf>),
+    /// `:w! file`, which writes over the file if it exists
+    Overwrite(PathBuf),
+    /// Quit, even with unsaved changes if forced
+    Quit { force: bool },
+    /// Save the buffer, then quit
+    WriteQuit,
+    /// Save the buffer if it has changes, then quit
+    Exit,
+    /// Open a file
+    Edit(PathBuf),
+    /// Move to a line, counting from 1
+    GotoLine(usize),
+    /// Change the line endings the buffer is saved with
+    SetLineEnding(LineEnding),
+    /// Replace matches of a pattern
+    Substitute {
+        /// The regex to look for
+        pattern: String,
+        /// What to replace each match with
+        replacement: String,
+        /// Every line rather than only the cursor's
+        whole_file: bool,
+        /// Every match on a line rather than only the first
+        global: bool,
+    },
+}
+
+/// Parse an ex command, the error being a message to show.
+pub fn parse(line: &str) -> Result<ExCommand, String> {
+    let line = line.trim();
+    let line = line.strip_prefix(':').unwrap_or(line).trim_start();
+
+    if !line.is_empty() && line.bytes().all(|c| c.is_ascii_digit()) {
+        return line.parse().map(ExCommand::GotoLine).map_err(|e| format!("Invalid line number: {}", e))
+    }
+    if let Some(rest) = line.strip_prefix("%s") {
+        return parse_substitute(rest, true)
+    }
+    if let Some(rest) = line.strip_prefix('s') {
+        if rest.starts_with(|c: char| !c.is_alphanumeric() && !c.is_whitespace()) {
+            return parse_substitute(rest, false)
+        }
+    }
+
+    if let Some(option) = line.strip_prefix("set ") {
+        return parse_set(option.trim())
+    }
+
+    let name_len = line.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(line.len());
+    let (name, rest) = line.split_at(name_len);
+    let (force, argument) = match rest.strip_prefix('!') {
+        Some(rest) => (true, rest.trim()),
+        None => (false, rest.trim()),
+    };
+    let file = if argument.is_empty() { None } else { Some(PathBuf::from(argument)) };
+
+    match (name, force, file) {
+        ("w" | "write", true, Some(file)) => Ok(ExCommand::Overwrite(file)),
+        ("w" | "write", _, file) => Ok(ExCommand::Write(file)),
+        ("q" | "quit", force, None) => Ok(ExCommand::Quit { force }),
+        ("wq", _, None) => Ok(ExCommand::WriteQuit),
+        ("x" | "xit", _, None) => Ok(ExCommand::Exit),
+        ("e" | "edit", _, Some(file)) => Ok(ExCommand::Edit(file)),
+        ("e" | "edit", _, None) => Err(String::from("No file name")),
+        ("q" | "quit" | "wq" | "x" | "xit", _, Some(_)) => Err(format!("Invalid argument: {}", line)),
+        _ => Err(format!("Not an editor command: {}", line)),
+    }
+}
+
+/// Parse the option given to `set`. Only the file format is supported.
+fn parse_set(option: &str) -> Result<ExCommand, String> {
+    match option.split_once('=') {
+        Some(("ff" | "fileformat", "unix")) => Ok(ExCommand::SetLineEnding(LineEnding::Lf)),
+        Some(("ff" | "fileformat", "dos")) => Ok(ExCommand::SetLineEnding(LineEnding::CrLf)),
+        Some(("ff" | "fileformat", value)) => Err(format!("Invalid file format: {}", value)),
+        _ => Err(format!("Unknown option: {}", option)),
+    }
+}
+
+/// Parse the `/pattern/replacement/flags` following `s`.
+///
+/// Any punctuation can be used instead of `/`, and is escaped with `\`.
+fn parse_substitute(source: &str, whole_file: bool) -> Result<ExCommand, String> {
+    let mut chars = source.chars();
+    let delimiter = chars.next().ok_or_else(|| String::from("No pattern given"))?;
+
+    let mut parts = vec![String::new()];
+    let mut escaped = false;
+    for c in chars {
+        if c == delimiter && !escaped && parts.len() < 3 {
+            parts.push(String::new());
+            continue
+        }
+        let part = parts.last_mut().unwrap();
+        if escaped {
+            if c != delimiter {
+                part.push('\\');
+            }
+            part.push(c);
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else {
+            part.push(c);
+        }
+    }
+    if escaped {
+        parts.last_mut().unwrap().push('\\');
+    }
+
+    let mut parts = parts.into_iter();
+    let pattern = parts.next().unwrap_or_default();
+    let replacement = parts.next().ok_or_else(|| String::from("No replacement given"))?;
+    let flags = parts.next().unwrap_or_default();
+    if pattern.is_empty() {
+        return Err(String::from("No pattern given"))
+    }
+    if let Some(flag) = flags.trim().chars().find(|&c| c != 'g') {
+        return Err(format!("Unknown substitute flag: {}", flag))
+    }
+
+    Ok(ExCommand::Substitute {
+        pattern,
+        replacement,
+        whole_file,
+        global: flags.contains('g'),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::path::PathBuf;
+
+    use crate::fileformat::LineEnding;
+
+    use super::{parse, ExCommand};
+
+    #[test]
+    fn test_file_commands() {
+        assert_eq!(parse("w"), Ok(ExCommand::Write(None)));
+        assert_eq!(parse(":w  notes.txt "), Ok(ExCommand::Write(Some(PathBuf::from("notes.txt")))));
+        assert_eq!(parse("w! notes.txt"), Ok(ExCommand::Overwrite(PathBuf::from("notes.txt"))));
+        assert_eq!(parse("w!"), Ok(ExCommand::Write(None)));
+        assert_eq!(parse("q"), Ok(ExCommand::Quit { force: false }));
+        assert_eq!(parse("quit!"), Ok(ExCommand::Quit { force: true }));
+        assert_eq!(parse("wq"), Ok(ExCommand::WriteQuit));
+        assert_eq!(parse("x"), Ok(ExCommand::Exit));
+        assert_eq!(parse("e src/main.rs"), Ok(ExCommand::Edit(PathBuf::from("src/main.rs"))));
+        assert_eq!(parse("42"), Ok(ExCommand::GotoLine(42)));
+        assert_eq!(parse("set ff=dos"), Ok(ExCommand::SetLineEnding(LineEnding::CrLf)));
+    }
+
+    #[test]
+    fn test_substitute() {
+        assert_eq!(parse("%s/a+/b/g"), Ok(ExCommand::Substitute {
+            pattern: String::from("a+"),
+            replacement: String::from("b"),
+            whole_file: true,
+            global: true,
+        }));
+        // other delimiters can be used, and escaped
+        assert_eq!(parse(r"s#a\#b#c\d#"), Ok(ExCommand::Substitute {
+            pattern: String::from("a#b"),
+            replacement: String::from(r"c\d"),
+            whole_file: false,
+            global: false,
+        }));
+        assert_eq!(parse("s/x/y"), parse("s/x/y/"));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(parse("e"), Err(String::from("No file name")));
+        assert_eq!(parse("frobnicate"), Err(String::from("Not an editor command: frobnicate")));
+        assert!(parse("q file").is_err());
+        assert!(parse("wq file").is_err());
+        assert!(parse("%s/a").is_err());
+        assert!(parse("%s//b/").is_err());
+        assert!(parse("s/a/b/i").is_err());
+        assert!(parse("99999999999999999999999").is_err());
+        assert!(parse("set ff=mac").is_err());
+        assert!(parse("set number").is_err());
+    }
+}
@@ -0,0 +1,388 @@
+//! How the text of a file is laid out on disk.
+//!
+//! Buffers always hold UTF-8 text with `\n` line breaks, and never start
+//! with a byte order mark. Files in another encoding, using `\r\n` or
+//! starting with a BOM are converted when they are loaded, and converted
+//! back when they are saved.
+//!
+//! Files which don't look like text at all are not converted, they are shown
+//! as a hex dump instead.
+
+use std::cmp;
+use std::fmt;
+use std::io::{self, Write};
+use std::str;
+
+/// The UTF-8 encoded byte order mark
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+const UTF16LE_BOM: &[u8] = b"\xFF\xFE";
+const UTF16BE_BOM: &[u8] = b"\xFE\xFF";
+
+/// Bytes looked at when deciding whether a file is binary
+const SNIFF_LEN: usize = 8192;
+
+/// Bytes of a binary file shown in its hex dump
+const MAX_DUMP_LEN: usize = 64 * 1024;
+
+/// The characters which end each line
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,     // `\n`, as on Unix
+    CrLf,   // `\r\n`, as on Windows
+}
+
+impl LineEnding {
+    /// The other line ending
+    pub fn toggled(self) -> LineEnding {
+        match self {
+            LineEnding::Lf => LineEnding::CrLf,
+            LineEnding::CrLf => LineEnding::Lf,
+        }
+    }
+}
+
+impl fmt::Display for LineEnding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LineEnding::Lf => write!(f, "LF"),
+            LineEnding::CrLf => write!(f, "CRLF"),
+        }
+    }
+}
+
+/// The character encoding of a file
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// ISO 8859-1, which every file that isn't valid UTF-8 is taken to be
+    Latin1,
+}
+
+impl Encoding {
+    fn bom(self) -> &'static [u8] {
+        match self {
+            Encoding::Utf8 => UTF8_BOM,
+            Encoding::Utf16Le => UTF16LE_BOM,
+            Encoding::Utf16Be => UTF16BE_BOM,
+            Encoding::Latin1 => b"",
+        }
+    }
+
+    /// Convert text to this encoding, failing if it has chars the encoding
+    /// can't represent.
+    fn encode(self, text: &[u8]) -> io::Result<Vec<u8>> {
+        if self == Encoding::Utf8 {
+            return Ok(text.to_vec())
+        }
+        let text = str::from_utf8(text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let encoded = match self {
+            Encoding::Utf8 => unreachable!(),
+            Encoding::Utf16Le => text.encode_utf16().flat_map(|unit| unit.to_le_bytes()).collect(),
+            Encoding::Utf16Be => text.encode_utf16().flat_map(|unit| unit.to_be_bytes()).collect(),
+            Encoding::Latin1 => {
+                let mut encoded = Vec::with_capacity(text.len());
+                for c in text.chars() {
+                    if c as u32 > 0xFF {
+                        let message = format!("{:?} can't be saved as {}", c, self);
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, message))
+                    }
+                    encoded.push(c as u8);
+                }
+                encoded
+            }
+        };
+        Ok(encoded)
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Encoding::Utf8 => write!(f, "UTF-8"),
+            Encoding::Utf16Le => write!(f, "UTF-16LE"),
+            Encoding::Utf16Be => write!(f, "UTF-16BE"),
+            Encoding::Latin1 => write!(f, "Latin-1"),
+        }
+    }
+}
+
+/// How a file was stored, so that saving it keeps it that way.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FileFormat {
+    pub encoding: Encoding,
+    pub line_ending: LineEnding,
+    /// Whether the file starts with a byte order mark
+    pub bom: bool,
+    /// Whether the last line ends with a line break
+    pub final_newline: bool,
+}
+
+impl Default for FileFormat {
+    fn default() -> FileFormat {
+        FileFormat {
+            encoding: Encoding::Utf8,
+            line_ending: LineEnding::Lf,
+            bom: false,
+            final_newline: true,
+        }
+    }
+}
+
+impl FileFormat {
+    /// Work out the format of a file's contents, and convert them to the text
+    /// a buffer holds. Gives `None` for a binary file.
+    ///
+    /// A file is taken to use `\r\n` when most of its lines end that way. In
+    /// a file using `\n`, any `\r\n` is left alone.
+    pub fn detect(contents: &[u8]) -> Option<(FileFormat, Vec<u8>)> {
+        let (encoding, bom, text) = decode(contents)?;
+
+        let newlines = text.iter().filter(|&&c| c == b'\n').count();
+        let crlfs = text.windows(2).filter(|pair| pair == b"\r\n").count();
+        let line_ending = if crlfs > 0 && crlfs * 2 >= newlines { LineEnding::CrLf } else { LineEnding::Lf };
+
+        let format = FileFormat {
+            encoding,
+            line_ending,
+            bom,
+            final_newline: text.is_empty() || text.ends_with(b"\n"),
+        };
+        let text = match line_ending {
+            LineEnding::Lf => text,
+            LineEnding::CrLf => {
+                let mut lf_text = Vec::with_capacity(text.len());
+                for (idx, &c) in text.iter().enumerate() {
+                    if !(c == b'\r' && text.get(idx + 1) == Some(&b'\n')) {
+                        lf_text.push(c);
+                    }
+                }
+                lf_text
+            }
+        };
+        Some((format, text))
+    }
+
+    /// Write out the lines of a buffer in this format.
+    ///
+    /// A file which ended with a line break still does, even if the buffer's
+    /// last line break was deleted.
+    pub fn write<I>(&self, out: &mut dyn Write, lines: I) -> io::Result<()>
+        where I: IntoIterator<Item = Vec<u8>>
+    {
+        if self.bom {
+            out.write_all(self.encoding.bom())?;
+        }
+        let mut ended = true;
+        for mut line in lines {
+            if line.is_empty() { continue }
+            ended = line.ends_with(b"\n");
+            if ended && self.line_ending == LineEnding::CrLf {
+                line.insert(line.len() - 1, b'\r');
+            }
+            out.write_all(&self.encoding.encode(&line)?)?;
+        }
+        if self.final_newline && !ended {
+            out.write_all(&self.encoding.encode(self.newline())?)?;
+        }
+        Ok(())
+    }
+
+    fn newline(&self) -> &'static [u8] {
+        match self.line_ending {
+            LineEnding::Lf => b"\n",
+            LineEnding::CrLf => b"\r\n",
+        }
+    }
+}
+
+impl fmt::Display for FileFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.line_ending, self.encoding)?;
+        if self.bom {
+            write!(f, " BOM")?;
+        }
+        Ok(())
+    }
+}
+
+/// Work out the encoding of a file and convert it to UTF-8, giving the
+/// encoding, whether there was a BOM and the text.
+fn decode(contents: &[u8]) -> Option<(Encoding, bool, Vec<u8>)> {
+    if let Some(rest) = contents.strip_prefix(UTF8_BOM) {
+        if str::from_utf8(rest).is_ok() {
+            return Some((Encoding::Utf8, true, rest.to_vec()))
+        }
+    }
+    if let Some(rest) = contents.strip_prefix(UTF16LE_BOM) {
+        if let Some(text) = decode_utf16(rest, u16::from_le_bytes) {
+            return Some((Encoding::Utf16Le, true, text))
+        }
+    }
+    if let Some(rest) = contents.strip_prefix(UTF16BE_BOM) {
+        if let Some(text) = decode_utf16(rest, u16::from_be_bytes) {
+            return Some((Encoding::Utf16Be, true, text))
+        }
+    }
+
+    // without a BOM, UTF-16 is recognised by mostly ASCII text having every
+    // other byte zero
+    let sniffed = &contents[..cmp::min(contents.len(), SNIFF_LEN)];
+    if let Some(encoding) = sniff_utf16(sniffed) {
+        let from_bytes = match encoding {
+            Encoding::Utf16Le => u16::from_le_bytes,
+            _ => u16::from_be_bytes,
+        };
+        if let Some(text) = decode_utf16(contents, from_bytes) {
+            return Some((encoding, false, text))
+        }
+    }
+
+    if looks_binary(sniffed) {
+        return None
+    }
+    if str::from_utf8(contents).is_ok() {
+        return Some((Encoding::Utf8, false, contents.to_vec()))
+    }
+    let text: String = contents.iter().map(|&c| c as char).collect();
+    Some((Encoding::Latin1, false, text.into_bytes()))
+}
+
+fn decode_utf16(contents: &[u8], from_bytes: fn([u8; 2]) -> u16) -> Option<Vec<u8>> {
+    if !contents.len().is_multiple_of(2) {
+        return None
+    }
+    let units = contents.chunks(2).map(|pair| from_bytes([pair[0], pair[1]]));
+    char::decode_utf16(units)
+        .collect::<Result<String, _>>()
+        .ok()
+        .map(String::into_bytes)
+}
+
+fn sniff_utf16(sniffed: &[u8]) -> Option<Encoding> {
+    if sniffed.len() < 2 || !sniffed.len().is_multiple_of(2) {
+        return None
+    }
+    let pairs = sniffed.len() / 2;
+    let even_zeros = sniffed.iter().step_by(2).filter(|&&c| c == 0).count();
+    let odd_zeros = sniffed.iter().skip(1).step_by(2).filter(|&&c| c == 0).count();
+    if even_zeros == 0 && odd_zeros * 2 > pairs {
+        Some(Encoding::Utf16Le)
+    } else if odd_zeros == 0 && even_zeros * 2 > pairs {
+        Some(Encoding::Utf16Be)
+    } else {
+        None
+    }
+}
+
+/// Whether a file has NUL bytes, or many other control chars than text has
+fn looks_binary(sniffed: &[u8]) -> bool {
+    let is_control = |c: u8| c < 0x20 && !matches!(c, b'\t' | b'\n' | b'\r' | 0x0C | 0x1B);
+    let controls = sniffed.iter().filter(|&&c| is_control(c)).count();
+    sniffed.contains(&0) || controls * 10 > sniffed.len()
+}
+
+/// Show a binary file as lines of offset, hex bytes and printable chars.
+///
+/// Only the start of large files is shown.
+pub fn hex_dump(contents: &[u8]) -> Vec<u8> {
+    let mut dump = String::new();
+    for (line, chunk) in contents[..cmp::min(contents.len(), MAX_DUMP_LEN)].chunks(16).enumerate() {
+        dump.push_str(&format!("{:08x} ", line * 16));
+        for column in 0..16 {
+            if column % 8 == 0 {
+                dump.push(' ');
+            }
+            match chunk.get(column) {
+                Some(byte) => dump.push_str(&format!("{:02x} ", byte)),
+                None => dump.push_str("   "),
+            }
+        }
+        let printable: String = chunk.iter()
+            .map(|&c| if c.is_ascii_graphic() || c == b' ' { c as char } else { '.' })
+            .collect();
+        dump.push_str(&format!(" |{}|\n", printable));
+    }
+    if contents.len() > MAX_DUMP_LEN {
+        dump.push_str(&format!("... {} more bytes\n", contents.len() - MAX_DUMP_LEN));
+    }
+    dump.into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{hex_dump, Encoding, FileFormat, LineEnding};
+
+    fn round_trip(contents: &[u8]) -> (FileFormat, Vec<u8>, Vec<u8>) {
+        let (format, text) = FileFormat::detect(contents).unwrap();
+        let lines = text.split_inclusive(|&c| c == b'\n').map(|line| line.to_vec());
+        let mut saved = Vec::new();
+        format.write(&mut saved, lines).unwrap();
+        (format, text, saved)
+    }
+
+    #[test]
+    fn test_crlf() {
+        let (format, text, saved) = round_trip(b"one\r\ntwo\r\n");
+        assert_eq!(format.line_ending, LineEnding::CrLf);
+        assert_eq!(text, b"one\ntwo\n");
+        assert_eq!(saved, b"one\r\ntwo\r\n");
+
+        // a stray `\r\n` in a `\n` file is kept as it is
+        let (format, text, saved) = round_trip(b"one\ntwo\r\nthree\n");
+        assert_eq!(format.line_ending, LineEnding::Lf);
+        assert_eq!(text, b"one\ntwo\r\nthree\n");
+        assert_eq!(saved, b"one\ntwo\r\nthree\n");
+    }
+
+    #[test]
+    fn test_bom_and_final_newline() {
+        let (format, text, saved) = round_trip(b"\xEF\xBB\xBFtext");
+        assert!(format.bom);
+        assert!(!format.final_newline);
+        assert_eq!(text, b"text");
+        assert_eq!(saved, b"\xEF\xBB\xBFtext");
+
+        // the final line break is put back if it was deleted
+        let (format, _) = FileFormat::detect(b"a\r\nb\r\n").unwrap();
+        let mut saved = Vec::new();
+        format.write(&mut saved, vec![b"a\n".to_vec(), b"b".to_vec()]).unwrap();
+        assert_eq!(saved, b"a\r\nb\r\n");
+
+        assert_eq!(FileFormat::detect(b"").unwrap().0, FileFormat::default());
+    }
+
+    #[test]
+    fn test_encodings() {
+        let (format, text, saved) = round_trip(b"caf\xE9\n");
+        assert_eq!(format.encoding, Encoding::Latin1);
+        assert_eq!(text, "café\n".as_bytes());
+        assert_eq!(saved, b"caf\xE9\n");
+
+        let utf16le = b"\xFF\xFEh\0\xE9\0\r\0\n\0";
+        let (format, text, saved) = round_trip(utf16le);
+        assert_eq!((format.encoding, format.bom, format.line_ending), (Encoding::Utf16Le, true, LineEnding::CrLf));
+        assert_eq!(text, "hé\n".as_bytes());
+        assert_eq!(saved, utf16le);
+
+        let (format, text, saved) = round_trip(b"\0h\0i");
+        assert_eq!((format.encoding, format.bom), (Encoding::Utf16Be, false));
+        assert_eq!(text, b"hi");
+        assert_eq!(saved, b"\0h\0i");
+
+        // chars Latin-1 doesn't have can't be saved
+        let (format, _) = FileFormat::detect(b"\xE9").unwrap();
+        let mut saved = Vec::new();
+        assert!(format.write(&mut saved, vec!["€".as_bytes().to_vec()]).is_err());
+    }
+
+    #[test]
+    fn test_binary() {
+        assert_eq!(FileFormat::detect(b"\x7fELF\x02\x01\x01\0\0\0"), None);
+        assert_eq!(FileFormat::detect(b"\x01\x02\x03\x04text"), None);
+        assert_eq!(hex_dump(b"\x7fELF\n"),
+                   b"00000000  7f 45 4c 46 0a                                    |.ELF.|\n".to_vec());
+    }
+}
@@ -0,0 +1,163 @@
+//! Keeping the undo history of files between sessions.
+//!
+//! When a buffer is saved, its undo tree is written to a cache directory,
+//! under a name made from the file's path. The history records a hash of
+//! the text it leads up to, and is only brought back when the file is opened
+//! with that same text. A file changed by something else in the meantime
+//! starts with a fresh history.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::buffer::Buffer;
+use crate::log::Log;
+use crate::save;
+use crate::utils::canonical;
+
+/// The first line of a history file, to change if the format ever does
+const HEADER: &str = "credits undo history 2";
+
+/// Write the undo history of a buffer, which has just been saved.
+pub fn save(buffer: &Buffer) -> io::Result<()> {
+    match cache_dir() {
+        Some(dir) => save_in(&dir, buffer),
+        None => Ok(()),
+    }
+}
+
+/// Bring back the undo history of a buffer's file, if it was saved with the
+/// text the buffer has now.
+pub fn restore(buffer: &mut Buffer) {
+    if let Some(dir) = cache_dir() {
+        restore_from(&dir, buffer);
+    }
+}
+
+fn save_in(dir: &Path, buffer: &Buffer) -> io::Result<()> {
+    let file = match buffer.file_path {
+        Some(ref file) => canonical(file),
+        None => return Ok(()),
+    };
+    fs::create_dir_all(dir)?;
+    save::write_atomically(&history_path(dir, &file), |out| {
+        writeln!(out, "{}", HEADER)?;
+        writeln!(out, "{}", file.display())?;
+        writeln!(out, "{:016x}", text_hash(buffer))?;
+        buffer.log.write_history(out)
+    })
+}
+
+fn restore_from(dir: &Path, buffer: &mut Buffer) {
+    let file = match buffer.file_path {
+        Some(ref file) => canonical(file),
+        None => return,
+    };
+    let source = match fs::read_to_string(history_path(dir, &file)) {
+        Ok(source) => source,
+        Err(_) => return,
+    };
+
+    let mut parts = source.splitn(4, '\n');
+    let header = (parts.next(), parts.next(), parts.next());
+    let file_name = file.display().to_string();
+    let hash = format!("{:016x}", text_hash(buffer));
+    if header != (Some(HEADER), Some(file_name.as_str()), Some(hash.as_str())) {
+        return
+    }
+    if let Some(log) = parts.next().and_then(Log::read_history) {
+        buffer.log = log;
+    }
+}
+
+/// `$XDG_CACHE_HOME/credits/undo`, or `~/.cache/credits/undo`
+fn cache_dir() -> Option<PathBuf> {
+    let cache = match env::var_os("XDG_CACHE_HOME") {
+        Some(cache) if !cache.is_empty() => PathBuf::from(cache),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".cache"),
+    };
+    Some(cache.join("credits/undo"))
+}
+
+fn history_path(dir: &Path, file: &Path) -> PathBuf {
+    dir.join(format!("{:016x}", fnv1a(file.to_string_lossy().bytes())))
+}
+
+/// Hash the text as it reads back in, with the line break which saving adds
+/// at the end of the file.
+fn text_hash(buffer: &Buffer) -> u64 {
+    let mut text: Vec<u8> = buffer.lines().flatten().collect();
+    if buffer.format.final_newline && !text.is_empty() && !text.ends_with(b"\n") {
+        text.push(b'\n');
+    }
+    fnv1a(text)
+}
+
+/// The 64 bit FNV-1a hash, which unlike the standard library's hashers is
+/// the same from one build to the next.
+fn fnv1a<I: IntoIterator<Item = u8>>(bytes: I) -> u64 {
+    bytes.into_iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs;
+
+    use tempdir::TempDir;
+
+    use crate::buffer::{Buffer, Mark};
+
+    use super::{restore_from, save_in};
+
+    #[test]
+    fn test_save_and_restore() {
+        let dir = TempDir::new("credits").unwrap();
+        let path = dir.path().join("file.txt");
+        let history = dir.path().join("undo");
+        fs::write(&path, "a").unwrap();
+
+        let mut buffer = Buffer::open(path.clone()).unwrap();
+        buffer.set_mark(Mark::Cursor(0), 1);
+        buffer.insert_char(Mark::Cursor(0), 'b');
+        fs::write(&path, "ab").unwrap();
+        save_in(&history, &buffer).unwrap();
+
+        // the edit can be undone after opening the file again
+        let mut reopened = Buffer::open(path.clone()).unwrap();
+        restore_from(&history, &mut reopened);
+        assert_eq!(reopened.undo(), Some(1));
+        assert_eq!(reopened.lines().next().unwrap(), b"a");
+
+        // but not once the file has been changed
+        fs::write(&path, "abc").unwrap();
+        let mut changed = Buffer::open(path).unwrap();
+        restore_from(&history, &mut changed);
+        assert_eq!(changed.undo(), None);
+    }
+
+    #[test]
+    fn test_restore_after_adding_final_newline() {
+        let dir = TempDir::new("credits").unwrap();
+        let path = dir.path().join("file.txt");
+        let history = dir.path().join("undo");
+
+        // a new buffer gets a line break at the end when it's saved
+        let mut buffer = Buffer::new();
+        buffer.set_mark(Mark::Cursor(0), 0);
+        buffer.insert_char(Mark::Cursor(0), 'a');
+        buffer.set_file_path(path.clone());
+        let mut saved = Vec::new();
+        buffer.format.write(&mut saved, buffer.lines()).unwrap();
+        fs::write(&path, saved).unwrap();
+        save_in(&history, &buffer).unwrap();
+
+        let mut reopened = Buffer::open(path).unwrap();
+        assert_eq!(reopened.lines().next().unwrap(), b"a\n");
+        restore_from(&history, &mut reopened);
+        assert_eq!(reopened.undo(), Some(0));
+    }
+}
@@ -1,7 +1,7 @@
-use gapbuffer::GapBuffer;
+use crate::rope::Rope;
 
 pub struct Lines<'a> {
-    pub buffer: &'a GapBuffer<u8>,
+    pub buffer: &'a Rope,
     pub tail: usize,
     pub head: usize,
 }
@@ -10,16 +10,19 @@ impl<'a> Iterator for Lines<'a> {
     type Item = Vec<u8>;
 
     fn next(&mut self) -> Option<Vec<u8>> {
-        if self.tail == self.head { return None; }
+        if self.tail >= self.head { return None; }
         let old_tail = self.tail;
         //update tail to either the first char after the next \n or to self.head
-        self.tail = (old_tail..self.head).filter(|i| { *i + 1 == self.head
-                                                       || self.buffer[*i] == b'\n' })
-                                         .take(1)
-                                         .next()
-                                         .unwrap() + 1;
-        Some((old_tail..if self.tail == self.head { self.tail - 1 } else { self.tail })
-            .map( |i| self.buffer[i] ).collect())
+        match self.buffer.next_newline(old_tail) {
+            Some(newline) if newline + 1 < self.head => {
+                self.tail = newline + 1;
+                Some(self.buffer.slice(old_tail, self.tail))
+            }
+            _ => {
+                self.tail = self.head;
+                Some(self.buffer.slice(old_tail, self.head - 1))
+            }
+        }
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
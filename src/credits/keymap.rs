@@ -47,6 +47,19 @@ impl Trie {
             }
         }
     }
+    fn unbind_keys(&mut self, keys: &[KeyEvent]) {
+        if let Trie::Node(ref mut map) = *self {
+            match keys.len() {
+                0 => {}
+                1 => { map.remove(&keys[0]); }
+                _ => {
+                    if let Some(node) = map.get_mut(&keys[0]) {
+                        node.unbind_keys(&keys[1..]);
+                    }
+                }
+            }
+        }
+    }
     fn bind_keys(&mut self, keys: &[KeyEvent], value: CommandInfo) {
         if keys.len() == 1 {
             self.bind_key(keys[0], value);
@@ -135,6 +148,11 @@ impl KeyMap {
         self.root.bind_key(key, value);
     }
 
+    /// Remove a key-sequence binding, if there is one
+    pub fn unbind_keys(&mut self, keys: &[KeyEvent]) {
+        self.root.unbind_keys(keys);
+    }
+
     // /// Insert or overwrite a key binding or key-sequence binding
     // pub fn bind(&mut self, binding: KeyBinding) {
     //     if binding.keys.len() == 1 {
@@ -0,0 +1,91 @@
+//! The kill ring holds text which was cut or copied, for pasting later.
+//!
+//! Like Emacs' kill ring, older entries stay around: after pasting, they can
+//! be cycled through in place of the text just pasted.
+
+use std::collections::VecDeque;
+
+/// Number of entries kept before the oldest ones are dropped
+const CAPACITY: usize = 60;
+
+/// Text which was cut or copied, the most recent first.
+pub struct KillRing {
+    entries: VecDeque<String>,
+    /// Index of the entry to paste next
+    yank_index: usize,
+}
+
+impl KillRing {
+    pub fn new() -> KillRing {
+        KillRing {
+            entries: VecDeque::new(),
+            yank_index: 0,
+        }
+    }
+
+    /// Add some text to the front of the ring, it is what gets pasted next.
+    pub fn push(&mut self, text: String) {
+        if text.is_empty() { return }
+
+        self.entries.push_front(text);
+        self.entries.truncate(CAPACITY);
+        self.yank_index = 0;
+    }
+
+    /// The text to paste
+    pub fn current(&self) -> Option<&str> {
+        self.entries.get(self.yank_index).map(|text| text.as_str())
+    }
+
+    /// Move on to the next older entry, wrapping around to the most recent one.
+    pub fn rotate(&mut self) -> Option<&str> {
+        if self.entries.is_empty() { return None }
+
+        self.yank_index = (self.yank_index + 1) % self.entries.len();
+        self.current()
+    }
+}
+
+impl Default for KillRing {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{KillRing, CAPACITY};
+
+    #[test]
+    fn test_push_and_rotate() {
+        let mut ring = KillRing::new();
+        assert_eq!(ring.current(), None);
+        assert_eq!(ring.rotate(), None);
+
+        ring.push(String::from("one"));
+        ring.push(String::new());
+        ring.push(String::from("two"));
+        assert_eq!(ring.current(), Some("two"));
+        assert_eq!(ring.rotate(), Some("one"));
+        assert_eq!(ring.rotate(), Some("two"));
+
+        // a new entry is the next one pasted, wherever the ring had got to
+        ring.rotate();
+        ring.push(String::from("three"));
+        assert_eq!(ring.current(), Some("three"));
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut ring = KillRing::new();
+        for i in 0..CAPACITY + 5 {
+            ring.push(i.to_string());
+        }
+        for _ in 0..CAPACITY - 1 {
+            ring.rotate();
+        }
+        assert_eq!(ring.current(), Some("5"));
+        assert_eq!(ring.rotate(), Some(&*(CAPACITY + 4).to_string()));
+    }
+}
@@ -10,15 +10,18 @@
 #![warn(missing_docs)]
 
 extern crate crossterm;
-extern crate gapbuffer;
-extern crate tempdir;
 extern crate regex;
+extern crate rustc_serialize;
 extern crate unicode_width;
 #[macro_use] extern crate lazy_static;
+#[cfg(test)] extern crate tempdir;
 
 pub use editor::Editor;
 pub use input::Input;
 pub use modes::{StandardMode, NormalMode, EmacsMode, Mode};
+pub use rope::Rope;
+pub use config::{Config, StartMode};
+pub use buffer::Buffer;
 
 mod input;
 mod utils;
@@ -32,3 +35,13 @@ mod overlay;
 mod command;
 mod textobject;
 mod iterators;
+mod rope;
+mod syntax;
+mod theme;
+mod config;
+mod killring;
+mod clipboard;
+mod ex;
+mod save;
+mod fileformat;
+mod history;
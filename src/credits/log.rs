@@ -1,28 +1,86 @@
 //! Primitive command log, currently used for undo / redo.
 //! This is a deliberately unoptimized representation, for simplicity.  It is by no means final.
+//!
+//! The log is a tree of every state the text has been in. Making an edit
+//! after undoing starts a new branch rather than throwing away what was
+//! undone, so any earlier state can be gone back to. Undo and redo move up
+//! and down a branch, while `older` and `newer` step through the states in
+//! the order they were made, across branches.
+//!
+//! Consecutive edits are merged into a single undo step: typing is undone a
+//! word at a time, or a whole Vi insert session at a time. A step ends when
+//! the cursor jumps elsewhere, or after a pause in typing. Commands which
+//! make several edits wrap them in `begin_group` and `end_group`, so they are
+//! always undone at once.
 
+use std::io::{self, Write};
 use std::mem;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// A pause in editing this long ends the undo step being built up
+const IDLE_TIMEOUT: Duration = Duration::from_secs(2);
 
 /// Represents a modification of data.
+#[derive(Clone)]
 pub enum Change {
-    ///Character insertion.
-    Insert(usize, u8),
-    ///Character removal.
-    Remove(usize, u8),
+    /// Text inserted at an index.
+    InsertStr(usize, Vec<u8>),
+    /// Text removed from an index, kept so that it can be put back.
+    RemoveRange(usize, Vec<u8>),
 }
 
 impl Change {
     /// Reverses a change, consuming it in the process
     pub fn reverse(self) -> Change {
         match self {
-            Change::Insert(usize, u8) => Change::Remove(usize, u8),
-            Change::Remove(usize, u8) => Change::Insert(usize, u8),
+            Change::InsertStr(idx, text) => Change::RemoveRange(idx, text),
+            Change::RemoveRange(idx, text) => Change::InsertStr(idx, text),
+        }
+    }
+
+    /// Where the change starts
+    pub fn idx(&self) -> usize {
+        match *self {
+            Change::InsertStr(idx, _) | Change::RemoveRange(idx, _) => idx,
+        }
+    }
+
+    fn text(&self) -> &[u8] {
+        match *self {
+            Change::InsertStr(_, ref text) | Change::RemoveRange(_, ref text) => text,
+        }
+    }
+
+    /// Make `next` part of this change, if it carries on where this one
+    /// ends: typing after inserted text, or deleting either side of removed
+    /// text. Gives `next` back if it can't be.
+    fn merge(&mut self, next: Change) -> Option<Change> {
+        match (self, next) {
+            (&mut Change::InsertStr(idx, ref mut text), Change::InsertStr(next_idx, next_text))
+                    if next_idx == idx + text.len() => {
+                text.extend(next_text);
+                None
+            }
+            (&mut Change::RemoveRange(idx, ref mut text), Change::RemoveRange(next_idx, next_text))
+                    if next_idx == idx => {
+                text.extend(next_text);
+                None
+            }
+            (&mut Change::RemoveRange(ref mut idx, ref mut text), Change::RemoveRange(next_idx, mut next_text))
+                    if next_idx + next_text.len() == *idx => {
+                next_text.append(text);
+                *text = next_text;
+                *idx = next_idx;
+                None
+            }
+            (_, next) => Some(next),
         }
     }
 }
 
 /// Log entry
 /// Entries may only be played linearly--they don't make sense out of order.
+#[derive(Clone)]
 pub struct LogEntry {
     /// The initial point position associated with this log entry.
     ///
@@ -36,6 +94,15 @@ pub struct LogEntry {
 }
 
 impl LogEntry {
+    /// Add a change, merging it into the last one where they join up.
+    fn push(&mut self, change: Change) {
+        let unmerged = match self.changes.last_mut() {
+            Some(last) => last.merge(change),
+            None => Some(change),
+        };
+        self.changes.extend(unmerged);
+    }
+
     /// Reverse a log entry, consuming it in the process.
     pub fn reverse(mut self) -> LogEntry {
         self.changes.reverse();
@@ -65,7 +132,7 @@ impl<'a> Transaction<'a> {
     /// The logging should occur after the change has been executed.  This may eventually allow
     /// rollback in case of failure.
     pub fn log(&mut self, change: Change, idx: usize) {
-        self.entry.changes.push(change);
+        self.entry.push(change);
         self.entry.end_point = idx;
     }
 }
@@ -76,31 +143,121 @@ impl<'a> Drop for Transaction<'a> {
         if self.entry.changes.is_empty() { return }
         // Create the new log entry
         let entry = LogEntry {
-            changes: mem::replace(&mut self.entry.changes, Vec::new()),
+            changes: mem::take(&mut self.entry.changes),
             .. self.entry
         };
-        // Commit the transaction.
-        self.entries.undo.push(entry);
-        // Clear the redo entries now that the transaction has been committed.
-        self.entries.redo.clear();
+        self.entries.commit(entry);
     }
 }
 
-/// Log entries structure.  Just two stacks.
+/// How consecutive edits are merged into a single undo step
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Grouping {
+    /// Typing is undone a word at a time, other edits one at a time
+    Words,
+    /// Every edit is merged, as in Vi's insert mode
+    Session,
+}
+
+impl Grouping {
+    /// Whether `next` belongs in the same undo step as `last`
+    fn merges(self, last: &LogEntry, next: &LogEntry) -> bool {
+        match self {
+            Grouping::Session => true,
+            Grouping::Words => {
+                let inserts_only = |entry: &LogEntry| entry.changes.iter().all(|change| matches!(change, Change::InsertStr(..)));
+                if !inserts_only(last) || !inserts_only(next) { return false }
+                match (last.changes.last(), next.changes.first()) {
+                    // typing carries on right after the last char, and
+                    // doesn't start a new word
+                    (Some(Change::InsertStr(last_idx, last_text)), Some(Change::InsertStr(idx, text))) => {
+                        let ends_word = last_text.last().is_some_and(u8::is_ascii_whitespace);
+                        let starts_word = text.first().is_some_and(|c| !c.is_ascii_whitespace());
+                        *idx == last_idx + last_text.len() && !(ends_word && starts_word)
+                    }
+                    _ => false,
+                }
+            }
+        }
+    }
+}
+
+/// A state of the text, reached from its parent state by the changes of `entry`
+struct State {
+    /// The changes made to the parent state, none for the first state
+    entry: LogEntry,
+    parent: usize,
+    /// The state redo goes to, the child most recently made or undone
+    redo_child: Option<usize>,
+    /// When the state was made
+    time: SystemTime,
+}
+
+/// Log entries structure.  A tree of states, stored in the order they were made.
 pub struct Log {
-    /// Undo log entries--LIFO stack.
-    undo: Vec<LogEntry>,
-    /// Redo log entries--LIFO stack.  Cleared after a new change (other than an undo or redo)
-    /// is committed.
-    redo: Vec<LogEntry>,
+    /// Every state of the text, the first being the text before any edits.
+    states: Vec<State>,
+    /// The state the text is in now
+    current: usize,
+    /// How edits are merged into undo steps
+    grouping: Grouping,
+    /// Number of explicit groups open, see `begin_group`
+    depth: usize,
+    /// Whether the current state holds the edits of the open explicit group
+    in_group: bool,
+    /// Whether the current state may still be merged with the next edit
+    mergeable: bool,
+    /// When the last edit was made
+    last_commit: Option<Instant>,
 }
 
 impl Log {
     /// Set up log entries.  They are initially empty.
     pub fn new() -> Log {
         Log {
-            undo: Vec::new(),
-            redo: Vec::new(),
+            states: vec![State {
+                entry: LogEntry { init_point: 0, end_point: 0, changes: Vec::new() },
+                parent: 0,
+                redo_child: None,
+                time: SystemTime::now(),
+            }],
+            current: 0,
+            grouping: Grouping::Words,
+            depth: 0,
+            in_group: false,
+            mergeable: false,
+            last_commit: None,
+        }
+    }
+
+    /// Change how edits are merged, starting a new undo step.
+    pub fn set_grouping(&mut self, grouping: Grouping) {
+        self.grouping = grouping;
+        self.mergeable = false;
+    }
+
+    /// Start a new undo step, such as when the cursor jumps elsewhere.
+    ///
+    /// An open explicit group carries on regardless.
+    pub fn break_group(&mut self) {
+        self.mergeable = false;
+    }
+
+    /// Merge every edit until the matching `end_group` into a single undo step.
+    ///
+    /// Groups can be nested, only the outermost one counts.
+    pub fn begin_group(&mut self) {
+        if self.depth == 0 {
+            self.in_group = false;
+        }
+        self.depth += 1;
+    }
+
+    /// Close a group opened with `begin_group`.
+    pub fn end_group(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+        if self.depth == 0 {
+            self.in_group = false;
         }
     }
 
@@ -119,30 +276,361 @@ impl Log {
         }
     }
 
-    /// This reverses the most recent change on the undo stack, places the new change on the redo
-    /// stack, and then returns a reference to it.  It is the caller's responsibility to actually
-    /// perform the change.
-    pub fn undo(&mut self) -> Option<&LogEntry> {
-        match self.undo.pop() {
-            Some(change) => {
-                let last = self.redo.len();
-                self.redo.push(change.reverse());
-                Some(&self.redo[last])
-            },
-            None => None
-        }
-    }
-    /// This reverses the most recent change on the redo stack, places the new change on the undo
-    /// stack, and then returns a reference to it.  It is the caller's responsibility to actually
+    /// Record a finished transaction, as part of the current state if it
+    /// belongs with it or else as a new state.
+    fn commit(&mut self, entry: LogEntry) {
+        let now = Instant::now();
+        let current = &self.states[self.current];
+        // a state which has been undone to keeps its edits, they are in its children
+        let extendable = self.current > 0 && current.redo_child.is_none();
+        let merge = extendable && (self.in_group || (self.mergeable
+            && self.last_commit.is_some_and(|last| now.duration_since(last) < IDLE_TIMEOUT)
+            && self.grouping.merges(&current.entry, &entry)));
+
+        if merge {
+            let current = &mut self.states[self.current].entry;
+            for change in entry.changes {
+                current.push(change);
+            }
+            current.end_point = entry.end_point;
+        } else {
+            let state = self.states.len();
+            self.states.push(State {
+                entry,
+                parent: self.current,
+                redo_child: None,
+                time: SystemTime::now(),
+            });
+            self.states[self.current].redo_child = Some(state);
+            self.current = state;
+        }
+        self.in_group = self.depth > 0;
+        self.mergeable = true;
+        self.last_commit = Some(now);
+    }
+
+    /// Move to the parent of the current state, returning the changes which
+    /// take the text there.  It is the caller's responsibility to actually
     /// perform the change.
-    pub fn redo(&mut self) -> Option<&LogEntry> {
-        match self.redo.pop() {
-            Some(change) => {
-                let last = self.undo.len();
-                self.undo.push(change.reverse());
-                Some(&self.undo[last])
-            },
-            None => None
+    pub fn undo(&mut self) -> Option<LogEntry> {
+        self.in_group = false;
+        self.mergeable = false;
+        if self.current == 0 { return None }
+        let state = self.current;
+        let parent = self.states[state].parent;
+        self.states[parent].redo_child = Some(state);
+        self.current = parent;
+        Some(self.states[state].entry.clone().reverse())
+    }
+
+    /// Move to the most recently visited child of the current state,
+    /// returning the changes which take the text there.  It is the caller's
+    /// responsibility to actually perform the change.
+    pub fn redo(&mut self) -> Option<LogEntry> {
+        self.in_group = false;
+        self.mergeable = false;
+        let child = self.states[self.current].redo_child?;
+        self.current = child;
+        Some(self.states[child].entry.clone())
+    }
+
+    /// Move to any state, returning the changes which take the text there,
+    /// in the order they are to be made.
+    pub fn goto(&mut self, target: usize) -> Vec<LogEntry> {
+        let mut entries = Vec::new();
+        if target >= self.states.len() { return entries }
+
+        // undo until reaching a state the target descends from
+        let path = self.path_to(target);
+        while !path.contains(&self.current) {
+            entries.extend(self.undo());
         }
+        // then redo down the branch to the target
+        let below = path.iter().position(|&state| state == self.current).unwrap_or(0);
+        for &state in path[..below].iter().rev() {
+            let parent = self.states[state].parent;
+            self.states[parent].redo_child = Some(state);
+            entries.extend(self.redo());
+        }
+        entries
+    }
+
+    /// Move to the state made just before the current one.
+    pub fn older(&mut self) -> Vec<LogEntry> {
+        match self.current {
+            0 => Vec::new(),
+            current => self.goto(current - 1),
+        }
+    }
+
+    /// Move to the state made just after the current one.
+    pub fn newer(&mut self) -> Vec<LogEntry> {
+        self.goto(self.current + 1)
+    }
+
+    /// The state the text is in now, counting from 0 in the order they were made
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// Write out every state, one per line, to be read back by `read_history`.
+    ///
+    /// A state is its parent, the time it was made, the old and new point
+    /// positions, then each change as `+idx:text` or `-idx:text` with the
+    /// text in hex.
+    pub fn write_history(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "{}", self.current)?;
+        for state in &self.states[1..] {
+            let time = state.time.duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+            write!(out, "{} {} {} {}", state.parent, time, state.entry.init_point, state.entry.end_point)?;
+            for change in &state.entry.changes {
+                let sign = if let Change::InsertStr(..) = *change { '+' } else { '-' };
+                write!(out, " {}{}:", sign, change.idx())?;
+                for byte in change.text() {
+                    write!(out, "{:02x}", byte)?;
+                }
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    /// Read back the states written by `write_history`, or None if they
+    /// aren't valid.
+    pub fn read_history(source: &str) -> Option<Log> {
+        let mut lines = source.lines();
+        let current = lines.next()?.parse().ok()?;
+        let mut log = Log::new();
+        for line in lines {
+            let mut fields = line.split(' ');
+            let mut number = || fields.next()?.parse::<u64>().ok();
+            let parent = number()? as usize;
+            let time = UNIX_EPOCH + Duration::from_secs(number()?);
+            let init_point = number()? as usize;
+            let end_point = number()? as usize;
+            let changes = fields.map(|field| {
+                let (idx, hex) = field.get(1..)?.split_once(':')?;
+                if hex.is_empty() || !hex.len().is_multiple_of(2) { return None }
+                let idx = idx.parse().ok()?;
+                let text = (0..hex.len()).step_by(2)
+                    .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+                    .collect::<Option<Vec<u8>>>()?;
+                match field.as_bytes()[0] {
+                    b'+' => Some(Change::InsertStr(idx, text)),
+                    b'-' => Some(Change::RemoveRange(idx, text)),
+                    _ => None,
+                }
+            }).collect::<Option<Vec<_>>>()?;
+
+            let state = log.states.len();
+            if parent >= state || changes.is_empty() { return None }
+            log.states[parent].redo_child = Some(state);
+            log.states.push(State {
+                entry: LogEntry { init_point, end_point, changes },
+                parent,
+                redo_child: None,
+                time,
+            });
+        }
+        if current >= log.states.len() { return None }
+        log.current = current;
+        Some(log)
+    }
+
+    /// The states from `state` up to the first one, `state` included
+    fn path_to(&self, mut state: usize) -> Vec<usize> {
+        let mut path = vec![state];
+        while state != 0 {
+            state = self.states[state].parent;
+            path.push(state);
+        }
+        path
+    }
+
+    /// Describe each state, oldest first, with a drawing of the tree.
+    ///
+    /// Meant to be listed newest at the top: each branch has its own column,
+    /// with `@` marking the current state and `*` the others.
+    pub fn describe_states(&self) -> Vec<String> {
+        // a branch keeps the column of the state it starts from, except for
+        // the newer branches starting there, which get a new column each
+        let mut columns = vec![0; self.states.len()];
+        let mut column_count = 1;
+        for state in 1..self.states.len() {
+            let parent = self.states[state].parent;
+            let first_child = (parent + 1..state).all(|other| self.states[other].parent != parent);
+            columns[state] = if first_child {
+                columns[parent]
+            } else {
+                column_count += 1;
+                column_count - 1
+            };
+        }
+        // the states a column's line runs between
+        let mut spans = vec![(usize::MAX, 0); column_count];
+        for state in 0..self.states.len() {
+            let span = &mut spans[columns[state]];
+            let from = if state == 0 { 0 } else { self.states[state].parent };
+            span.0 = span.0.min(from);
+            span.1 = span.1.max(state);
+        }
+
+        let now = SystemTime::now();
+        (0..self.states.len()).map(|state| {
+            let graph: String = (0..column_count).map(|column| {
+                if column == columns[state] {
+                    if state == self.current { '@' } else { '*' }
+                } else if spans[column].0 < state && state < spans[column].1 {
+                    '│'
+                } else {
+                    ' '
+                }
+            }).collect();
+            let (inserted, removed) = self.states[state].entry.changes.iter()
+                .fold((0, 0), |(inserted, removed), change| match *change {
+                    Change::InsertStr(_, ref text) => (inserted + text.len(), removed),
+                    Change::RemoveRange(_, ref text) => (inserted, removed + text.len()),
+                });
+            let age = now.duration_since(self.states[state].time).unwrap_or_default();
+            if state == 0 {
+                format!("{} {:>4}  original", graph, state)
+            } else {
+                format!("{} {:>4}  +{} -{}  {} ago", graph, state, inserted, removed, format_age(age))
+            }
+        }).collect()
+    }
+}
+
+/// A duration in its largest whole unit, such as `3m`
+fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::time::{Duration, Instant};
+
+    use super::{Change, Grouping, Log};
+
+    fn type_text(log: &mut Log, idx: usize, text: &str) {
+        for (i, byte) in text.bytes().enumerate() {
+            let mut transaction = log.start(idx + i);
+            transaction.log(Change::InsertStr(idx + i, vec![byte]), idx + i);
+        }
+    }
+
+    /// The number of bytes changed by an undo
+    fn undo_len(log: &mut Log) -> Option<usize> {
+        log.undo().map(|entry| entry.changes.iter().map(|change| change.text().len()).sum())
+    }
+
+    #[test]
+    fn test_words() {
+        let mut log = Log::new();
+        type_text(&mut log, 0, "one two");
+        assert_eq!(undo_len(&mut log), Some(3));
+        assert_eq!(undo_len(&mut log), Some(4));
+        assert_eq!(undo_len(&mut log), None);
+
+        // typing elsewhere, after a jump or after a pause starts a new step
+        let mut log = Log::new();
+        type_text(&mut log, 0, "ab");
+        type_text(&mut log, 10, "cd");
+        type_text(&mut log, 12, "ef");
+        log.break_group();
+        type_text(&mut log, 14, "gh");
+        log.last_commit = Some(Instant::now() - Duration::from_secs(60));
+        type_text(&mut log, 16, "ij");
+        assert_eq!(undo_len(&mut log), Some(2));
+        assert_eq!(undo_len(&mut log), Some(2));
+        assert_eq!(undo_len(&mut log), Some(4));
+        assert_eq!(undo_len(&mut log), Some(2));
+    }
+
+    #[test]
+    fn test_session_and_explicit_groups() {
+        let mut log = Log::new();
+        log.set_grouping(Grouping::Session);
+        type_text(&mut log, 0, "one two");
+        {
+            let mut transaction = log.start(6);
+            transaction.log(Change::RemoveRange(6, b"o".to_vec()), 6);
+        }
+        assert_eq!(undo_len(&mut log), Some(8));
+
+        let mut log = Log::new();
+        {
+            let mut transaction = log.start(0);
+            transaction.log(Change::RemoveRange(0, b"x".to_vec()), 0);
+        }
+        log.begin_group();
+        type_text(&mut log, 0, "a b");
+        log.begin_group();
+        log.break_group();
+        type_text(&mut log, 20, "c");
+        log.end_group();
+        log.end_group();
+        assert_eq!(undo_len(&mut log), Some(4));
+        assert_eq!(undo_len(&mut log), Some(1));
+    }
+
+    #[test]
+    fn test_merge_changes() {
+        let mut log = Log::new();
+        log.set_grouping(Grouping::Session);
+        type_text(&mut log, 3, "abc");
+        {
+            // deleting forwards, then backwards, from the middle of "abc"
+            let mut transaction = log.start(4);
+            transaction.log(Change::RemoveRange(4, b"b".to_vec()), 4);
+            transaction.log(Change::RemoveRange(4, b"c".to_vec()), 4);
+            transaction.log(Change::RemoveRange(3, b"a".to_vec()), 3);
+        }
+        let entry = log.undo().unwrap();
+        assert_eq!(entry.changes.len(), 2);
+        assert!(matches!(entry.changes[0], Change::InsertStr(3, ref text) if text == b"abc"));
+        assert!(matches!(entry.changes[1], Change::RemoveRange(3, ref text) if text == b"abc"));
+    }
+
+    #[test]
+    fn test_undo_tree() {
+        let mut log = Log::new();
+        type_text(&mut log, 0, "a");
+        log.break_group();
+        type_text(&mut log, 1, "b");
+        assert!(log.undo().is_some());
+        // editing after an undo starts a new branch, keeping the old one
+        log.break_group();
+        type_text(&mut log, 1, "c");
+        assert_eq!(log.current(), 3);
+
+        // redo follows the branch most recently on
+        assert!(log.undo().is_some());
+        assert_eq!(log.redo().map(|entry| entry.changes.len()), Some(1));
+        assert_eq!(log.current(), 3);
+
+        // older and newer go by the time states were made, across branches
+        let entries = log.older();
+        assert_eq!(log.current(), 2);
+        assert!(matches!(entries[0].changes[0], Change::RemoveRange(1, ref text) if text == b"c"));
+        assert!(matches!(entries[1].changes[0], Change::InsertStr(1, ref text) if text == b"b"));
+        assert_eq!(log.older().len(), 1);
+        assert_eq!(log.current(), 1);
+        assert_eq!(log.goto(3).len(), 1);
+        assert!(log.newer().is_empty());
+
+        let states = log.describe_states();
+        assert_eq!(states.len(), 4);
+        assert!(states[0].ends_with("original"));
+        assert!(states[1].starts_with("* "));
+        assert!(states[2].starts_with("*│"));
+        assert!(states[3].starts_with(" @"));
     }
 }
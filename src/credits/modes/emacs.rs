@@ -42,6 +42,74 @@ impl EmacsMode {
                 args: None,
             }
         );
+        keymap.bind_keys(
+            &[KeyEvent::Ctrl('x'), KeyEvent::Ctrl('w')],
+            CommandInfo {
+                command_name: String::from("editor::save_buffer_as"),
+                args: None,
+            }
+        );
+
+        keymap.bind_keys(
+            &[KeyEvent::Ctrl('x'), KeyEvent::Char('u')],
+            CommandInfo {
+                command_name: String::from("editor::undo_tree"),
+                args: None,
+            }
+        );
+
+        // Buffers
+        keymap.bind_keys(
+            &[KeyEvent::Ctrl('x'), KeyEvent::Char('b')],
+            CommandInfo {
+                command_name: String::from("editor::list_buffers"),
+                args: None,
+            }
+        );
+        keymap.bind_keys(
+            &[KeyEvent::Ctrl('x'), KeyEvent::Char('k')],
+            CommandInfo {
+                command_name: String::from("editor::close_buffer"),
+                args: None,
+            }
+        );
+        keymap.bind_keys(
+            &[KeyEvent::Ctrl('x'), KeyEvent::Right],
+            CommandInfo {
+                command_name: String::from("editor::next_buffer"),
+                args: None,
+            }
+        );
+        keymap.bind_keys(
+            &[KeyEvent::Ctrl('x'), KeyEvent::Left],
+            CommandInfo {
+                command_name: String::from("editor::prev_buffer"),
+                args: None,
+            }
+        );
+
+        // Search
+        keymap.bind_key(
+            KeyEvent::Ctrl('s'),
+            CommandInfo {
+                command_name: String::from("editor::search"),
+                args: None,
+            }
+        );
+        keymap.bind_key(
+            KeyEvent::Ctrl('r'),
+            CommandInfo {
+                command_name: String::from("editor::find_prev"),
+                args: None,
+            }
+        );
+        keymap.bind_key(
+            KeyEvent::Alt('%'),
+            CommandInfo {
+                command_name: String::from("editor::replace"),
+                args: None,
+            }
+        );
 
         // Cursor movement
         keymap.bind_key(
@@ -126,6 +194,52 @@ impl EmacsMode {
             }
         );
 
+        // Selection
+        keymap.bind_key(
+            KeyEvent::Null, // Ctrl-space
+            CommandInfo {
+                command_name: String::from("buffer::set_selection_mark"),
+                args: None,
+            }
+        );
+        keymap.bind_key(
+            KeyEvent::Ctrl('g'),
+            CommandInfo {
+                command_name: String::from("buffer::clear_selection"),
+                args: None,
+            }
+        );
+
+        // Killing and yanking
+        keymap.bind_key(
+            KeyEvent::Ctrl('w'),
+            CommandInfo {
+                command_name: String::from("buffer::cut"),
+                args: None,
+            }
+        );
+        keymap.bind_key(
+            KeyEvent::Alt('w'),
+            CommandInfo {
+                command_name: String::from("buffer::copy"),
+                args: None,
+            }
+        );
+        keymap.bind_key(
+            KeyEvent::Ctrl('y'),
+            CommandInfo {
+                command_name: String::from("buffer::paste"),
+                args: None,
+            }
+        );
+        keymap.bind_key(
+            KeyEvent::Alt('y'),
+            CommandInfo {
+                command_name: String::from("buffer::yank_pop"),
+                args: None,
+            }
+        );
+
         // Editing
         keymap.bind_key(
             KeyEvent::Char('\t'),
@@ -239,6 +353,10 @@ impl Mode for EmacsMode {
         }
 
     }
+
+    fn keymap_mut(&mut self) -> &mut KeyMap {
+        &mut self.keymap
+    }
 }
 
 impl Default for EmacsMode {
@@ -117,4 +117,8 @@ impl Mode for InsertMode {
             BuilderEvent::Incomplete
         }
     }
+
+    fn keymap_mut(&mut self) -> &mut KeyMap {
+        &mut self.keymap
+    }
 }
@@ -1,20 +1,25 @@
 use crossterm::KeyEvent;
 use crate::command::BuilderEvent;
+use crate::keymap::KeyMap;
 
 pub use self::standard::StandardMode;
 pub use self::normal::NormalMode;
 pub use self::insert::InsertMode;
 pub use self::emacs::EmacsMode;
+pub use self::visual::VisualMode;
 
 mod standard;
 mod normal;
 mod insert;
 mod emacs;
+mod visual;
 
 #[derive(Copy, Clone, Debug)]
 pub enum ModeType {
     Normal,
     Insert,
+    Visual,
+    VisualLine,
 }
 
 /// The concept of Iota's modes are taken from Vi.
@@ -24,4 +29,7 @@ pub enum ModeType {
 pub trait Mode {
     /// Given a Key, return a Command wrapped in a BuilderEvent for the Editor to interpret
     fn handle_key_event(&mut self, key: KeyEvent) -> BuilderEvent;
+
+    /// The KeyMap of this mode, for adding the user's own key bindings
+    fn keymap_mut(&mut self) -> &mut KeyMap;
 }
@@ -1,6 +1,6 @@
 use crossterm::KeyEvent;
 use crate::keymap::{KeyMap, KeyMapState, CommandInfo};
-use crate::command::{BuilderEvent, BuilderArgs };
+use crate::command::{BuilderEvent, BuilderArgs};
 use crate::textobject::{ Offset, Kind, Anchor };
 use crate::buffer::Mark;
 use crate::overlay::OverlayType;
@@ -10,10 +10,18 @@ use super::Mode;
 
 
 /// `NormalMode` mimics Vi's Normal mode.
+///
+/// Operators such as `d` wait for a motion, then act on the text the motion
+/// moves over: `dw` deletes a word, `3dw` or `d3w` three of them. Typing
+/// the operator twice, as in `dd`, acts on whole lines. Operators are keys
+/// bound to `buffer::operator_pending`, so they can be rebound like any other.
 pub struct NormalMode {
     keymap: KeyMap,
     number: Option<i32>,
     reading_number: bool,
+    /// The operator waiting for a motion, the key which started it, and the
+    /// count typed before it
+    pending: Option<(char, KeyEvent, Option<i32>)>,
 }
 
 impl NormalMode {
@@ -24,113 +32,234 @@ impl NormalMode {
             keymap: NormalMode::key_defaults(),
             number: None,
             reading_number: false,
+            pending: None,
         }
     }
 
     /// Creates a `KeyMap` with default `NormalMode` key bindings
     fn key_defaults() -> KeyMap {
         let mut keymap = KeyMap::new();
-        // movement
-        // { keys: 'h', command: 'buffer::move_cursor', args: { direction: backward, kind: char, number: 1 } }
+        bind_motions(&mut keymap);
+
+        // actions
         keymap.bind_key(
-            KeyEvent::Char('h'),
+            KeyEvent::Char('p'),
             CommandInfo {
-                command_name: String::from("buffer::move_cursor"),
-                args: Some(BuilderArgs::new().with_kind(Kind::Char)
-                                             .with_offset(Offset::Backward(1, Mark::Cursor(0))))
+                command_name: String::from("buffer::paste"),
+                args: None,
             }
         );
+
         keymap.bind_key(
-            KeyEvent::Char('j'),
+            KeyEvent::Char('u'),
             CommandInfo {
-                command_name: String::from("buffer::move_cursor"),
-                args: Some(BuilderArgs::new().with_kind(Kind::Line(Anchor::Same))
-                                             .with_offset(Offset::Forward(1, Mark::Cursor(0))))
+                command_name: String::from("editor::undo"),
+                args: None,
             }
         );
         keymap.bind_key(
-            KeyEvent::Char('k'),
+            KeyEvent::Ctrl('r'),
             CommandInfo {
-                command_name: String::from("buffer::move_cursor"),
-                args: Some(BuilderArgs::new().with_kind(Kind::Line(Anchor::Same))
-                                             .with_offset(Offset::Backward(1, Mark::Cursor(0))))
+                command_name: String::from("editor::redo"),
+                args: None,
             }
         );
-        keymap.bind_key(
-            KeyEvent::Char('l'),
+        keymap.bind_keys(
+            &[KeyEvent::Char('g'), KeyEvent::Char('-')],
             CommandInfo {
-                command_name: String::from("buffer::move_cursor"),
-                args: Some(BuilderArgs::new().with_kind(Kind::Char)
-                                             .with_offset(Offset::Forward(1, Mark::Cursor(0))))
+                command_name: String::from("editor::undo_older"),
+                args: None,
             }
         );
-        keymap.bind_key(
-            KeyEvent::Char('w'),
+        keymap.bind_keys(
+            &[KeyEvent::Char('g'), KeyEvent::Char('+')],
             CommandInfo {
-                command_name: String::from("buffer::move_cursor"),
-                args: Some(BuilderArgs::new().with_kind(Kind::Word(Anchor::Start))
-                                             .with_offset(Offset::Forward(1, Mark::Cursor(0))))
+                command_name: String::from("editor::undo_newer"),
+                args: None,
             }
         );
+
         keymap.bind_key(
-            KeyEvent::Char('b'),
+            KeyEvent::Char('i'),
             CommandInfo {
-                command_name: String::from("buffer::move_cursor"),
-                args: Some(BuilderArgs::new().with_kind(Kind::Word(Anchor::Start))
-                                             .with_offset(Offset::Backward(1, Mark::Cursor(0))))
+                command_name: String::from("editor::set_mode"),
+                args: Some(BuilderArgs::new().with_mode(ModeType::Insert)),
             }
         );
         keymap.bind_key(
-            KeyEvent::Char('$'),
+            KeyEvent::Char('v'),
             CommandInfo {
-                command_name: String::from("buffer::move_cursor"),
-                args: Some(BuilderArgs::new().with_kind(Kind::Line(Anchor::End))
-                                             .with_offset(Offset::Forward(0, Mark::Cursor(0))))
+                command_name: String::from("editor::set_mode"),
+                args: Some(BuilderArgs::new().with_mode(ModeType::Visual)),
             }
         );
         keymap.bind_key(
-            KeyEvent::Char('0'),
+            KeyEvent::Char('V'),
             CommandInfo {
-                command_name: String::from("buffer::move_cursor"),
-                args: Some(BuilderArgs::new().with_kind(Kind::Line(Anchor::Start))
-                                             .with_offset(Offset::Backward(0, Mark::Cursor(0))))
+                command_name: String::from("editor::set_mode"),
+                args: Some(BuilderArgs::new().with_mode(ModeType::VisualLine)),
             }
         );
-
-        // actions
         keymap.bind_key(
-            KeyEvent::Char('u'),
+            KeyEvent::Char(':'),
             CommandInfo {
-                command_name: String::from("editor::undo"),
-                args: None,
+                command_name: String::from("editor::set_overlay"),
+                args: Some(BuilderArgs::new().with_overlay(OverlayType::CommandPrompt)),
             }
         );
+
+        // operators, which wait for a motion
+        for &operator in &['d', 'c', 'y', '>', '<'] {
+            keymap.bind_key(
+                KeyEvent::Char(operator),
+                CommandInfo {
+                    command_name: String::from("buffer::operator_pending"),
+                    args: Some(BuilderArgs::new().with_char_arg(operator)),
+                }
+            );
+        }
+
+        // search
         keymap.bind_key(
-            KeyEvent::Ctrl('r'),
+            KeyEvent::Char('/'),
             CommandInfo {
-                command_name: String::from("editor::redo"),
+                command_name: String::from("editor::search_regex"),
                 args: None,
             }
         );
-
         keymap.bind_key(
-            KeyEvent::Char('i'),
+            KeyEvent::Char('n'),
             CommandInfo {
-                command_name: String::from("editor::set_mode"),
-                args: Some(BuilderArgs::new().with_mode(ModeType::Insert)),
+                command_name: String::from("editor::find_next"),
+                args: None,
             }
         );
         keymap.bind_key(
-            KeyEvent::Char(':'),
+            KeyEvent::Char('N'),
             CommandInfo {
-                command_name: String::from("editor::set_overlay"),
-                args: Some(BuilderArgs::new().with_overlay(OverlayType::CommandPrompt)),
+                command_name: String::from("editor::find_prev"),
+                args: None,
             }
         );
 
         keymap
     }
 
+    /// Finish the pending operator with the motion bound to `key`.
+    ///
+    /// The counts typed before the operator and before the motion multiply.
+    fn complete_operator(&mut self, operator: char, start: KeyEvent, count: Option<i32>, key: KeyEvent) -> BuilderEvent {
+        let count = count.unwrap_or(1).saturating_mul(self.number.unwrap_or(1)).max(1);
+        let (kind, offset) = if key == start {
+            // doubled, the operator acts on `count` lines from the cursor
+            (Kind::Line(Anchor::Same), Offset::Forward(count as usize - 1, Mark::Cursor(0)))
+        } else {
+            match self.keymap.check_key(key) {
+                KeyMapState::Match(CommandInfo { ref command_name, args: Some(ref args) })
+                    if command_name == "buffer::move_cursor" => {
+                    match (args.kind, args.offset) {
+                        (Some(kind), Some(offset)) => (kind, scale_offset(offset, count as usize)),
+                        _ => return self.cancel_operator(),
+                    }
+                }
+                KeyMapState::Continue => {
+                    self.pending = Some((operator, start, None));
+                    self.number = Some(count);
+                    return BuilderEvent::Incomplete
+                }
+                _ => return self.cancel_operator(),
+            }
+        };
+
+        self.number = None;
+        BuilderEvent::Complete(CommandInfo {
+            command_name: String::from("buffer::operate"),
+            args: Some(BuilderArgs::new().with_char_arg(operator).with_kind(kind).with_offset(offset)),
+        })
+    }
+
+    fn cancel_operator(&mut self) -> BuilderEvent {
+        self.number = None;
+        BuilderEvent::Invalid
+    }
+
+}
+
+/// Bind the keys which move the cursor in Vi's Normal and Visual modes
+pub(super) fn bind_motions(keymap: &mut KeyMap) {
+    // { keys: 'h', command: 'buffer::move_cursor', args: { direction: backward, kind: char, number: 1 } }
+    keymap.bind_key(
+        KeyEvent::Char('h'),
+        CommandInfo {
+            command_name: String::from("buffer::move_cursor"),
+            args: Some(BuilderArgs::new().with_kind(Kind::Char)
+                                         .with_offset(Offset::Backward(1, Mark::Cursor(0))))
+        }
+    );
+    keymap.bind_key(
+        KeyEvent::Char('j'),
+        CommandInfo {
+            command_name: String::from("buffer::move_cursor"),
+            args: Some(BuilderArgs::new().with_kind(Kind::Line(Anchor::Same))
+                                         .with_offset(Offset::Forward(1, Mark::Cursor(0))))
+        }
+    );
+    keymap.bind_key(
+        KeyEvent::Char('k'),
+        CommandInfo {
+            command_name: String::from("buffer::move_cursor"),
+            args: Some(BuilderArgs::new().with_kind(Kind::Line(Anchor::Same))
+                                         .with_offset(Offset::Backward(1, Mark::Cursor(0))))
+        }
+    );
+    keymap.bind_key(
+        KeyEvent::Char('l'),
+        CommandInfo {
+            command_name: String::from("buffer::move_cursor"),
+            args: Some(BuilderArgs::new().with_kind(Kind::Char)
+                                         .with_offset(Offset::Forward(1, Mark::Cursor(0))))
+        }
+    );
+    keymap.bind_key(
+        KeyEvent::Char('w'),
+        CommandInfo {
+            command_name: String::from("buffer::move_cursor"),
+            args: Some(BuilderArgs::new().with_kind(Kind::Word(Anchor::Start))
+                                         .with_offset(Offset::Forward(1, Mark::Cursor(0))))
+        }
+    );
+    keymap.bind_key(
+        KeyEvent::Char('b'),
+        CommandInfo {
+            command_name: String::from("buffer::move_cursor"),
+            args: Some(BuilderArgs::new().with_kind(Kind::Word(Anchor::Start))
+                                         .with_offset(Offset::Backward(1, Mark::Cursor(0))))
+        }
+    );
+    keymap.bind_key(
+        KeyEvent::Char('$'),
+        CommandInfo {
+            command_name: String::from("buffer::move_cursor"),
+            args: Some(BuilderArgs::new().with_kind(Kind::Line(Anchor::End))
+                                         .with_offset(Offset::Forward(0, Mark::Cursor(0))))
+        }
+    );
+    keymap.bind_key(
+        KeyEvent::Char('0'),
+        CommandInfo {
+            command_name: String::from("buffer::move_cursor"),
+            args: Some(BuilderArgs::new().with_kind(Kind::Line(Anchor::Start))
+                                         .with_offset(Offset::Backward(0, Mark::Cursor(0))))
+        }
+    );
+}
+
+/// Move `count` times as far as `offset` does
+fn scale_offset(offset: Offset, count: usize) -> Offset {
+    match offset {
+        Offset::Forward(n, _) | Offset::Backward(n, _) => offset.with_num(n.saturating_mul(count)),
+        Offset::Absolute(_) => offset,
+    }
 }
 
 impl Mode for NormalMode {
@@ -141,7 +270,7 @@ impl Mode for NormalMode {
                 let n = c.to_digit(10).unwrap() as i32;
                 self.reading_number = true;
                 if let Some(current) = self.number {
-                    self.number = Some((current*10) + n);
+                    self.number = Some(current.saturating_mul(10).saturating_add(n));
                 } else {
                     self.number = Some(n);
                 }
@@ -150,7 +279,20 @@ impl Mode for NormalMode {
                 self.reading_number = false;
             }
         }
+        if let Some((operator, start, count)) = self.pending.take() {
+            return self.complete_operator(operator, start, count, key)
+        }
         match self.keymap.check_key(key) {
+            KeyMapState::Match(c) if c.command_name == "buffer::operator_pending" => {
+                let operator = c.args.and_then(|args| args.char_args);
+                match operator {
+                    Some(operator) => {
+                        self.pending = Some((operator, key, self.number.take()));
+                        BuilderEvent::Incomplete
+                    }
+                    None => self.cancel_operator(),
+                }
+            }
             KeyMapState::Match(mut c) => {
                 if let Some(num) = self.number {
                     if let Some(args) = c.args {
@@ -165,6 +307,10 @@ impl Mode for NormalMode {
             }
         }
     }
+
+    fn keymap_mut(&mut self) -> &mut KeyMap {
+        &mut self.keymap
+    }
 }
 
 impl Default for NormalMode {
@@ -172,3 +318,123 @@ impl Default for NormalMode {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use crossterm::KeyEvent;
+
+    use crate::command::{BuilderArgs, BuilderEvent};
+    use crate::keymap::CommandInfo;
+    use crate::modes::Mode;
+    use crate::textobject::{Kind, Offset};
+
+    use super::NormalMode;
+
+    /// Type the keys, returning the command and its args once one is complete
+    fn type_keys(mode: &mut NormalMode, keys: &str) -> Option<(String, Option<char>, Kind, Offset)> {
+        for c in keys.chars() {
+            if let BuilderEvent::Complete(info) = mode.handle_key_event(KeyEvent::Char(c)) {
+                let args = info.args.unwrap();
+                return Some((info.command_name, args.char_args, args.kind.unwrap(), args.offset.unwrap()))
+            }
+        }
+        None
+    }
+
+    fn offset_num(offset: Offset) -> usize {
+        match offset {
+            Offset::Forward(n, _) | Offset::Backward(n, _) | Offset::Absolute(n) => n,
+        }
+    }
+
+    #[test]
+    fn test_operator_and_motion() {
+        let mut mode = NormalMode::new();
+        let (name, operator, kind, offset) = type_keys(&mut mode, "dw").unwrap();
+        assert_eq!(name, "buffer::operate");
+        assert_eq!(operator, Some('d'));
+        assert!(matches!(kind, Kind::Word(_)));
+        assert_eq!(offset_num(offset), 1);
+
+        // counts before the operator and the motion multiply
+        let (_, operator, _, offset) = type_keys(&mut mode, "3c2w").unwrap();
+        assert_eq!(operator, Some('c'));
+        assert_eq!(offset_num(offset), 6);
+        let (_, _, kind, offset) = type_keys(&mut mode, "y2k").unwrap();
+        assert!(matches!(kind, Kind::Line(_)));
+        assert!(matches!(offset, Offset::Backward(2, _)));
+
+        // a motion which doesn't move by a number of objects isn't scaled
+        let (_, _, _, offset) = type_keys(&mut mode, "2d$").unwrap();
+        assert_eq!(offset_num(offset), 0);
+    }
+
+    #[test]
+    fn test_doubled_operator() {
+        let mut mode = NormalMode::new();
+        let (_, operator, kind, offset) = type_keys(&mut mode, "yy").unwrap();
+        assert_eq!(operator, Some('y'));
+        assert!(matches!(kind, Kind::Line(_)));
+        assert!(matches!(offset, Offset::Forward(0, _)));
+
+        let (_, operator, _, offset) = type_keys(&mut mode, "3>>").unwrap();
+        assert_eq!(operator, Some('>'));
+        assert!(matches!(offset, Offset::Forward(2, _)));
+    }
+
+    #[test]
+    fn test_cancel_operator() {
+        let mut mode = NormalMode::new();
+        assert!(matches!(mode.handle_key_event(KeyEvent::Char('d')), BuilderEvent::Incomplete));
+        assert!(matches!(mode.handle_key_event(KeyEvent::Esc), BuilderEvent::Invalid));
+
+        // the count went with the cancelled operator
+        let (name, _, _, offset) = type_keys(&mut mode, "j").unwrap();
+        assert_eq!(name, "buffer::move_cursor");
+        assert_eq!(offset_num(offset), 1);
+    }
+
+    #[test]
+    fn test_rebind_operator() {
+        let mut mode = NormalMode::new();
+        mode.keymap.bind_key(KeyEvent::Char('d'), CommandInfo {
+            command_name: String::from("buffer::paste"),
+            args: None,
+        });
+        mode.keymap.bind_key(KeyEvent::Char('q'), CommandInfo {
+            command_name: String::from("buffer::operator_pending"),
+            args: Some(BuilderArgs::new().with_char_arg('d')),
+        });
+        match mode.handle_key_event(KeyEvent::Char('d')) {
+            BuilderEvent::Complete(info) => assert_eq!(info.command_name, "buffer::paste"),
+            _ => panic!("d should be bound to paste"),
+        }
+
+        // the operator is doubled with the key which started it
+        let (name, operator, kind, _) = type_keys(&mut mode, "qq").unwrap();
+        assert_eq!(name, "buffer::operate");
+        assert_eq!(operator, Some('d'));
+        assert!(matches!(kind, Kind::Line(_)));
+    }
+
+    #[test]
+    fn test_operator_key_inside_sequence() {
+        let mut mode = NormalMode::new();
+        assert!(matches!(mode.handle_key_event(KeyEvent::Char('g')), BuilderEvent::Incomplete));
+        mode.handle_key_event(KeyEvent::Char('d'));
+
+        // the d ended the g sequence instead of waiting for a motion
+        let (name, _, _, _) = type_keys(&mut mode, "w").unwrap();
+        assert_eq!(name, "buffer::move_cursor");
+    }
+
+    #[test]
+    fn test_large_counts_saturate() {
+        let mut mode = NormalMode::new();
+        let (_, _, _, offset) = type_keys(&mut mode, "100000d100000j").unwrap();
+        assert_eq!(offset_num(offset), i32::MAX as usize);
+        let (_, _, _, offset) = type_keys(&mut mode, "99999999999dd").unwrap();
+        assert_eq!(offset_num(offset), i32::MAX as usize - 1);
+    }
+}
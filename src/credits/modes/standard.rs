@@ -50,6 +50,80 @@ impl StandardMode {
                 args: None,
             }
         );
+        keymap.bind_key(
+            KeyEvent::Alt('s'),
+            CommandInfo {
+                command_name: String::from("editor::save_buffer_as"),
+                args: None,
+            }
+        );
+        keymap.bind_key(
+            KeyEvent::Ctrl('o'),
+            CommandInfo {
+                command_name: String::from("editor::find_file"),
+                args: None,
+            }
+        );
+
+        // Buffers
+        keymap.bind_key(
+            KeyEvent::Alt('n'),
+            CommandInfo {
+                command_name: String::from("editor::next_buffer"),
+                args: None,
+            }
+        );
+        keymap.bind_key(
+            KeyEvent::Alt('p'),
+            CommandInfo {
+                command_name: String::from("editor::prev_buffer"),
+                args: None,
+            }
+        );
+        keymap.bind_key(
+            KeyEvent::Alt('b'),
+            CommandInfo {
+                command_name: String::from("editor::list_buffers"),
+                args: None,
+            }
+        );
+        keymap.bind_key(
+            KeyEvent::Ctrl('w'),
+            CommandInfo {
+                command_name: String::from("editor::close_buffer"),
+                args: None,
+            }
+        );
+
+        // Search
+        keymap.bind_key(
+            KeyEvent::Ctrl('f'),
+            CommandInfo {
+                command_name: String::from("editor::search"),
+                args: None,
+            }
+        );
+        keymap.bind_key(
+            KeyEvent::Ctrl('g'),
+            CommandInfo {
+                command_name: String::from("editor::find_next"),
+                args: None,
+            }
+        );
+        keymap.bind_key(
+            KeyEvent::Alt('g'),
+            CommandInfo {
+                command_name: String::from("editor::find_prev"),
+                args: None,
+            }
+        );
+        keymap.bind_key(
+            KeyEvent::Alt('r'),
+            CommandInfo {
+                command_name: String::from("editor::replace"),
+                args: None,
+            }
+        );
 
         // Cursor movement
         keymap.bind_key(
@@ -119,6 +193,63 @@ impl StandardMode {
             }
         );
 
+        // Selection
+        keymap.bind_key(
+            KeyEvent::ShiftUp,
+            CommandInfo {
+                command_name: String::from("buffer::extend_selection"),
+                args: Some(BuilderArgs::new().with_kind(Kind::Line(Anchor::Same))
+                                             .with_offset(Offset::Backward(1, Mark::Cursor(0))))
+            }
+        );
+        keymap.bind_key(
+            KeyEvent::ShiftDown,
+            CommandInfo {
+                command_name: String::from("buffer::extend_selection"),
+                args: Some(BuilderArgs::new().with_kind(Kind::Line(Anchor::Same))
+                                             .with_offset(Offset::Forward(1, Mark::Cursor(0))))
+            }
+        );
+        keymap.bind_key(
+            KeyEvent::ShiftLeft,
+            CommandInfo {
+                command_name: String::from("buffer::extend_selection"),
+                args: Some(BuilderArgs::new().with_kind(Kind::Char)
+                                             .with_offset(Offset::Backward(1, Mark::Cursor(0))))
+            }
+        );
+        keymap.bind_key(
+            KeyEvent::ShiftRight,
+            CommandInfo {
+                command_name: String::from("buffer::extend_selection"),
+                args: Some(BuilderArgs::new().with_kind(Kind::Char)
+                                             .with_offset(Offset::Forward(1, Mark::Cursor(0))))
+            }
+        );
+
+        // Clipboard
+        keymap.bind_key(
+            KeyEvent::Ctrl('c'),
+            CommandInfo {
+                command_name: String::from("buffer::copy"),
+                args: None,
+            }
+        );
+        keymap.bind_key(
+            KeyEvent::Ctrl('x'),
+            CommandInfo {
+                command_name: String::from("buffer::cut"),
+                args: None,
+            }
+        );
+        keymap.bind_key(
+            KeyEvent::Ctrl('v'),
+            CommandInfo {
+                command_name: String::from("buffer::paste"),
+                args: None,
+            }
+        );
+
         // Editing
         keymap.bind_key(
             KeyEvent::Char('\t'),
@@ -217,6 +348,10 @@ impl Mode for StandardMode {
         }
 
     }
+
+    fn keymap_mut(&mut self) -> &mut KeyMap {
+        &mut self.keymap
+    }
 }
 
 impl Default for StandardMode {
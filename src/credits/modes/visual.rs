@@ -0,0 +1,145 @@
+use crossterm::KeyEvent;
+use crate::keymap::{KeyMap, KeyMapState, CommandInfo};
+use crate::command::{BuilderEvent, BuilderArgs};
+use crate::modes::ModeType;
+
+use super::Mode;
+use super::normal::bind_motions;
+
+
+/// `VisualMode` mimics Vi's Visual and Visual Line modes.
+///
+/// The motions of Normal mode move the cursor and so extend the selection,
+/// and operators act on the selected text before going back to Normal mode.
+pub struct VisualMode {
+    keymap: KeyMap,
+    number: Option<i32>,
+    reading_number: bool,
+}
+
+impl VisualMode {
+
+    /// Create a new instance of `VisualMode`
+    pub fn new() -> VisualMode {
+        VisualMode {
+            keymap: VisualMode::key_defaults(),
+            number: None,
+            reading_number: false,
+        }
+    }
+
+    /// Creates a `KeyMap` with default `VisualMode` key bindings
+    fn key_defaults() -> KeyMap {
+        let mut keymap = KeyMap::new();
+        bind_motions(&mut keymap);
+
+        // operators, without a motion they act on the selection
+        for &(key, operator) in &[('d', 'd'), ('x', 'd'), ('c', 'c'), ('y', 'y'), ('>', '>'), ('<', '<')] {
+            keymap.bind_key(
+                KeyEvent::Char(key),
+                CommandInfo {
+                    command_name: String::from("buffer::operate"),
+                    args: Some(BuilderArgs::new().with_char_arg(operator)),
+                }
+            );
+        }
+
+        // pressing the key of the current visual mode again leaves it
+        keymap.bind_key(
+            KeyEvent::Char('v'),
+            CommandInfo {
+                command_name: String::from("editor::set_mode"),
+                args: Some(BuilderArgs::new().with_mode(ModeType::Visual)),
+            }
+        );
+        keymap.bind_key(
+            KeyEvent::Char('V'),
+            CommandInfo {
+                command_name: String::from("editor::set_mode"),
+                args: Some(BuilderArgs::new().with_mode(ModeType::VisualLine)),
+            }
+        );
+        keymap.bind_key(
+            KeyEvent::Esc,
+            CommandInfo {
+                command_name: String::from("editor::set_mode"),
+                args: Some(BuilderArgs::new().with_mode(ModeType::Normal)),
+            }
+        );
+
+        keymap
+    }
+
+}
+
+impl Mode for VisualMode {
+    fn handle_key_event(&mut self, key: KeyEvent) -> BuilderEvent {
+        if let KeyEvent::Char(c) = key {
+            // '0' might be bound (start of line), and cannot be the start of a number sequence
+            if c.is_ascii_digit() && (self.reading_number || c != '0') {
+                let n = c.to_digit(10).unwrap() as i32;
+                self.reading_number = true;
+                self.number = Some(self.number.unwrap_or(0) * 10 + n);
+                return BuilderEvent::Incomplete;
+            } else if self.reading_number {
+                self.reading_number = false;
+            }
+        }
+        match self.keymap.check_key(key) {
+            KeyMapState::Match(mut c) => {
+                if let Some(num) = self.number.take() {
+                    c.args = c.args.map(|args| args.with_number(num));
+                }
+                BuilderEvent::Complete(c)
+            }
+            _ => {
+                BuilderEvent::Incomplete
+            }
+        }
+    }
+
+    fn keymap_mut(&mut self) -> &mut KeyMap {
+        &mut self.keymap
+    }
+}
+
+impl Default for VisualMode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crossterm::KeyEvent;
+
+    use crate::command::BuilderEvent;
+    use crate::modes::Mode;
+
+    use super::VisualMode;
+
+    #[test]
+    fn test_motions_and_operators() {
+        let mut mode = VisualMode::new();
+        assert!(matches!(mode.handle_key_event(KeyEvent::Char('2')), BuilderEvent::Incomplete));
+        match mode.handle_key_event(KeyEvent::Char('j')) {
+            BuilderEvent::Complete(info) => {
+                assert_eq!(info.command_name, "buffer::move_cursor");
+                assert_eq!(info.args.unwrap().number, Some(2));
+            }
+            _ => panic!("j should move the cursor"),
+        }
+
+        // operators take no motion, they act on the selection
+        match mode.handle_key_event(KeyEvent::Char('x')) {
+            BuilderEvent::Complete(info) => {
+                let args = info.args.unwrap();
+                assert_eq!(info.command_name, "buffer::operate");
+                assert_eq!(args.char_args, Some('d'));
+                assert!(args.kind.is_none());
+            }
+            _ => panic!("x should delete the selection"),
+        }
+    }
+}
@@ -1,23 +1,102 @@
 use std::cmp;
+use std::env;
+use std::fs;
 use std::io::prelude::*;
 use std::convert::TryInto;
+use std::path::PathBuf;
 
 use unicode_width::UnicodeWidthStr;
 // use rustbox::{Style, Color, RustBox};
-use crossterm::{KeyEvent, TerminalCursor, Attribute, Color, Colored, Colorize, Crossterm};
+use crossterm::{KeyEvent, TerminalCursor, Attribute, Crossterm};
 
 use crate::editor::ALL_COMMANDS;
-use crate::command::BuilderEvent;
+use crate::command::{BuilderEvent, BuilderArgs};
 use crate::keymap::CommandInfo;
+use crate::theme::Theme;
 
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum OverlayType {
     CommandPrompt,
+    BufferPicker,
+    ThemePicker,
+    FindFile,
+    SaveBufferAs,
+    Search,
+    RegexSearch,
+    Replace,
+    ReplaceAll,
+    UndoTree,
+}
+
+macro_rules! print_char {
+    ($col:expr, $row:expr, $ch:expr) => {
+        let cursor = TerminalCursor::new();
+        cursor.goto($col, $row).unwrap();
+        let stdout = std::io::stdout();
+        let mut handle = stdout.lock();
+        write!(handle, "{}{}", $ch, Attribute::Reset).unwrap();
+        // crossterm::terminal().write($ch).unwrap();
+        // crossterm::terminal().write(Attribute::Reset).unwrap(); // Clear color and style settings
+    };
+}
+
+/// Draw a boxed list of items just above the prompt line at the bottom of the screen.
+///
+/// The first item is drawn at the bottom of the list. `selected_index` counts
+/// from 1, so that 0 means no item is selected.
+fn draw_list(rb: &mut Crossterm, theme: &Theme, items: &[&str], selected_index: usize) {
+    let height = rb.terminal().terminal_size().1 - 1;
+
+    // find the longest item in the list
+    let mut max = 20u16;
+    for item in items {
+        max = cmp::max(max, UnicodeWidthStr::width(*item).try_into().unwrap());
+    }
+
+    // leave room for the box and the prompt line, scrolling to keep the
+    // selected item in view
+    let shown = cmp::min(items.len(), height.saturating_sub(2) as usize);
+    let first = selected_index.saturating_sub(shown);
+    let items = &items[first..first + shown];
+
+    let style = theme.overlay;
+    let selected_style = theme.overlay_selected.over(style);
+
+    let mut index = 1u16;
+    for item in items {
+        print_char!(0, height - index, format!("{}│", style));
+        print_char!(max + 1, height - index, format!("{}│", style));
+
+        // If we're drawing the selected item
+        let item_style = if first + index as usize == selected_index { selected_style } else { style };
+
+        let mut chars = item.chars();
+        for x in 0..max {
+            let ch = chars.next().unwrap_or(' ');
+            print_char!(x + 1, height - index, format!("{}{}", item_style, ch));
+        }
+
+        index += 1;
+    }
+
+    print_char!(0, height - index, format!("{}╭", style));
+    for x in 1..max + 1 {
+        print_char!(x, height - index, format!("{}─", style));
+    }
+    print_char!(max + 1, height - index, format!("{}╮", style));
+}
+
+/// Draw the prompt line at the bottom of the screen, across its whole width.
+fn draw_prompt(rb: &mut Crossterm, theme: &Theme, prefix: &str, data: &str) {
+    let (width, height) = rb.terminal().terminal_size();
+    let used = UnicodeWidthStr::width(prefix) + UnicodeWidthStr::width(data);
+    let padding = (width as usize).saturating_sub(used);
+    print_char!(0, height - 1, format!("{}{}{}{}", theme.overlay, prefix, data, " ".repeat(padding)));
 }
 
 pub trait Overlay {
-    fn draw(&self, rb: &mut Crossterm);
+    fn draw(&self, rb: &mut Crossterm, theme: &Theme);
     fn draw_cursor(&mut self, rb: &mut Crossterm);
     fn handle_key_event(&mut self, key: KeyEvent) -> BuilderEvent;
 }
@@ -53,87 +132,13 @@ impl CommandPrompt {
 
 
 impl Overlay for CommandPrompt {
-    fn draw(&self, rb: &mut Crossterm) {
-        let height = rb.terminal().terminal_size().1 - 1;
-        let offset = self.prefix.len();
-
-        let keys = self.get_filtered_command_names();
-
-        // find the longest command in the resulting list
-        let mut max = 20u16;
-        for k in &keys {
-            max = cmp::max(max, k.len().try_into().unwrap());
-        }
-
-        macro_rules! print_char {
-            ($col:expr, $row:expr, $ch:expr) => {
-                let cursor = TerminalCursor::new();
-                cursor.goto($col, $row).unwrap();
-                let stdout = std::io::stdout();
-                let mut handle = stdout.lock();
-                write!(handle, "{}{}", $ch, Attribute::Reset).unwrap();
-                // crossterm::terminal().write($ch).unwrap();
-                // crossterm::terminal().write(Attribute::Reset).unwrap(); // Clear color and style settings
-            };
-        }
-
+    fn draw(&self, rb: &mut Crossterm, theme: &Theme) {
         // draw the command completion list
-        let mut index = 1u16;
-        for key in &keys {
-            // rb.print_char(0, height - index, Style::empty(), Color::White, Color::Black, '│');
-            // rb.print_char(max + 1, height - index, Style::empty(), Color::White, Color::Black, '│');
-            print_char!(0, height - index, '│');
-            print_char!(max + 1, height - index, '│');
-
-            // let (fg, bg) = if index == self.selected_index.try_into().unwrap() {
-            //     (Color::White, Color::Red)
-            // } else {
-            //     (Color::White, Color::Black)
-            // };
-            let selected = index as usize == self.selected_index; // If we're drawing the selected item
-
-            let mut chars = key.chars();
-            for x in 0..max {
-                if let Some(ch) = chars.next() {
-                    // rb.print_char(x + 1, height - index, Style::empty(), fg, bg, ch);
-                    if selected {
-                        print_char!(x + 1, height - index, format!("{}{}", Colored::Fg(Color::Red), ch));
-                    } else {
-                        print_char!(x + 1, height - index, ch);
-                    }
-                } else {
-                    // rb.print_char(x + 1, height - index, Style::empty(), fg, bg, ' ');
-                    if selected {
-                        print_char!(x + 1, height - index, " ".red());
-                    } else {
-                        print_char!(x + 1, height - index, " ");
-                    }
-                }
-            }
-
-            index += 1;
-        }
-
-        // rb.print_char(0, height - index, Style::empty(), Color::White, Color::Black, '╭');
-        print_char!(0, height - index, '╭');
-        for x in 1..max + 1 {
-            // rb.print_char(x, height - keys.len() - 1, Style::empty(), Color::White, Color::Black, '─');
-            print_char!(x, height - keys.len() as u16 - 1, '─');
-        }
-        // rb.print_char(max + 1, height - index, Style::empty(), Color::White, Color::Black, '╮');
-        print_char!(max + 1, height - index, '╮');
-
-        // draw the given prefix
-        for (index, ch) in self.prefix.chars().enumerate() {
-            // rb.print_char(index, height, Style::empty(), Color::White, Color::Black, ch);
-            print_char!(index.try_into().unwrap(), height, ch);
-        }
+        let keys = self.get_filtered_command_names();
+        let keys: Vec<&str> = keys.iter().map(|k| **k).collect();
+        draw_list(rb, theme, &keys, self.selected_index);
 
-        // draw the overlay data
-        for (index, ch) in self.data.chars().enumerate() {
-            // rb.print_char(index + offset, height, Style::empty(), Color::White, Color::Black, ch);
-            print_char!((index + offset) as u16, height, ch);
-        }
+        draw_prompt(rb, theme, &self.prefix, &self.data);
     }
 
     fn draw_cursor(&mut self, rb: &mut Crossterm) {
@@ -157,9 +162,16 @@ impl Overlay for CommandPrompt {
             }
             KeyEvent::Backspace => { self.data.pop(); },
             KeyEvent::Char('\n') => { // Enter
-                let command_info = CommandInfo {
-                    command_name: self.data.clone(),
-                    args: None,
+                // anything which isn't the name of a command is an ex command, such as `wq`
+                let command_info = if self.data.trim().is_empty() {
+                    CommandInfo { command_name: String::from("editor::noop"), args: None }
+                } else if ALL_COMMANDS.contains_key(&*self.data) {
+                    CommandInfo { command_name: self.data.clone(), args: None }
+                } else {
+                    CommandInfo {
+                        command_name: String::from("editor::ex"),
+                        args: Some(BuilderArgs::new().with_str(self.data.clone())),
+                    }
                 };
                 return BuilderEvent::Complete(command_info);
             }
@@ -195,3 +207,633 @@ impl Overlay for CommandPrompt {
         BuilderEvent::Incomplete
     }
 }
+
+/// Lists some names, such as the open buffers or the themes, and picks one.
+///
+/// The command is given both the index and the name of the item picked.
+pub struct Picker {
+    names: Vec<String>,
+    prefix: String,
+    command_name: String,
+    selected_index: usize,
+}
+
+impl Picker {
+    /// `current` is the index of the item in use at the moment, which starts out selected.
+    pub fn new(prefix: &str, command_name: &str, names: Vec<String>, current: usize) -> Picker {
+        Picker {
+            selected_index: current + 1,
+            names,
+            prefix: String::from(prefix),
+            command_name: String::from(command_name),
+        }
+    }
+}
+
+impl Overlay for Picker {
+    fn draw(&self, rb: &mut Crossterm, theme: &Theme) {
+        let names: Vec<&str> = self.names.iter().map(|n| n.as_str()).collect();
+        draw_list(rb, theme, &names, self.selected_index);
+
+        let name = self.names.get(self.selected_index.wrapping_sub(1)).map_or("", |n| n.as_str());
+        draw_prompt(rb, theme, &self.prefix, name);
+    }
+
+    fn draw_cursor(&mut self, rb: &mut Crossterm) {
+        let height = rb.terminal().terminal_size().1 - 1;
+        let prefix_len = UnicodeWidthStr::width(self.prefix.as_str());
+        rb.cursor().goto(prefix_len as u16, height).unwrap();
+    }
+
+    fn handle_key_event(&mut self, key: KeyEvent) -> BuilderEvent {
+        match key {
+            KeyEvent::Esc => {
+                let command_info = CommandInfo {
+                    command_name: String::from("editor::noop"),
+                    args: None,
+                };
+                return BuilderEvent::Complete(command_info);
+            }
+            KeyEvent::Char('\n') if self.selected_index > 0 => { // Enter
+                let index = self.selected_index - 1;
+                let command_info = CommandInfo {
+                    command_name: self.command_name.clone(),
+                    args: Some(BuilderArgs::new().with_number(index as i32).with_str(self.names[index].clone())),
+                };
+                return BuilderEvent::Complete(command_info);
+            }
+            KeyEvent::Up if self.selected_index < self.names.len() => {
+                self.selected_index += 1;
+            }
+            KeyEvent::Down if self.selected_index > 1 => {
+                self.selected_index -= 1;
+            }
+            _ => {}
+        }
+        BuilderEvent::Incomplete
+    }
+}
+
+/// Prompts for the path of a file, for opening or saving.
+///
+/// Entries of the directory being typed are listed as completions, and Tab
+/// completes the path as far as it is unambiguous.
+pub struct FilePrompt {
+    data: String,
+    prefix: String,
+    /// The command which is given the chosen path
+    command_name: String,
+    selected_index: usize,
+    /// The directory part of `data` which `entries` were read from
+    entries_dir: String,
+    /// Names of the entries in `entries_dir`, directories end with a '/'
+    entries: Vec<String>,
+}
+
+impl FilePrompt {
+    /// `path` is what the prompt starts out with, empty for the current directory.
+    pub fn new(prefix: &str, command_name: &str, path: String) -> FilePrompt {
+        let mut prompt = FilePrompt {
+            data: path,
+            prefix: String::from(prefix),
+            command_name: String::from(command_name),
+            selected_index: 0,
+            entries_dir: String::new(),
+            entries: Vec::new(),
+        };
+        prompt.read_entries();
+        prompt
+    }
+
+    /// Split the typed path into its directory and the start of a file name.
+    fn split_data(&self) -> (&str, &str) {
+        match self.data.rfind('/') {
+            Some(idx) => (&self.data[..idx + 1], &self.data[idx + 1..]),
+            None => ("", &self.data),
+        }
+    }
+
+    /// Re-read the directory entries if the typed directory has changed.
+    fn read_entries(&mut self) {
+        let dir = self.split_data().0.to_string();
+        if dir == self.entries_dir && !self.entries.is_empty() { return }
+
+        let path = if dir.is_empty() { PathBuf::from(".") } else { expand_home(&dir) };
+        self.entries = match fs::read_dir(path) {
+            Ok(entries) => {
+                entries.filter_map(|e| e.ok()).map(|e| {
+                    let mut name = e.file_name().to_string_lossy().into_owned();
+                    if e.path().is_dir() { name.push('/'); }
+                    name
+                }).collect()
+            }
+            Err(_) => Vec::new(),
+        };
+        self.entries.sort();
+        self.entries_dir = dir;
+    }
+
+    /// Entries which match the typed file name, in the order they are drawn.
+    fn get_matching_entries(&self) -> Vec<&str> {
+        let name = self.split_data().1;
+        let mut matches: Vec<&str> = self.entries.iter()
+            .map(|e| e.as_str())
+            // only show hidden files when asked for
+            .filter(|e| e.starts_with(name) && (name.starts_with('.') || !e.starts_with('.')))
+            .collect();
+        matches.reverse();
+
+        matches
+    }
+
+    /// Replace the file name part of the typed path.
+    fn complete_to(&mut self, name: &str) {
+        self.data = format!("{}{}", self.split_data().0, name);
+        self.selected_index = 0;
+        self.read_entries();
+    }
+
+    /// Complete the typed path with the selected entry, or as far as all matching entries agree.
+    fn complete(&mut self) {
+        let completion = {
+            let matches = self.get_matching_entries();
+            if self.selected_index > 0 {
+                matches.get(self.selected_index - 1).map(|m| m.to_string())
+            } else if let Some(first) = matches.first() {
+                let common = matches.iter().fold(first.len(), |len, m| {
+                    cmp::min(len, first.bytes().zip(m.bytes()).take_while(|&(a, b)| a == b).count())
+                });
+                first.get(..common).map(|c| c.to_string())
+            } else {
+                None
+            }
+        };
+        if let Some(name) = completion {
+            self.complete_to(&name);
+        }
+    }
+}
+
+/// Expand a leading `~` to the user's home directory.
+fn expand_home(path: &str) -> PathBuf {
+    if path == "~" || path.starts_with("~/") {
+        if let Some(home) = env::var_os("HOME") {
+            return PathBuf::from(home).join(path[1..].trim_start_matches('/'))
+        }
+    }
+    PathBuf::from(path)
+}
+
+impl Overlay for FilePrompt {
+    fn draw(&self, rb: &mut Crossterm, theme: &Theme) {
+        draw_list(rb, theme, &self.get_matching_entries(), self.selected_index);
+        draw_prompt(rb, theme, &self.prefix, &self.data);
+    }
+
+    fn draw_cursor(&mut self, rb: &mut Crossterm) {
+        let height = rb.terminal().terminal_size().1 - 1;
+        let prefix_len = UnicodeWidthStr::width(self.prefix.as_str());
+        let data_len = UnicodeWidthStr::width(self.data.as_str());
+        rb.cursor().goto((prefix_len + data_len) as u16, height).unwrap();
+    }
+
+    fn handle_key_event(&mut self, key: KeyEvent) -> BuilderEvent {
+        match key {
+            KeyEvent::Esc => {
+                let command_info = CommandInfo {
+                    command_name: String::from("editor::noop"),
+                    args: None,
+                };
+                return BuilderEvent::Complete(command_info);
+            }
+            KeyEvent::Backspace => {
+                self.data.pop();
+                self.selected_index = 0;
+                self.read_entries();
+            }
+            KeyEvent::Char('\n') => { // Enter
+                if self.selected_index > 0 {
+                    self.complete();
+                }
+                // keep browsing when a directory was picked
+                if !self.data.is_empty() && !self.data.ends_with('/') {
+                    let path = expand_home(&self.data);
+                    let command_info = CommandInfo {
+                        command_name: self.command_name.clone(),
+                        args: Some(BuilderArgs::new().with_str(path.to_string_lossy().into_owned())),
+                    };
+                    return BuilderEvent::Complete(command_info);
+                }
+            }
+            KeyEvent::Up if self.selected_index < self.get_matching_entries().len() => {
+                self.selected_index += 1;
+            }
+            KeyEvent::Down if self.selected_index > 0 => {
+                self.selected_index -= 1;
+            }
+            KeyEvent::Char('\t') => self.complete(), // Tab
+            KeyEvent::Char(c) => {
+                self.data.push(c);
+                self.selected_index = 0;
+                self.read_entries();
+            }
+            _ => {}
+        }
+        BuilderEvent::Incomplete
+    }
+}
+
+/// Prompts for a search pattern, searching as it is typed.
+///
+/// The pattern is taken literally unless regex search is turned on, which
+/// Ctrl-t toggles.
+pub struct SearchPrompt {
+    data: String,
+    regex: bool,
+}
+
+impl SearchPrompt {
+    pub fn new(regex: bool) -> SearchPrompt {
+        SearchPrompt {
+            data: String::new(),
+            regex,
+        }
+    }
+
+    fn prefix(&self) -> &'static str {
+        if self.regex { "regex search: " } else { "search: " }
+    }
+
+    /// Search for what has been typed so far.
+    fn search(&self) -> BuilderEvent {
+        let pattern = if self.regex { self.data.clone() } else { regex::quote(&self.data) };
+        BuilderEvent::Preview(CommandInfo {
+            command_name: String::from("editor::search"),
+            args: Some(BuilderArgs::new().with_str(pattern)),
+        })
+    }
+}
+
+impl Overlay for SearchPrompt {
+    fn draw(&self, rb: &mut Crossterm, theme: &Theme) {
+        draw_prompt(rb, theme, self.prefix(), &self.data);
+    }
+
+    fn draw_cursor(&mut self, rb: &mut Crossterm) {
+        let height = rb.terminal().terminal_size().1 - 1;
+        let prefix_len = UnicodeWidthStr::width(self.prefix());
+        let data_len = UnicodeWidthStr::width(self.data.as_str());
+        rb.cursor().goto((prefix_len + data_len) as u16, height).unwrap();
+    }
+
+    fn handle_key_event(&mut self, key: KeyEvent) -> BuilderEvent {
+        let command_name = match key {
+            KeyEvent::Esc => "editor::cancel_search",
+            KeyEvent::Char('\n') => "editor::end_search", // Enter
+            KeyEvent::Down | KeyEvent::Ctrl('n') | KeyEvent::Ctrl('s') |
+            KeyEvent::Ctrl('f') | KeyEvent::Ctrl('g') => {
+                return BuilderEvent::Preview(CommandInfo {
+                    command_name: String::from("editor::find_next"),
+                    args: None,
+                })
+            }
+            KeyEvent::Up | KeyEvent::Ctrl('p') | KeyEvent::Ctrl('r') => {
+                return BuilderEvent::Preview(CommandInfo {
+                    command_name: String::from("editor::find_prev"),
+                    args: None,
+                })
+            }
+            KeyEvent::Ctrl('t') => {
+                self.regex = !self.regex;
+                return self.search()
+            }
+            KeyEvent::Backspace => {
+                self.data.pop();
+                return self.search()
+            }
+            KeyEvent::Char(c) => {
+                self.data.push(c);
+                return self.search()
+            }
+            _ => return BuilderEvent::Incomplete,
+        };
+        BuilderEvent::Complete(CommandInfo {
+            command_name: String::from(command_name),
+            args: None,
+        })
+    }
+}
+
+/// Prompts for a pattern, and then for the text to replace its matches with.
+///
+/// Like the search prompt, the pattern is taken literally unless regex
+/// replacement is turned on with Ctrl-t.
+pub struct ReplacePrompt {
+    pattern: String,
+    replacement: Option<String>,
+    regex: bool,
+    /// The command which is given the pattern and replacement
+    command_name: String,
+}
+
+impl ReplacePrompt {
+    pub fn new(command_name: &str) -> ReplacePrompt {
+        ReplacePrompt {
+            pattern: String::new(),
+            replacement: None,
+            regex: false,
+            command_name: String::from(command_name),
+        }
+    }
+
+    fn prefix(&self) -> String {
+        match self.replacement {
+            Some(_) => format!("replace {} with: ", self.pattern),
+            None if self.regex => String::from("replace regex: "),
+            None => String::from("replace: "),
+        }
+    }
+
+    /// The text being typed at the moment
+    fn data(&mut self) -> &mut String {
+        match self.replacement {
+            Some(ref mut replacement) => replacement,
+            None => &mut self.pattern,
+        }
+    }
+}
+
+impl Overlay for ReplacePrompt {
+    fn draw(&self, rb: &mut Crossterm, theme: &Theme) {
+        let data = self.replacement.as_ref().unwrap_or(&self.pattern);
+        draw_prompt(rb, theme, &self.prefix(), data);
+    }
+
+    fn draw_cursor(&mut self, rb: &mut Crossterm) {
+        let height = rb.terminal().terminal_size().1 - 1;
+        let prefix_len = UnicodeWidthStr::width(self.prefix().as_str());
+        let data_len = UnicodeWidthStr::width(self.data().as_str());
+        rb.cursor().goto((prefix_len + data_len) as u16, height).unwrap();
+    }
+
+    fn handle_key_event(&mut self, key: KeyEvent) -> BuilderEvent {
+        match key {
+            KeyEvent::Esc => {
+                let command_info = CommandInfo {
+                    command_name: String::from("editor::noop"),
+                    args: None,
+                };
+                return BuilderEvent::Complete(command_info);
+            }
+            KeyEvent::Char('\n') => { // Enter
+                match self.replacement {
+                    None if !self.pattern.is_empty() => self.replacement = Some(String::new()),
+                    None => {}
+                    Some(ref replacement) => {
+                        // without regex, neither the pattern nor `$` in the replacement are special
+                        let (pattern, replacement) = if self.regex {
+                            (self.pattern.clone(), replacement.clone())
+                        } else {
+                            (regex::quote(&self.pattern), replacement.replace('$', "$$"))
+                        };
+                        let command_info = CommandInfo {
+                            command_name: self.command_name.clone(),
+                            args: Some(BuilderArgs::new().with_str(pattern).with_replacement(replacement)),
+                        };
+                        return BuilderEvent::Complete(command_info);
+                    }
+                }
+            }
+            KeyEvent::Ctrl('t') if self.replacement.is_none() => { self.regex = !self.regex }
+            KeyEvent::Backspace => { self.data().pop(); }
+            KeyEvent::Char(c) => { self.data().push(c) }
+            _ => {}
+        }
+        BuilderEvent::Incomplete
+    }
+}
+
+/// Asks whether to replace each match in turn.
+pub struct ReplaceQuery {
+    prefix: String,
+}
+
+impl ReplaceQuery {
+    /// `replacement` is what the matches are replaced with, shown in the prompt.
+    pub fn new(replacement: &str) -> ReplaceQuery {
+        ReplaceQuery {
+            prefix: format!("replace with {}? (y/n/a/q) ", replacement),
+        }
+    }
+}
+
+impl Overlay for ReplaceQuery {
+    fn draw(&self, rb: &mut Crossterm, theme: &Theme) {
+        draw_prompt(rb, theme, &self.prefix, "");
+    }
+
+    fn draw_cursor(&mut self, rb: &mut Crossterm) {
+        let height = rb.terminal().terminal_size().1 - 1;
+        let prefix_len = UnicodeWidthStr::width(self.prefix.as_str());
+        rb.cursor().goto(prefix_len as u16, height).unwrap();
+    }
+
+    fn handle_key_event(&mut self, key: KeyEvent) -> BuilderEvent {
+        let answer = match key {
+            KeyEvent::Char(c @ 'y') | KeyEvent::Char(c @ 'n') |
+            KeyEvent::Char(c @ 'a') | KeyEvent::Char(c @ 'q') => c,
+            KeyEvent::Esc => 'q',
+            _ => return BuilderEvent::Incomplete,
+        };
+        // the editor closes this once there is nothing left to ask about
+        BuilderEvent::Preview(CommandInfo {
+            command_name: String::from("editor::answer_replace"),
+            args: Some(BuilderArgs::new().with_char_arg(answer)),
+        })
+    }
+}
+
+/// Asks a yes or no question, running a command if the answer is yes.
+pub struct ConfirmPrompt {
+    prefix: String,
+    command: CommandInfo,
+}
+
+impl ConfirmPrompt {
+    /// `question` is shown in the prompt, followed by `(y/n)`.
+    pub fn new(question: &str, command: CommandInfo) -> ConfirmPrompt {
+        ConfirmPrompt {
+            prefix: format!("{} (y/n) ", question),
+            command,
+        }
+    }
+}
+
+impl Overlay for ConfirmPrompt {
+    fn draw(&self, rb: &mut Crossterm, theme: &Theme) {
+        draw_prompt(rb, theme, &self.prefix, "");
+    }
+
+    fn draw_cursor(&mut self, rb: &mut Crossterm) {
+        let height = rb.terminal().terminal_size().1 - 1;
+        let prefix_len = UnicodeWidthStr::width(self.prefix.as_str());
+        rb.cursor().goto(prefix_len as u16, height).unwrap();
+    }
+
+    fn handle_key_event(&mut self, key: KeyEvent) -> BuilderEvent {
+        match key {
+            KeyEvent::Char('y') => BuilderEvent::Complete(self.command.clone()),
+            KeyEvent::Char('n') | KeyEvent::Esc => BuilderEvent::Complete(CommandInfo {
+                command_name: String::from("editor::noop"),
+                args: None,
+            }),
+            _ => BuilderEvent::Incomplete,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+
+    use crossterm::KeyEvent;
+    use tempdir::TempDir;
+
+    use crate::command::{Action, BuilderEvent, CommandError, Instruction};
+    use crate::editor::build_command;
+    use crate::ex;
+    use crate::keymap::CommandInfo;
+
+    use super::{expand_home, CommandPrompt, FilePrompt, Overlay};
+
+    /// Type a line into the command prompt and press enter
+    fn enter(line: &str) -> CommandInfo {
+        let mut prompt = CommandPrompt::new();
+        for c in line.chars() {
+            assert!(matches!(prompt.handle_key_event(KeyEvent::Char(c)), BuilderEvent::Incomplete));
+        }
+        match prompt.handle_key_event(KeyEvent::Char('\n')) {
+            BuilderEvent::Complete(info) => info,
+            _ => panic!("enter should finish the prompt"),
+        }
+    }
+
+    #[test]
+    fn test_command_prompt_bad_input() {
+        // a command missing its arguments is an error, not a crash
+        let error = build_command(enter("editor::set_mode")).err();
+        assert_eq!(error, Some(CommandError::MissingArgument("mode")));
+
+        // anything else is an ex command, which reports a typo when it runs
+        let command = build_command(enter("editr::quit")).unwrap();
+        match command.action {
+            Action::Instruction(Instruction::Ex(line)) => {
+                assert_eq!(ex::parse(&line), Err(String::from("Not an editor command: editr::quit")));
+            }
+            action => panic!("expected an ex command, got {:?}", action),
+        }
+
+        assert!(build_command(enter("")).is_ok());
+        let unknown = CommandInfo { command_name: String::from("editor::frobnicate"), args: None };
+        assert_eq!(build_command(unknown).err(), Some(CommandError::Unknown(String::from("editor::frobnicate"))));
+    }
+
+    /// A directory holding `apple1`, `apple2`, `banana`, `.hidden` and `sub/inner`
+    fn file_tree() -> TempDir {
+        let dir = TempDir::new("credits").unwrap();
+        for name in &["apple1", "apple2", "banana", ".hidden"] {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("sub/inner"), "").unwrap();
+        dir
+    }
+
+    fn file_prompt(dir: &TempDir, typed: &str) -> FilePrompt {
+        let mut prompt = FilePrompt::new("open: ", "editor::find_file", format!("{}/", dir.path().display()));
+        for c in typed.chars() {
+            prompt.handle_key_event(KeyEvent::Char(c));
+        }
+        prompt
+    }
+
+    #[test]
+    fn test_file_prompt_split_data() {
+        let mut prompt = FilePrompt::new("open: ", "editor::find_file", String::from("src/credits/lib"));
+        assert_eq!(prompt.split_data(), ("src/credits/", "lib"));
+        prompt.data = String::from("lib");
+        assert_eq!(prompt.split_data(), ("", "lib"));
+        prompt.data = String::from("/");
+        assert_eq!(prompt.split_data(), ("/", ""));
+    }
+
+    #[test]
+    fn test_file_prompt_complete() {
+        let dir = file_tree();
+        let root = format!("{}/", dir.path().display());
+
+        // tab completes as far as the matching names agree
+        let mut prompt = file_prompt(&dir, "a");
+        prompt.handle_key_event(KeyEvent::Char('\t'));
+        assert_eq!(prompt.data, format!("{}apple", root));
+        assert_eq!(prompt.get_matching_entries(), vec!["apple2", "apple1"]);
+
+        // or to the entry picked from the list
+        prompt.handle_key_event(KeyEvent::Up);
+        prompt.handle_key_event(KeyEvent::Char('\t'));
+        assert_eq!(prompt.data, format!("{}apple2", root));
+
+        // and does nothing without a match
+        let mut prompt = file_prompt(&dir, "x");
+        prompt.handle_key_event(KeyEvent::Char('\t'));
+        assert_eq!(prompt.data, format!("{}x", root));
+    }
+
+    #[test]
+    fn test_file_prompt_hidden_files() {
+        let dir = file_tree();
+        let prompt = file_prompt(&dir, "");
+        assert_eq!(prompt.get_matching_entries(), vec!["sub/", "banana", "apple2", "apple1"]);
+        let prompt = file_prompt(&dir, ".");
+        assert_eq!(prompt.get_matching_entries(), vec![".hidden"]);
+    }
+
+    #[test]
+    fn test_file_prompt_reads_directories() {
+        let dir = file_tree();
+        let mut prompt = file_prompt(&dir, "s");
+        prompt.handle_key_event(KeyEvent::Char('\t'));
+        assert_eq!(prompt.data, format!("{}/sub/", dir.path().display()));
+        assert_eq!(prompt.get_matching_entries(), vec!["inner"]);
+
+        // going back up reads the parent again
+        prompt.handle_key_event(KeyEvent::Backspace);
+        assert_eq!(prompt.get_matching_entries(), vec!["sub/"]);
+
+        // entering a directory keeps browsing, entering a file opens it
+        let mut prompt = file_prompt(&dir, "sub/");
+        assert!(matches!(prompt.handle_key_event(KeyEvent::Char('\n')), BuilderEvent::Incomplete));
+        let mut prompt = file_prompt(&dir, "sub/inner");
+        match prompt.handle_key_event(KeyEvent::Char('\n')) {
+            BuilderEvent::Complete(info) => {
+                assert_eq!(info.command_name, "editor::find_file");
+                let path = info.args.and_then(|args| args.str_args);
+                assert_eq!(path, Some(format!("{}/sub/inner", dir.path().display())));
+            }
+            _ => panic!("enter should open the file"),
+        }
+    }
+
+    #[test]
+    fn test_expand_home() {
+        assert_eq!(expand_home("src/~"), PathBuf::from("src/~"));
+        assert_eq!(expand_home("~user/file"), PathBuf::from("~user/file"));
+        if let Some(home) = env::var_os("HOME") {
+            assert_eq!(expand_home("~"), PathBuf::from(&home));
+            assert_eq!(expand_home("~/file"), PathBuf::from(&home).join("file"));
+        }
+    }
+}
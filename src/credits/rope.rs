@@ -0,0 +1,494 @@
+//! A rope of bytes, used to store the text of a `Buffer`.
+//!
+//! The text is kept in small leaves arranged in a height balanced binary tree.
+//! Every branch caches the number of bytes and line breaks beneath it, so
+//! indexing, editing and line lookups all take logarithmic time regardless of
+//! how large the text is.
+
+use std::cmp;
+use std::iter::FromIterator;
+use std::mem;
+use std::ops::Index;
+
+/// Leaves are never grown past this many bytes by an in-place edit.
+const MAX_LEAF: usize = 1024;
+
+enum Node {
+    Leaf(Vec<u8>),
+    Branch(Box<Branch>),
+}
+
+struct Branch {
+    left: Node,
+    right: Node,
+    len: usize,
+    newlines: usize,
+    height: usize,
+}
+
+impl Node {
+    fn empty() -> Node {
+        Node::Leaf(Vec::new())
+    }
+
+    /// Build a balanced tree holding `bytes`.
+    fn from_bytes(bytes: &[u8]) -> Node {
+        if bytes.len() <= MAX_LEAF {
+            return Node::Leaf(bytes.to_vec())
+        }
+        let mid = bytes.len() / 2;
+        Node::branch(Node::from_bytes(&bytes[..mid]), Node::from_bytes(&bytes[mid..]))
+    }
+
+    /// Join two nodes without rebalancing them.
+    fn branch(left: Node, right: Node) -> Node {
+        Node::Branch(Box::new(Branch {
+            len: left.len() + right.len(),
+            newlines: left.newlines() + right.newlines(),
+            height: cmp::max(left.height(), right.height()) + 1,
+            left,
+            right,
+        }))
+    }
+
+    fn len(&self) -> usize {
+        match *self {
+            Node::Leaf(ref bytes) => bytes.len(),
+            Node::Branch(ref branch) => branch.len,
+        }
+    }
+
+    fn newlines(&self) -> usize {
+        match *self {
+            Node::Leaf(ref bytes) => count_newlines(bytes),
+            Node::Branch(ref branch) => branch.newlines,
+        }
+    }
+
+    fn height(&self) -> usize {
+        match *self {
+            Node::Leaf(_) => 0,
+            Node::Branch(ref branch) => branch.height,
+        }
+    }
+
+    fn into_branch(self) -> Branch {
+        match self {
+            Node::Branch(branch) => *branch,
+            Node::Leaf(_) => unreachable!("a leaf has no children"),
+        }
+    }
+
+    fn get(&self, idx: usize) -> &u8 {
+        match *self {
+            Node::Leaf(ref bytes) => &bytes[idx],
+            Node::Branch(ref branch) => {
+                let left_len = branch.left.len();
+                if idx < left_len {
+                    branch.left.get(idx)
+                } else {
+                    branch.right.get(idx - left_len)
+                }
+            }
+        }
+    }
+
+    /// Number of line breaks before `idx`.
+    fn line_of(&self, idx: usize) -> usize {
+        match *self {
+            Node::Leaf(ref bytes) => count_newlines(&bytes[..idx]),
+            Node::Branch(ref branch) => {
+                let left_len = branch.left.len();
+                if idx < left_len {
+                    branch.left.line_of(idx)
+                } else {
+                    branch.left.newlines() + branch.right.line_of(idx - left_len)
+                }
+            }
+        }
+    }
+
+    /// Index of the line break numbered `n`, counting from zero.
+    fn nth_newline(&self, n: usize) -> usize {
+        match *self {
+            Node::Leaf(ref bytes) => {
+                bytes.iter().enumerate()
+                     .filter(|&(_, byte)| *byte == b'\n')
+                     .nth(n)
+                     .map(|(idx, _)| idx)
+                     .unwrap()
+            }
+            Node::Branch(ref branch) => {
+                let left_newlines = branch.left.newlines();
+                if n < left_newlines {
+                    branch.left.nth_newline(n)
+                } else {
+                    branch.left.len() + branch.right.nth_newline(n - left_newlines)
+                }
+            }
+        }
+    }
+
+    fn copy_range(&self, start: usize, end: usize, out: &mut Vec<u8>) {
+        match *self {
+            Node::Leaf(ref bytes) => out.extend_from_slice(&bytes[start..end]),
+            Node::Branch(ref branch) => {
+                let left_len = branch.left.len();
+                if start < left_len {
+                    branch.left.copy_range(start, cmp::min(end, left_len), out);
+                }
+                if end > left_len {
+                    branch.right.copy_range(start.saturating_sub(left_len), end - left_len, out);
+                }
+            }
+        }
+    }
+
+    /// Insert into a leaf in place, if the leaf has room. Returns whether the insert happened.
+    fn insert_in_leaf(&mut self, idx: usize, bytes: &[u8]) -> bool {
+        match *self {
+            Node::Leaf(ref mut leaf) => {
+                if leaf.len() + bytes.len() > MAX_LEAF {
+                    return false
+                }
+                let tail = leaf.split_off(idx);
+                leaf.extend_from_slice(bytes);
+                leaf.extend(tail);
+                true
+            }
+            Node::Branch(ref mut branch) => {
+                let left_len = branch.left.len();
+                let inserted = if idx <= left_len {
+                    branch.left.insert_in_leaf(idx, bytes)
+                } else {
+                    branch.right.insert_in_leaf(idx - left_len, bytes)
+                };
+                if inserted {
+                    branch.len += bytes.len();
+                    branch.newlines += count_newlines(bytes);
+                }
+                inserted
+            }
+        }
+    }
+
+    /// Remove a byte from a leaf in place, if that leaves the leaf non-empty.
+    fn remove_in_leaf(&mut self, idx: usize) -> Option<u8> {
+        match *self {
+            Node::Leaf(ref mut leaf) => {
+                if leaf.len() > 1 { Some(leaf.remove(idx)) } else { None }
+            }
+            Node::Branch(ref mut branch) => {
+                let left_len = branch.left.len();
+                let removed = if idx < left_len {
+                    branch.left.remove_in_leaf(idx)
+                } else {
+                    branch.right.remove_in_leaf(idx - left_len)
+                };
+                if let Some(byte) = removed {
+                    branch.len -= 1;
+                    if byte == b'\n' { branch.newlines -= 1; }
+                }
+                removed
+            }
+        }
+    }
+}
+
+/// Build a branch from two nodes whose heights differ by at most two,
+/// rotating if needed so the result is balanced.
+fn balance(left: Node, right: Node) -> Node {
+    let (left_height, right_height) = (left.height(), right.height());
+
+    if left_height > right_height + 1 {
+        let l = left.into_branch();
+        if l.left.height() >= l.right.height() {
+            Node::branch(l.left, Node::branch(l.right, right))
+        } else {
+            let lr = l.right.into_branch();
+            Node::branch(Node::branch(l.left, lr.left), Node::branch(lr.right, right))
+        }
+    } else if right_height > left_height + 1 {
+        let r = right.into_branch();
+        if r.right.height() >= r.left.height() {
+            Node::branch(Node::branch(left, r.left), r.right)
+        } else {
+            let rl = r.left.into_branch();
+            Node::branch(Node::branch(left, rl.left), Node::branch(rl.right, r.right))
+        }
+    } else {
+        Node::branch(left, right)
+    }
+}
+
+/// Concatenate two balanced trees into one balanced tree.
+fn join(left: Node, right: Node) -> Node {
+    if left.len() == 0 { return right }
+    if right.len() == 0 { return left }
+
+    let (left_height, right_height) = (left.height(), right.height());
+    if left_height > right_height + 1 {
+        let l = left.into_branch();
+        balance(l.left, join(l.right, right))
+    } else if right_height > left_height + 1 {
+        let r = right.into_branch();
+        balance(join(left, r.left), r.right)
+    } else {
+        match (left, right) {
+            (Node::Leaf(mut a), Node::Leaf(b)) => {
+                if a.len() + b.len() <= MAX_LEAF {
+                    a.extend(b);
+                    Node::Leaf(a)
+                } else {
+                    Node::branch(Node::Leaf(a), Node::Leaf(b))
+                }
+            }
+            (left, right) => Node::branch(left, right),
+        }
+    }
+}
+
+/// Split a tree into the bytes before `idx` and the bytes from `idx` on.
+fn split(node: Node, idx: usize) -> (Node, Node) {
+    match node {
+        Node::Leaf(mut bytes) => {
+            let tail = bytes.split_off(idx);
+            (Node::Leaf(bytes), Node::Leaf(tail))
+        }
+        Node::Branch(branch) => {
+            let branch = *branch;
+            let left_len = branch.left.len();
+            if idx < left_len {
+                let (a, b) = split(branch.left, idx);
+                (a, join(b, branch.right))
+            } else if idx > left_len {
+                let (a, b) = split(branch.right, idx - left_len);
+                (join(branch.left, a), b)
+            } else {
+                (branch.left, branch.right)
+            }
+        }
+    }
+}
+
+fn count_newlines(bytes: &[u8]) -> usize {
+    bytes.iter().filter(|byte| **byte == b'\n').count()
+}
+
+/// Text storage with logarithmic time edits and line lookups.
+pub struct Rope {
+    root: Node,
+}
+
+impl Rope {
+    /// Create an empty rope.
+    pub fn new() -> Rope {
+        Rope { root: Node::empty() }
+    }
+
+    /// Number of bytes in the rope.
+    pub fn len(&self) -> usize {
+        self.root.len()
+    }
+
+    /// Whether the rope holds no text.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of line breaks in the rope.
+    pub fn newlines(&self) -> usize {
+        self.root.newlines()
+    }
+
+    /// Insert a byte at `idx`.
+    pub fn insert(&mut self, idx: usize, byte: u8) {
+        self.insert_slice(idx, &[byte]);
+    }
+
+    /// Insert a run of bytes at `idx`.
+    pub fn insert_slice(&mut self, idx: usize, bytes: &[u8]) {
+        assert!(idx <= self.len(), "insert index {} out of range", idx);
+        if bytes.is_empty() || self.root.insert_in_leaf(idx, bytes) {
+            return
+        }
+
+        let root = mem::replace(&mut self.root, Node::empty());
+        let (left, right) = split(root, idx);
+        self.root = join(join(left, Node::from_bytes(bytes)), right);
+    }
+
+    /// Remove the byte at `idx`, returning it. None if `idx` is out of range.
+    pub fn remove(&mut self, idx: usize) -> Option<u8> {
+        if idx >= self.len() {
+            return None
+        }
+        if let Some(byte) = self.root.remove_in_leaf(idx) {
+            return Some(byte)
+        }
+        self.remove_range(idx, idx + 1).pop()
+    }
+
+    /// Remove the bytes from `start` up to `end`, returning them.
+    pub fn remove_range(&mut self, start: usize, end: usize) -> Vec<u8> {
+        let end = cmp::min(end, self.len());
+        if start >= end {
+            return Vec::new()
+        }
+
+        let root = mem::replace(&mut self.root, Node::empty());
+        let (left, rest) = split(root, start);
+        let (middle, right) = split(rest, end - start);
+        self.root = join(left, right);
+
+        let mut removed = Vec::with_capacity(end - start);
+        middle.copy_range(0, middle.len(), &mut removed);
+        removed
+    }
+
+    /// Copy out the bytes from `start` up to `end`.
+    pub fn slice(&self, start: usize, end: usize) -> Vec<u8> {
+        let end = cmp::min(end, self.len());
+        let mut bytes = Vec::with_capacity(end.saturating_sub(start));
+        if start < end {
+            self.root.copy_range(start, end, &mut bytes);
+        }
+        bytes
+    }
+
+    /// The zero-based line number that `idx` is on, ie. the number of line breaks before it.
+    pub fn line_of(&self, idx: usize) -> usize {
+        self.root.line_of(cmp::min(idx, self.len()))
+    }
+
+    /// Index of the line break numbered `n`, counting from zero.
+    pub fn nth_newline(&self, n: usize) -> Option<usize> {
+        if n < self.newlines() { Some(self.root.nth_newline(n)) } else { None }
+    }
+
+    /// Index of the first byte of the zero-based line `line`.
+    pub fn line_start(&self, line: usize) -> Option<usize> {
+        if line == 0 { Some(0) } else { self.nth_newline(line - 1).map(|idx| idx + 1) }
+    }
+
+    /// Index of the first line break at or after `idx`.
+    pub fn next_newline(&self, idx: usize) -> Option<usize> {
+        self.nth_newline(self.line_of(idx))
+    }
+}
+
+impl Default for Rope {
+    fn default() -> Rope {
+        Rope::new()
+    }
+}
+
+impl Index<usize> for Rope {
+    type Output = u8;
+
+    fn index(&self, idx: usize) -> &u8 {
+        assert!(idx < self.len(), "index {} out of range", idx);
+        self.root.get(idx)
+    }
+}
+
+impl<'a> From<&'a [u8]> for Rope {
+    fn from(bytes: &'a [u8]) -> Rope {
+        Rope { root: Node::from_bytes(bytes) }
+    }
+}
+
+impl FromIterator<u8> for Rope {
+    fn from_iter<I: IntoIterator<Item=u8>>(iter: I) -> Rope {
+        let bytes: Vec<u8> = iter.into_iter().collect();
+        Rope::from(&bytes[..])
+    }
+}
+
+impl Extend<u8> for Rope {
+    fn extend<I: IntoIterator<Item=u8>>(&mut self, iter: I) {
+        let bytes: Vec<u8> = iter.into_iter().collect();
+        let len = self.len();
+        self.insert_slice(len, &bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{Rope, MAX_LEAF};
+
+    fn sample_text(lines: usize) -> Vec<u8> {
+        (0..lines).flat_map(|n| format!("line number {}\n", n).into_bytes()).collect()
+    }
+
+    #[test]
+    fn test_insert_and_index() {
+        let mut rope = Rope::new();
+        for (idx, byte) in b"Hello".iter().enumerate() {
+            rope.insert(idx, *byte);
+        }
+        rope.insert(0, b'>');
+
+        assert_eq!(rope.len(), 6);
+        assert_eq!(rope[0], b'>');
+        assert_eq!(rope.slice(0, rope.len()), b">Hello");
+    }
+
+    #[test]
+    fn test_large_text_round_trip() {
+        let text = sample_text(10_000);
+        let rope = Rope::from(&text[..]);
+
+        assert_eq!(rope.len(), text.len());
+        assert_eq!(rope.slice(0, rope.len()), text);
+        assert_eq!(rope[MAX_LEAF * 3 + 7], text[MAX_LEAF * 3 + 7]);
+    }
+
+    #[test]
+    fn test_many_inserts_stay_balanced() {
+        let mut rope = Rope::new();
+        let text = sample_text(2_000);
+        for byte in &text {
+            let len = rope.len();
+            rope.insert(len, *byte);
+        }
+
+        assert_eq!(rope.slice(0, rope.len()), text);
+        assert!(rope.root.height() < 20);
+    }
+
+    #[test]
+    fn test_remove() {
+        let text = sample_text(1_000);
+        let mut rope = Rope::from(&text[..]);
+
+        assert_eq!(rope.remove(0), Some(b'l'));
+        assert_eq!(rope.remove(rope.len()), None);
+        assert_eq!(rope.remove_range(0, 13), b"ine number 0\n");
+        assert_eq!(rope.slice(0, 13), b"line number 1");
+        assert_eq!(rope.len(), text.len() - 14);
+    }
+
+    #[test]
+    fn test_line_lookups() {
+        let text = sample_text(5_000);
+        let mut rope = Rope::from(&text[..]);
+
+        assert_eq!(rope.newlines(), 5_000);
+        assert_eq!(rope.line_of(0), 0);
+        assert_eq!(rope.nth_newline(0), Some(13));
+        assert_eq!(rope.line_start(1), Some(14));
+        assert_eq!(rope.line_of(14), 1);
+        assert_eq!(rope.next_newline(14), Some(27));
+        assert_eq!(rope.nth_newline(5_000), None);
+
+        let start = rope.line_start(4_321).unwrap();
+        assert_eq!(rope.slice(start, start + 16), b"line number 4321");
+        assert_eq!(rope.line_of(start), 4_321);
+
+        rope.insert_slice(start, b"new\nlines\n");
+        assert_eq!(rope.newlines(), 5_002);
+        assert_eq!(rope.line_start(4_323), Some(start + 10));
+    }
+}
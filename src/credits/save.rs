@@ -0,0 +1,171 @@
+//! Saving files without ever leaving a half written file behind.
+//!
+//! The text is written to a temporary file in the same directory as the
+//! file being saved, flushed to disk and then renamed over it. Renaming
+//! within a directory is atomic, so after a crash or a failed write the file
+//! holds either the old text or the new, never a mix.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Links followed before giving up, in case they form a loop
+const MAX_LINKS: usize = 40;
+
+/// Write a file atomically, with `write` giving the contents.
+///
+/// A symlink is written through, replacing the file it points to. An
+/// existing file keeps its permissions, and its owner where the user is
+/// allowed to set it.
+pub fn write_atomically<F>(path: &Path, write: F) -> io::Result<()>
+    where F: FnOnce(&mut dyn Write) -> io::Result<()>
+{
+    let target = resolve_links(path)?;
+    let dir = match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let name = target.file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file name"))?
+        .to_string_lossy()
+        .into_owned();
+
+    let (temp_path, file) = create_temp_file(&dir, &name)?;
+    let result = write_temp_file(file, &target, write).and_then(|_| fs::rename(&temp_path, &target));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+        return result
+    }
+
+    sync_dir(&dir);
+    Ok(())
+}
+
+/// The file a path refers to once any symlinks are followed.
+///
+/// The file doesn't have to exist, a link to a missing file gives the path
+/// the file would be created at.
+fn resolve_links(path: &Path) -> io::Result<PathBuf> {
+    let mut path = path.to_path_buf();
+    for _ in 0..MAX_LINKS {
+        match fs::symlink_metadata(&path) {
+            Ok(ref metadata) if metadata.file_type().is_symlink() => {
+                let link = fs::read_link(&path)?;
+                // relative links are relative to the directory the link is in
+                path = match path.parent() {
+                    Some(dir) => dir.join(link),
+                    None => link,
+                };
+            }
+            _ => return Ok(path),
+        }
+    }
+    Err(io::Error::other("too many levels of symbolic links"))
+}
+
+/// Create a new hidden file in `dir` for saving the file called `name`
+fn create_temp_file(dir: &Path, name: &str) -> io::Result<(PathBuf, File)> {
+    let mut attempt = 0;
+    loop {
+        let path = dir.join(format!(".{}.{}-{}.tmp", name, process::id(), attempt));
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists && attempt < 100 => attempt += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Fill in the temporary file, giving it the target's permissions, and flush it to disk.
+fn write_temp_file<F>(file: File, target: &Path, write: F) -> io::Result<()>
+    where F: FnOnce(&mut dyn Write) -> io::Result<()>
+{
+    let mut out = BufWriter::new(file);
+    write(&mut out)?;
+    let file = out.into_inner().map_err(|e| e.into_error())?;
+
+    if let Ok(metadata) = fs::metadata(target) {
+        file.set_permissions(metadata.permissions())?;
+        preserve_owner(&file, &metadata);
+    }
+    file.sync_all()
+}
+
+#[cfg(unix)]
+fn preserve_owner(file: &File, metadata: &fs::Metadata) {
+    use std::os::unix::fs::{fchown, MetadataExt};
+
+    // only root can give a file away, so for anyone else this is expected to
+    // fail unless they already own it
+    let _ = fchown(file, Some(metadata.uid()), Some(metadata.gid()));
+}
+
+#[cfg(not(unix))]
+fn preserve_owner(_file: &File, _metadata: &fs::Metadata) {}
+
+/// Flush the rename to disk too. Not every platform can open a directory,
+/// so this is best effort.
+fn sync_dir(dir: &Path) {
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs;
+    use std::io;
+
+    use tempdir::TempDir;
+
+    use super::write_atomically;
+
+    #[test]
+    fn test_write_atomically() {
+        let dir = TempDir::new("credits").unwrap();
+        let path = dir.path().join("file.txt");
+        write_atomically(&path, |out| out.write_all(b"first")).unwrap();
+        write_atomically(&path, |out| out.write_all(b"second")).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+
+        // no temporary files are left behind
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_failed_write_keeps_file() {
+        let dir = TempDir::new("credits").unwrap();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "original").unwrap();
+
+        let result = write_atomically(&path, |out| {
+            out.write_all(b"partial")?;
+            Err(io::Error::other("disk full"))
+        });
+        assert!(result.is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"original");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        assert!(write_atomically(&dir.path().join("missing/file.txt"), |_| Ok(())).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks_and_permissions() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let dir = TempDir::new("credits").unwrap();
+        let target = dir.path().join("target.sh");
+        let link = dir.path().join("link.sh");
+        fs::write(&target, "old").unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o750)).unwrap();
+        symlink("target.sh", &link).unwrap();
+
+        write_atomically(&link, |out| out.write_all(b"new")).unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read(&target).unwrap(), b"new");
+        assert_eq!(fs::metadata(&target).unwrap().permissions().mode() & 0o777, 0o750);
+    }
+}
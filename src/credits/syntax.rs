@@ -0,0 +1,462 @@
+//! Syntax highlighting.
+//!
+//! Grammars are written in a simple line based format, with one directive per
+//! line and `#` starting a comment line:
+//!
+//! ```text
+//! name rust
+//! extensions rs
+//! keywords keyword fn let mut
+//! comment //
+//! comment /* */
+//! string " " \
+//! rule number \b[0-9]+\b
+//! ```
+//!
+//! - `name <name>` names the grammar.
+//! - `extensions <ext>...` files with one of these extensions use the grammar.
+//! - `keywords <class> <word>...` whole words which are highlighted as `class`.
+//! - `comment <start> [<end>]` a comment up to `end`, or to the end of the line.
+//! - `string <start> <end> [<escape>]` a string, in which `escape` skips over the next char.
+//! - `region <class> <start> [<end> [<escape>]]` like a string, highlighted as `class`.
+//! - `rule <class> <regex>` text matching the regex, which is the rest of the line.
+//!
+//! Comments, strings and regions with an end may span several lines. At each
+//! point in a line, regions are tried first (longest start first), then
+//! keywords, then rules in the order they are given.
+
+use std::cmp;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+
+use regex::Regex;
+
+/// Every grammar, loaded the first time one is needed
+static GRAMMARS: OnceLock<Vec<Arc<Grammar>>> = OnceLock::new();
+
+/// Grammars which are always available
+static BUILTIN_GRAMMARS: [&str; 3] = [
+    include_str!("../../syntax/rust.syntax"),
+    include_str!("../../syntax/toml.syntax"),
+    include_str!("../../syntax/markdown.syntax"),
+];
+
+/// A part of a line to highlight as `class`, from byte `start` up to `end`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub class: String,
+}
+
+/// Where a line starts: in plain text, or inside one of the grammar's regions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LineState {
+    Normal,
+    InRegion(usize),
+}
+
+/// Text between two delimiters, such as a comment or a string
+struct Region {
+    class: String,
+    start: String,
+    /// None if the region runs to the end of the line
+    end: Option<String>,
+    escape: Option<char>,
+}
+
+/// The rules for highlighting one language.
+pub struct Grammar {
+    pub name: String,
+    extensions: Vec<String>,
+    keywords: HashMap<String, String>,
+    regions: Vec<Region>,
+    rules: Vec<(String, Regex)>,
+}
+
+impl Grammar {
+    /// Parse a grammar, see the module documentation for the format.
+    ///
+    /// The error names the line which could not be parsed.
+    pub fn parse(source: &str) -> Result<Grammar, String> {
+        let mut grammar = Grammar {
+            name: String::new(),
+            extensions: Vec::new(),
+            keywords: HashMap::new(),
+            regions: Vec::new(),
+            rules: Vec::new(),
+        };
+
+        for (number, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') { continue }
+
+            let error = |message: &str| format!("line {}: {}", number + 1, message);
+            let (directive, rest) = split_word(line);
+            let args: Vec<&str> = rest.split_whitespace().collect();
+            match directive {
+                "name" => grammar.name = rest.to_string(),
+                "extensions" => grammar.extensions.extend(args.iter().map(|a| a.to_string())),
+                "keywords" => {
+                    if args.len() < 2 {
+                        return Err(error("expected a class and some keywords"))
+                    }
+                    for word in &args[1..] {
+                        grammar.keywords.insert(word.to_string(), args[0].to_string());
+                    }
+                }
+                "comment" if args.len() == 1 || args.len() == 2 => {
+                    grammar.regions.push(parse_region("comment", &args).ok_or_else(|| error("bad comment"))?);
+                }
+                "string" if args.len() == 2 || args.len() == 3 => {
+                    grammar.regions.push(parse_region("string", &args).ok_or_else(|| error("bad string"))?);
+                }
+                "region" if args.len() >= 2 => {
+                    grammar.regions.push(parse_region(args[0], &args[1..]).ok_or_else(|| error("bad region"))?);
+                }
+                "rule" => {
+                    let (class, pattern) = split_word(rest);
+                    if pattern.is_empty() {
+                        return Err(error("expected a class and a regex"))
+                    }
+                    let regex = Regex::new(pattern).map_err(|e| error(&e.to_string()))?;
+                    grammar.rules.push((class.to_string(), regex));
+                }
+                "comment" | "string" | "region" => return Err(error("wrong number of delimiters")),
+                _ => return Err(error(&format!("unknown directive `{}`", directive))),
+            }
+        }
+
+        if grammar.name.is_empty() {
+            return Err(String::from("the grammar has no name"))
+        }
+        // longer delimiters first, so that ``` is not taken for `
+        grammar.regions.sort_by_key(|r| cmp::Reverse(r.start.len()));
+        Ok(grammar)
+    }
+
+    /// Split a line into highlighted spans, given the state at its start.
+    ///
+    /// Returns the spans, in order, along with the state at the start of the next line.
+    pub fn tokenize_line(&self, line: &str, state: LineState) -> (Vec<Span>, LineState) {
+        let mut spans = Vec::new();
+        let mut pos = 0;
+
+        if let LineState::InRegion(index) = state {
+            let class = &self.regions[index].class;
+            match self.region_end(index, line, 0) {
+                Some(end) => {
+                    spans.push(span(0, end, class));
+                    pos = end;
+                }
+                None => {
+                    if !line.is_empty() {
+                        spans.push(span(0, line.len(), class));
+                    }
+                    return (spans, state)
+                }
+            }
+        }
+
+        // rules are matched against the whole line, so that anchors and word
+        // boundaries see the text around a match
+        let rule_matches: Vec<Vec<(usize, usize)>> = self.rules.iter()
+            .map(|(_, regex)| regex.find_iter(line).filter(|&(s, e)| s != e).collect())
+            .collect();
+
+        while pos < line.len() {
+            let rest = &line[pos..];
+
+            if let Some(index) = self.regions.iter().position(|r| rest.starts_with(&*r.start)) {
+                let region = &self.regions[index];
+                match self.region_end(index, line, pos + region.start.len()) {
+                    Some(end) => {
+                        spans.push(span(pos, end, &region.class));
+                        pos = end;
+                        continue
+                    }
+                    None => {
+                        spans.push(span(pos, line.len(), &region.class));
+                        let next = if region.end.is_some() { LineState::InRegion(index) } else { LineState::Normal };
+                        return (spans, next)
+                    }
+                }
+            }
+
+            let at_word = rest.starts_with(is_word_char) && !line[..pos].ends_with(is_word_char);
+            let word_len = rest.find(|c| !is_word_char(c)).unwrap_or(rest.len());
+            if at_word {
+                if let Some(class) = self.keywords.get(&rest[..word_len]) {
+                    spans.push(span(pos, pos + word_len, class));
+                    pos += word_len;
+                    continue
+                }
+            }
+
+            let rule = rule_matches.iter().enumerate()
+                .filter_map(|(i, matches)| matches.iter().find(|m| m.0 == pos).map(|m| (i, m.1)))
+                .next();
+            if let Some((i, end)) = rule {
+                spans.push(span(pos, end, &self.rules[i].0));
+                pos = end;
+                continue
+            }
+
+            // skip over whole words, so that rules don't match inside of them
+            pos += if at_word { word_len } else { rest.chars().next().map_or(1, char::len_utf8) };
+        }
+
+        (spans, LineState::Normal)
+    }
+
+    /// Index just past the end of the region, searching from `from`.
+    ///
+    /// None if the region doesn't end on this line.
+    fn region_end(&self, index: usize, line: &str, from: usize) -> Option<usize> {
+        let region = &self.regions[index];
+        let end = region.end.as_ref()?;
+
+        let mut chars = line[from..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            if Some(c) == region.escape {
+                chars.next();
+            } else if line[from + i..].starts_with(&**end) {
+                return Some(from + i + end.len())
+            }
+        }
+        None
+    }
+}
+
+/// Highlights the lines of a buffer.
+///
+/// The state at the start of each line is remembered, so that only lines
+/// after a change need to be tokenized again.
+pub struct Highlighter {
+    grammar: Arc<Grammar>,
+    /// State at the start of each line, as far as it is known
+    states: Vec<LineState>,
+}
+
+impl Highlighter {
+    pub fn new(grammar: Arc<Grammar>) -> Highlighter {
+        Highlighter {
+            grammar,
+            states: vec![LineState::Normal],
+        }
+    }
+
+    /// Forget what is known about the lines after `line`, which has changed.
+    pub fn invalidate_from(&mut self, line: usize) {
+        self.states.truncate(line + 1);
+    }
+
+    /// Highlight `count` lines, starting with line number `first`.
+    ///
+    /// `line_text` gives the text of a line, or None past the last line.
+    pub fn highlight<F>(&mut self, first: usize, count: usize, line_text: F) -> Vec<Vec<Span>>
+        where F: Fn(usize) -> Option<String>
+    {
+        let mut highlighted = Vec::new();
+        for line in cmp::min(self.states.len() - 1, first)..first + count {
+            let text = match line_text(line) {
+                Some(text) => text,
+                None => break,
+            };
+            let (spans, next) = self.grammar.tokenize_line(&text, self.states[line]);
+            if self.states.len() == line + 1 {
+                self.states.push(next);
+            }
+            if line >= first {
+                highlighted.push(spans);
+            }
+        }
+        highlighted
+    }
+}
+
+/// The grammar for a file, picked by its extension
+pub fn grammar_for(path: &Path) -> Option<Arc<Grammar>> {
+    let extension = path.extension()?.to_str()?;
+    GRAMMARS.get_or_init(load_grammars).iter().find(|g| g.extensions.iter().any(|e| e == extension)).cloned()
+}
+
+/// Grammars from the user's syntax directory, followed by the built in ones.
+///
+/// Grammar files which can't be read or parsed are skipped.
+fn load_grammars() -> Vec<Arc<Grammar>> {
+    let mut grammars = Vec::new();
+    if let Some(dir) = user_syntax_dir() {
+        if let Ok(entries) = fs::read_dir(dir) {
+            for entry in entries.filter_map(|e| e.ok()) {
+                if entry.path().extension() == Some("syntax".as_ref()) {
+                    let grammar = fs::read_to_string(entry.path()).ok()
+                        .and_then(|source| Grammar::parse(&source).ok());
+                    grammars.extend(grammar.map(Arc::new));
+                }
+            }
+        }
+    }
+    for source in BUILTIN_GRAMMARS.iter() {
+        grammars.push(Arc::new(Grammar::parse(source).expect("invalid built in grammar")));
+    }
+    grammars
+}
+
+/// `~/.config/credits/syntax`, where users can add their own grammars
+fn user_syntax_dir() -> Option<PathBuf> {
+    let home = env::var_os("HOME")?;
+    Some(PathBuf::from(home).join(".config/credits/syntax"))
+}
+
+fn parse_region(class: &str, args: &[&str]) -> Option<Region> {
+    let escape = match args.get(2) {
+        Some(escape) => {
+            let mut chars = escape.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Some(c),
+                _ => return None,
+            }
+        }
+        None => None,
+    };
+    Some(Region {
+        class: class.to_string(),
+        start: args.first()?.to_string(),
+        end: args.get(1).map(|end| end.to_string()),
+        escape,
+    })
+}
+
+/// Split off the first word of a line, and trim the rest.
+fn split_word(line: &str) -> (&str, &str) {
+    match line.find(char::is_whitespace) {
+        Some(idx) => (&line[..idx], line[idx..].trim()),
+        None => (line, ""),
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn span(start: usize, end: usize, class: &str) -> Span {
+    Span { start, end, class: class.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{Grammar, LineState, Span, BUILTIN_GRAMMARS};
+
+    fn spans(grammar: &Grammar, line: &str) -> Vec<(String, String)> {
+        grammar.tokenize_line(line, LineState::Normal).0.into_iter()
+            .map(|Span { start, end, class }| (line[start..end].to_string(), class))
+            .collect()
+    }
+
+    fn pairs(expected: &[(&str, &str)]) -> Vec<(String, String)> {
+        expected.iter().map(|&(text, class)| (text.to_string(), class.to_string())).collect()
+    }
+
+    fn test_grammar() -> Grammar {
+        Grammar::parse("
+            # a small test language
+            name test
+            extensions tst
+            keywords keyword if else
+            comment //
+            comment /* */
+            string \" \" \\
+            rule number \\b[0-9]+\\b
+        ").unwrap()
+    }
+
+    #[test]
+    fn test_keywords_are_whole_words() {
+        let grammar = test_grammar();
+        assert_eq!(spans(&grammar, "if elsewhere else"), pairs(&[("if", "keyword"), ("else", "keyword")]));
+    }
+
+    #[test]
+    fn test_rules_skip_words() {
+        let grammar = test_grammar();
+        assert_eq!(spans(&grammar, "x1 = 12"), pairs(&[("12", "number")]));
+    }
+
+    #[test]
+    fn test_strings_and_escapes() {
+        let grammar = test_grammar();
+        assert_eq!(spans(&grammar, r#"a "if \" 1" 2"#), pairs(&[(r#""if \" 1""#, "string"), ("2", "number")]));
+    }
+
+    #[test]
+    fn test_line_comment() {
+        let grammar = test_grammar();
+        let (spans, state) = grammar.tokenize_line("1 // if", LineState::Normal);
+        assert_eq!(spans.last().unwrap().class, "comment");
+        assert_eq!((spans.last().unwrap().start, spans.last().unwrap().end), (2, 7));
+        assert_eq!(state, LineState::Normal);
+    }
+
+    #[test]
+    fn test_block_comment_spans_lines() {
+        let grammar = test_grammar();
+        let (_, state) = grammar.tokenize_line("1 /* if", LineState::Normal);
+        assert!(state != LineState::Normal);
+
+        let (spans, state) = grammar.tokenize_line("still */ else", state);
+        let text = "still */ else";
+        let found: Vec<(&str, &str)> = spans.iter().map(|s| (&text[s.start..s.end], &*s.class)).collect();
+        assert_eq!(found, vec![("still */", "comment"), ("else", "keyword")]);
+        assert_eq!(state, LineState::Normal);
+    }
+
+    #[test]
+    fn test_longest_delimiter_first() {
+        let grammar = Grammar::parse("
+            name md
+            region code ``` ```
+            region inline ` `
+        ").unwrap();
+        let (_, state) = grammar.tokenize_line("```rust", LineState::Normal);
+        let (spans, _) = grammar.tokenize_line("let `a` = 1;", state);
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].class, "code");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Grammar::parse("extensions x").is_err());
+        assert!(Grammar::parse("name x\nrule number (").is_err());
+        assert!(Grammar::parse("name x\nstring \"").is_err());
+        assert!(Grammar::parse("name x\nfrobnicate").err().unwrap().starts_with("line 2"));
+    }
+
+    #[test]
+    fn test_builtin_grammars_parse() {
+        for source in BUILTIN_GRAMMARS.iter() {
+            assert!(Grammar::parse(source).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_rust_grammar() {
+        let grammar = Grammar::parse(BUILTIN_GRAMMARS[0]).unwrap();
+        assert_eq!(spans(&grammar, "let c = 'a'; // done"),
+                   pairs(&[("let", "keyword"), ("'a'", "string"), ("// done", "comment")]));
+        assert_eq!(spans(&grammar, "fn f<'a>() -> u8 { println!(\"{}\", 1) }"),
+                   pairs(&[("fn", "keyword"), ("'a", "type"), ("u8", "type"), ("println!", "macro"),
+                           ("\"{}\"", "string"), ("1", "number")]));
+    }
+
+    #[test]
+    fn test_markdown_grammar() {
+        let grammar = Grammar::parse(BUILTIN_GRAMMARS[2]).unwrap();
+        assert_eq!(spans(&grammar, "# Title"), pairs(&[("# Title", "heading")]));
+        assert_eq!(spans(&grammar, "not a # heading"), pairs(&[]));
+    }
+}
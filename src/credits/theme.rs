@@ -0,0 +1,297 @@
+//! Colour themes.
+//!
+//! Themes use the same line based format as syntax grammars. Each line gives
+//! the colours of some part of the editor, foreground first, optionally
+//! followed by `reverse` to swap them:
+//!
+//! ```text
+//! name default
+//! status white #0000af
+//! selection reverse
+//! syntax keyword magenta
+//! ```
+//!
+//! The parts are `text`, `status`, `message`, `overlay`, `overlay_selected`,
+//! `selection` and `search`, while `syntax <class>` colours a class of syntax
+//! highlighting. A colour is a `#rrggbb` hex code, a colour name such as
+//! `dark_blue`, a number from the 256 colour palette, or `default` for the
+//! terminal's own colour. Hex codes are drawn as the closest palette colour
+//! unless the terminal supports true colour.
+
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+use crossterm::{Attribute, Color, Colored};
+
+use crate::utils;
+
+/// Themes which are always available
+static BUILTIN_THEMES: [(&str, &str); 2] = [
+    ("default", include_str!("../../themes/default.theme")),
+    ("light", include_str!("../../themes/light.theme")),
+];
+
+/// Colours to draw some text with, the terminal's own colours where None.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    /// Whether the foreground and background are swapped
+    pub reverse: bool,
+}
+
+impl Style {
+    /// This style, with the colours it doesn't set taken from `base`
+    pub fn over(self, base: Style) -> Style {
+        Style {
+            fg: self.fg.or(base.fg),
+            bg: self.bg.or(base.bg),
+            reverse: self.reverse || base.reverse,
+        }
+    }
+}
+
+impl fmt::Display for Style {
+    /// Writes the escape codes which switch to this style
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(fg) = self.fg {
+            write!(f, "{}", Colored::Fg(fg))?;
+        }
+        if let Some(bg) = self.bg {
+            write!(f, "{}", Colored::Bg(bg))?;
+        }
+        if self.reverse {
+            write!(f, "{}", Attribute::Reverse)?;
+        }
+        Ok(())
+    }
+}
+
+/// The colours of every part of the editor.
+pub struct Theme {
+    pub name: String,
+    pub text: Style,
+    pub status: Style,
+    pub message: Style,
+    pub overlay: Style,
+    pub overlay_selected: Style,
+    pub selection: Style,
+    pub search: Style,
+    syntax: HashMap<String, Style>,
+}
+
+impl Theme {
+    /// Parse a theme, see the module documentation for the format.
+    ///
+    /// With `truecolor` false, hex colours are downsampled to the 256 colour
+    /// palette. The error names the line which could not be parsed.
+    pub fn parse(source: &str, truecolor: bool) -> Result<Theme, String> {
+        let mut theme = Theme {
+            name: String::new(),
+            text: Style::default(),
+            status: Style::default(),
+            message: Style::default(),
+            overlay: Style::default(),
+            overlay_selected: Style::default(),
+            selection: Style::default(),
+            search: Style::default(),
+            syntax: HashMap::new(),
+        };
+
+        for (number, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') { continue }
+
+            let error = |message: &str| format!("line {}: {}", number + 1, message);
+            let mut words: Vec<&str> = line.split_whitespace().collect();
+            if words[0] == "name" {
+                theme.name = words[1..].join(" ");
+                continue
+            }
+            let class = if words[0] == "syntax" && words.len() > 1 { Some(words.remove(1)) } else { None };
+            let reverse = words.len() > 1 && words[words.len() - 1] == "reverse";
+            if reverse {
+                words.pop();
+            }
+
+            let style = match words.len() {
+                1 if reverse => Style { reverse, ..Style::default() },
+                2 | 3 => Style {
+                    fg: parse_color(words[1], truecolor).map_err(|e| error(&e))?,
+                    bg: match words.get(2) {
+                        Some(bg) => parse_color(bg, truecolor).map_err(|e| error(&e))?,
+                        None => None,
+                    },
+                    reverse,
+                },
+                _ => return Err(error("expected a foreground and maybe a background colour")),
+            };
+
+            match (words[0], class) {
+                ("syntax", Some(class)) => { theme.syntax.insert(class.to_string(), style); }
+                ("text", None) => theme.text = style,
+                ("status", None) => theme.status = style,
+                ("message", None) => theme.message = style,
+                ("overlay", None) => theme.overlay = style,
+                ("overlay_selected", None) => theme.overlay_selected = style,
+                ("selection", None) => theme.selection = style,
+                ("search", None) => theme.search = style,
+                (part, _) => return Err(error(&format!("unknown part `{}`", part))),
+            }
+        }
+
+        if theme.name.is_empty() {
+            return Err(String::from("the theme has no name"))
+        }
+        Ok(theme)
+    }
+
+    /// The style for a class of syntax highlighting
+    pub fn syntax_style(&self, class: &str) -> Style {
+        self.syntax.get(class).cloned().unwrap_or_default()
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme::parse(BUILTIN_THEMES[0].1, truecolor()).expect("invalid built in theme")
+    }
+}
+
+/// Load the theme with the given name, from the user's theme directory or the built in ones.
+pub fn load(name: &str) -> Result<Theme, String> {
+    let user_theme = user_theme_dir().map(|dir| dir.join(format!("{}.theme", name)));
+    let source = match user_theme {
+        Some(ref path) if path.is_file() => {
+            fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?
+        }
+        _ => {
+            match BUILTIN_THEMES.iter().find(|theme| theme.0 == name) {
+                Some(theme) => theme.1.to_string(),
+                None => return Err(format!("no theme named {}", name)),
+            }
+        }
+    };
+    Theme::parse(&source, truecolor()).map_err(|e| format!("theme {}, {}", name, e))
+}
+
+/// Names of all the themes which can be loaded
+pub fn theme_names() -> Vec<String> {
+    let mut names: Vec<String> = BUILTIN_THEMES.iter().map(|theme| theme.0.to_string()).collect();
+    if let Some(Ok(entries)) = user_theme_dir().map(fs::read_dir) {
+        for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+            if path.extension() == Some("theme".as_ref()) {
+                names.extend(path.file_stem().map(|stem| stem.to_string_lossy().into_owned()));
+            }
+        }
+    }
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// `~/.config/credits/themes`, where users can add their own themes
+fn user_theme_dir() -> Option<PathBuf> {
+    let home = env::var_os("HOME")?;
+    Some(PathBuf::from(home).join(".config/credits/themes"))
+}
+
+/// Whether the terminal says it can draw any RGB colour
+fn truecolor() -> bool {
+    match env::var("COLORTERM") {
+        Ok(colorterm) => colorterm == "truecolor" || colorterm == "24bit",
+        Err(_) => false,
+    }
+}
+
+fn parse_color(color: &str, truecolor: bool) -> Result<Option<Color>, String> {
+    let color = match color {
+        "default" => return Ok(None),
+        "black" => Color::Black,
+        "dark_grey" => Color::DarkGrey,
+        "red" => Color::Red,
+        "dark_red" => Color::DarkRed,
+        "green" => Color::Green,
+        "dark_green" => Color::DarkGreen,
+        "yellow" => Color::Yellow,
+        "dark_yellow" => Color::DarkYellow,
+        "blue" => Color::Blue,
+        "dark_blue" => Color::DarkBlue,
+        "magenta" => Color::Magenta,
+        "dark_magenta" => Color::DarkMagenta,
+        "cyan" => Color::Cyan,
+        "dark_cyan" => Color::DarkCyan,
+        "white" => Color::White,
+        "grey" => Color::Grey,
+        _ if color.starts_with('#') => {
+            let hex = &color[1..];
+            let channel = |i: usize| hex.get(i..i + 2).and_then(|c| u8::from_str_radix(c, 16).ok());
+            match (hex.len(), channel(0), channel(2), channel(4)) {
+                (6, Some(r), Some(g), Some(b)) => {
+                    if truecolor {
+                        Color::Rgb { r, g, b }
+                    } else {
+                        Color::AnsiValue(utils::rgb_to_short(hex) as u8)
+                    }
+                }
+                _ => return Err(format!("bad colour `{}`", color)),
+            }
+        }
+        _ => match color.parse::<u8>() {
+            Ok(value) => Color::AnsiValue(value),
+            Err(_) => return Err(format!("unknown colour `{}`", color)),
+        },
+    };
+    Ok(Some(color))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crossterm::Color;
+
+    use super::{Theme, Style, BUILTIN_THEMES};
+
+    #[test]
+    fn test_parse_theme() {
+        let theme = Theme::parse("
+            name test
+            status white #0000af
+            search black
+            selection reverse
+            syntax keyword 13 default
+        ", true).unwrap();
+
+        assert_eq!(theme.name, "test");
+        assert_eq!(theme.status, Style { fg: Some(Color::White), bg: Some(Color::Rgb { r: 0, g: 0, b: 0xaf }), reverse: false });
+        assert_eq!(theme.search, Style { fg: Some(Color::Black), bg: None, reverse: false });
+        assert_eq!(theme.selection, Style { fg: None, bg: None, reverse: true });
+        assert_eq!(theme.syntax_style("keyword"), Style { fg: Some(Color::AnsiValue(13)), bg: None, reverse: false });
+        assert_eq!(theme.syntax_style("unknown"), Style::default());
+        assert_eq!(theme.text, Style::default());
+    }
+
+    #[test]
+    fn test_downsample() {
+        let theme = Theme::parse("name test\nstatus #0000af #ffffff", false).unwrap();
+        assert_eq!(theme.status, Style { fg: Some(Color::AnsiValue(19)), bg: Some(Color::AnsiValue(231)), reverse: false });
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Theme::parse("status white", true).is_err());
+        assert!(Theme::parse("name x\nstatus #00af", true).is_err());
+        assert!(Theme::parse("name x\nstatus mauve", true).is_err());
+        assert!(Theme::parse("name x\nborder white", true).err().unwrap().starts_with("line 2"));
+    }
+
+    #[test]
+    fn test_builtin_themes_parse() {
+        for &(name, source) in BUILTIN_THEMES.iter() {
+            assert_eq!(Theme::parse(source, false).unwrap().name, name);
+        }
+    }
+}
@@ -1,5 +1,8 @@
 #![allow(dead_code)]
 
+use std::fs;
+use std::path::{Path, PathBuf};
+
 use regex::Regex;
 
 static ANSI_COLORS: [[i32; 3]; 256] = [
@@ -102,3 +105,9 @@ pub fn char_width(c: char, is_cjk: bool, tab_width: usize, position: usize) -> O
 }
 
 
+
+/// The full path of a file, so that however it is named it can be recognised
+/// as the same file. A file which doesn't exist yet keeps the path given.
+pub fn canonical(file: &Path) -> PathBuf {
+    fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf())
+}
@@ -1,24 +1,26 @@
 // For a list of 256 terminal colors: https://jonasjacek.github.io/colors/
 
 use std::cmp;
-use std::borrow::Cow;
-use std::path::Path;
-use std::path::PathBuf;
-use std::io::Write;
-use std::fs::{File, rename};
+use std::path::{Path, PathBuf};
+use std::io::{self, Write};
 use std::sync::{Mutex, Arc};
 use std::time::SystemTime;
 
 // use rustbox::{Color, RustBox, Style as RustBoxStyle};
-use crossterm::{Color, TerminalCursor, Colorize, Colored, Styler, Attribute, Crossterm};
+use crossterm::{TerminalCursor, Attribute, Crossterm};
 
-use tempdir::TempDir;
+use regex::Regex;
 use unicode_width::UnicodeWidthChar;
 
 use crate::buffer::{Buffer, Mark};
-use crate::overlay::{CommandPrompt, Overlay, OverlayType};
-use crate::utils;
+use crate::command::ReplaceAnswer;
+use crate::fileformat::LineEnding;
+use crate::overlay::Overlay;
+use crate::history;
+use crate::save;
 use crate::textobject::{Anchor, TextObject, Kind, Offset};
+use crate::theme::{Style, Theme};
+use crate::utils;
 
 // FIXME: Temporary replacement for the RustBox method `print_char` and this source's reliance on it.
 // Such that: rb.print_char(offset, height + 1, RustBoxStyle::empty(), Color::White, Color::Black, ch);
@@ -56,6 +58,15 @@ pub struct View<'v> {
     /// The current View's cursor - a reference into the Buffer
     cursor: Mark,
 
+    /// The other end of the selection from the cursor, while something is selected
+    selection: Mark,
+
+    /// Whether the selection goes away once the cursor moves without extending it
+    transient_selection: bool,
+
+    /// How much of the text between the selection mark and the cursor is selected
+    selection_kind: SelectionKind,
+
     /// Number of lines from the top/bottom of the View after which vertical
     /// scrolling begins.
     threshold: u16,
@@ -63,6 +74,40 @@ pub struct View<'v> {
     /// Message to be displayed in the status bar along with the time it
     /// was displayed.
     message: Option<(String, SystemTime)>,
+
+    /// Pattern of the last search, matches of which are highlighted
+    search: Option<Regex>,
+
+    /// Where the cursor was when the search prompt was opened
+    search_origin: Option<usize>,
+
+    /// The matches being replaced, while asking about each one
+    replacing: Option<Replacing>,
+
+    /// The colours everything is drawn in
+    pub theme: Theme,
+}
+
+/// How the text between the selection mark and the cursor is selected.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SelectionKind {
+    /// Up to the cursor
+    Plain,
+    /// Up to and including the char under the cursor, like Vi's Visual mode
+    Inclusive,
+    /// Every line from the mark's to the cursor's, like Vi's Visual Line mode
+    Lines,
+}
+
+/// Progress through replacing the matches of a pattern.
+struct Replacing {
+    /// Matches which haven't been asked about, the next one last
+    remaining: Vec<(usize, usize, String)>,
+    /// How many matches have been replaced
+    replaced: usize,
+    /// Where the cursor was when replacing started, moved along by the
+    /// replacements before it
+    origin: usize,
 }
 
 impl<'v> View<'v> {
@@ -84,25 +129,41 @@ impl<'v> View<'v> {
             top_line: top_line,
             left_col: 0,
             cursor: cursor,
+            selection: Mark::Selection(0),
+            transient_selection: false,
+            selection_kind: SelectionKind::Plain,
             overlay: None,
             threshold: 5,
             message: None,
+            search: None,
+            search_origin: None,
+            replacing: None,
+            theme: Theme::default(),
             height: height,
             width: width,
         }
     }
 
+    /// Show a different buffer in this View.
+    ///
+    /// Buffers which have been shown before keep their cursor position.
     pub fn set_buffer(&mut self, buffer: Arc<Mutex<Buffer>>) {
         self.last_buffer = Some(self.buffer.clone());
 
         {
             let mut b = buffer.lock().unwrap();
 
-            b.set_mark(self.cursor, 0);
-            b.set_mark(self.top_line, 0);
+            if b.get_mark_idx(self.cursor).is_none() {
+                b.set_mark(self.cursor, 0);
+            }
+            if b.get_mark_idx(self.top_line).is_none() {
+                b.set_mark(self.top_line, 0);
+            }
         }
 
         self.buffer = buffer;
+        self.left_col = 0;
+        self.maybe_move_screen();
     }
 
     pub fn switch_last_buffer(&mut self) {
@@ -152,17 +213,39 @@ impl<'v> View<'v> {
     pub fn draw(&mut self, rb: &mut Crossterm) {
         self.clear(rb);
         {
-            let buffer = self.buffer.lock().unwrap();
+            let mut buffer = self.buffer.lock().unwrap();
             let height = self.get_height() - 1;
             let width = self.get_width() - 1;
 
+            let mut syntax = buffer.highlight_lines(self.top_line, height as usize).into_iter();
+
             // FIXME: don't use unwrap here
             //        This will fail if for some reason the buffer doesnt have
             //        the top_line mark
             let mut lines = buffer.lines_from(self.top_line).unwrap().take(height as usize);
+            let mut line_start = buffer.get_mark_idx(self.top_line).unwrap_or(0);
+            let selection = self.selection_range(&buffer);
             for y_position in 0..height {
                 let line = lines.next().unwrap_or_else(Vec::new);
-                draw_line(rb, &line, y_position, self.left_col);
+                let line_end = line_start + line.len();
+                let line = String::from_utf8_lossy(&line);
+
+                // search matches are drawn over the syntax highlighting
+                let mut styles: Vec<(usize, usize, Style)> = syntax.next().unwrap_or_default().into_iter()
+                    .map(|span| (span.start, span.end, self.theme.syntax_style(&span.class)))
+                    .collect();
+                if let Some(ref pattern) = self.search {
+                    let search = self.theme.search;
+                    styles.extend(pattern.find_iter(&line).map(|(start, end)| (start, end, search)));
+                }
+                if let Some((start, end)) = selection {
+                    if start < line_end && end > line_start {
+                        let start = start.saturating_sub(line_start);
+                        styles.push((start, end - line_start, self.theme.selection));
+                    }
+                }
+                line_start = line_end;
+                draw_line(rb, &line, &styles, self.theme.text, buffer.tab_width, y_position, self.left_col);
             }
 
         }
@@ -172,7 +255,7 @@ impl<'v> View<'v> {
         match self.overlay {
             None => self.draw_cursor(rb),
             Some(ref mut overlay) => {
-                overlay.draw(rb);
+                overlay.draw(rb, &self.theme);
                 overlay.draw_cursor(rb);
             }
         }
@@ -186,7 +269,7 @@ impl<'v> View<'v> {
         let mut cursor_status = buffer.get_mark_display_coords(self.cursor).unwrap_or((0,0));
         cursor_status = (cursor_status.0 + 1, cursor_status.1 + 1);
 
-        let mut status_text: String = format!("{} [{}]", Colored::Bg(Color::Rgb{r:0,g:0,b:175}), buffer_name);
+        let mut status_text: String = format!("{} [{}]", self.theme.status, buffer_name);
 
         let width = self.get_width();
         let height = self.get_height();
@@ -199,6 +282,11 @@ impl<'v> View<'v> {
         }
 
         status_text.push_str(&format!(" ({}, {})", cursor_status.0, cursor_status.1));
+        if buffer.read_only {
+            status_text.push_str(" binary, read only");
+        } else {
+            status_text.push_str(&format!(" {}", buffer.format));
+        }
 
         let len = status_text.len() as u16;
         for _ in 0..width-len {
@@ -208,7 +296,7 @@ impl<'v> View<'v> {
 
         // For the message at the very bottom of the window
         if let Some((ref message, _time)) = self.message {
-            print_char!(out, 0, height + 1, message);
+            print_char!(out, 0, height + 1, format!("{}{}", self.theme.message, message));
         }
     }
 
@@ -223,12 +311,8 @@ impl<'v> View<'v> {
         }
     }
 
-    pub fn set_overlay(&mut self, overlay_type: OverlayType) {
-        match overlay_type {
-            OverlayType::CommandPrompt => {
-                self.overlay = Some(Box::new(CommandPrompt::new()));
-            }
-        }
+    pub fn set_overlay(&mut self, overlay: Box<dyn Overlay + 'v>) {
+        self.overlay = Some(overlay);
     }
 
     /// Display the given message
@@ -255,10 +339,203 @@ impl<'v> View<'v> {
     }
 
     pub fn move_mark(&mut self, mark: Mark, object: TextObject) {
+        if mark == self.cursor && self.transient_selection {
+            self.clear_selection();
+        }
         self.buffer.lock().unwrap().set_mark_to_object(mark, object);
         self.maybe_move_screen();
     }
 
+    /// Start a selection at the cursor, which stays until it is cleared.
+    pub fn set_selection_mark(&mut self) {
+        {
+            let mut buffer = self.buffer.lock().unwrap();
+            let cursor = buffer.get_mark_idx(self.cursor).unwrap_or(0);
+            buffer.set_mark(self.selection, cursor);
+        }
+        self.transient_selection = false;
+        self.selection_kind = SelectionKind::Plain;
+        self.show_message(String::from("Mark set"));
+    }
+
+    /// Select text from the cursor until the selection is cleared.
+    ///
+    /// While something is already selected, only how it is selected changes.
+    pub fn start_selection(&mut self, kind: SelectionKind) {
+        {
+            let mut buffer = self.buffer.lock().unwrap();
+            if buffer.get_mark_idx(self.selection).is_none() {
+                let cursor = buffer.get_mark_idx(self.cursor).unwrap_or(0);
+                buffer.set_mark(self.selection, cursor);
+            }
+        }
+        self.transient_selection = false;
+        self.selection_kind = kind;
+    }
+
+    /// How text is being selected, None when there is no selection
+    pub fn selection_kind(&self) -> Option<SelectionKind> {
+        self.buffer.lock().unwrap().get_mark_idx(self.selection).map(|_| self.selection_kind)
+    }
+
+    /// Move the cursor, selecting the text it moves over.
+    ///
+    /// A selection started this way goes away when the cursor next moves
+    /// without extending it.
+    pub fn extend_selection(&mut self, object: TextObject) {
+        {
+            let mut buffer = self.buffer.lock().unwrap();
+            if buffer.get_mark_idx(self.selection).is_none() {
+                let cursor = buffer.get_mark_idx(self.cursor).unwrap_or(0);
+                buffer.set_mark(self.selection, cursor);
+                self.transient_selection = true;
+            }
+            buffer.set_mark_to_object(self.cursor, object);
+        }
+        self.maybe_move_screen();
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.buffer.lock().unwrap().clear_mark(self.selection);
+        self.transient_selection = false;
+        self.selection_kind = SelectionKind::Plain;
+    }
+
+    /// The selected text, or the line the cursor is on when nothing is selected.
+    ///
+    /// A selection started at the mark is cleared, like Emacs does after copying.
+    pub fn copy_selection(&mut self) -> Vec<u8> {
+        let text = {
+            let buffer = self.buffer.lock().unwrap();
+            let (start, end) = self.selection_or_line(&buffer);
+            buffer.slice(start, end)
+        };
+        if !self.transient_selection {
+            self.clear_selection();
+        }
+        text
+    }
+
+    /// Remove the selected text, or the line the cursor is on when nothing is
+    /// selected, and return it.
+    pub fn cut_selection(&mut self) -> Vec<u8> {
+        let (start, end) = self.selection_or_line(&self.buffer.lock().unwrap());
+        self.clear_selection();
+        self.cut_range(start, end)
+    }
+
+    /// Start and end of the text between the cursor and an object.
+    ///
+    /// Moving up or down lines covers the whole of each line, like Vi's
+    /// linewise motions.
+    pub fn object_range(&self, object: TextObject) -> Option<(usize, usize)> {
+        let buffer = self.buffer.lock().unwrap();
+        if let Kind::Line(Anchor::Same) = object.kind {
+            return buffer.lines_range(object.offset)
+        }
+        let cursor = buffer.get_mark_idx(self.cursor)?;
+        let target = buffer.get_object_index(object)?.absolute;
+        Some((cmp::min(cursor, target), cmp::max(cursor, target)))
+    }
+
+    /// The text between `start` and `end`
+    pub fn copy_range(&self, start: usize, end: usize) -> Vec<u8> {
+        self.buffer.lock().unwrap().slice(start, end)
+    }
+
+    /// Remove the text between `start` and `end` and return it.
+    pub fn cut_range(&mut self, start: usize, end: usize) -> Vec<u8> {
+        let removed = {
+            let mut buffer = self.buffer.lock().unwrap();
+            let removed = buffer.remove_range(start, end);
+            buffer.set_mark(self.cursor, start);
+            removed
+        };
+        self.maybe_move_screen();
+        removed.unwrap_or_default()
+    }
+
+    /// Put `indent` at the start of each line between `start` and `end`,
+    /// leaving blank lines alone.
+    pub fn indent_lines(&mut self, start: usize, end: usize, indent: &str) {
+        let mut buffer = self.buffer.lock().unwrap();
+        let replacements: Vec<_> = line_starts(&buffer, start, end).into_iter()
+            .filter(|&line| {
+                let (line, line_end) = buffer.line_range(line);
+                !buffer.slice(line, line_end).iter().all(u8::is_ascii_whitespace)
+            })
+            .map(|line| (line, line, String::from(indent)))
+            .collect();
+        buffer.replace_ranges(&replacements);
+        move_to_first_non_blank(&mut buffer, self.cursor, start);
+    }
+
+    /// Remove a tab, or up to a tab width of spaces, from the start of each
+    /// line between `start` and `end`.
+    pub fn dedent_lines(&mut self, start: usize, end: usize) {
+        let mut buffer = self.buffer.lock().unwrap();
+        let replacements: Vec<_> = line_starts(&buffer, start, end).into_iter()
+            .filter_map(|line| {
+                let (line, line_end) = buffer.line_range(line);
+                let text = buffer.slice(line, line_end);
+                let width = if text.first() == Some(&b'\t') {
+                    1
+                } else {
+                    text.iter().take(buffer.tab_width).take_while(|&&c| c == b' ').count()
+                };
+                if width > 0 { Some((line, line + width, String::new())) } else { None }
+            })
+            .collect();
+        buffer.replace_ranges(&replacements);
+        move_to_first_non_blank(&mut buffer, self.cursor, start);
+    }
+
+    /// Insert text at the cursor, which ends up after it.
+    ///
+    /// Returns where the text was inserted, so it can be replaced by `replace_paste`.
+    pub fn paste(&mut self, text: &str) -> (usize, usize) {
+        self.clear_selection();
+        let cursor = self.buffer.lock().unwrap().get_mark_idx(self.cursor).unwrap_or(0);
+        self.replace_paste((cursor, cursor), text)
+    }
+
+    /// Replace the text pasted at `range` with some other text.
+    pub fn replace_paste(&mut self, range: (usize, usize), text: &str) -> (usize, usize) {
+        let (start, end) = range;
+        {
+            let mut buffer = self.buffer.lock().unwrap();
+            buffer.replace_ranges(&[(start, end, String::from(text))]);
+            buffer.set_mark(self.cursor, start + text.len());
+        }
+        self.maybe_move_screen();
+        (start, start + text.len())
+    }
+
+    fn selection_or_line(&self, buffer: &Buffer) -> (usize, usize) {
+        self.selection_range(buffer).unwrap_or_else(|| {
+            let cursor = buffer.get_mark_idx(self.cursor).unwrap_or(0);
+            buffer.line_range(cursor)
+        })
+    }
+
+    /// Start and end of the selected text, if anything is selected
+    pub fn selected_range(&self) -> Option<(usize, usize)> {
+        self.selection_range(&self.buffer.lock().unwrap())
+    }
+
+    fn selection_range(&self, buffer: &Buffer) -> Option<(usize, usize)> {
+        let anchor = buffer.get_mark_idx(self.selection)?;
+        let cursor = buffer.get_mark_idx(self.cursor)?;
+        let (start, end) = (cmp::min(anchor, cursor), cmp::max(anchor, cursor));
+        let (start, end) = match self.selection_kind {
+            SelectionKind::Plain => (start, end),
+            SelectionKind::Inclusive => (start, buffer.next_char(end)),
+            SelectionKind::Lines => (buffer.line_range(start).0, buffer.line_range(end).1),
+        };
+        let end = cmp::min(end, buffer.len() - 1);
+        if start < end { Some((start, end)) } else { None }
+    }
+
     /// Update the top_line mark if necessary to keep the cursor on the screen.
     fn maybe_move_screen(&mut self) {
         let mut buffer = self.buffer.lock().unwrap();
@@ -302,6 +579,237 @@ impl<'v> View<'v> {
         }
     }
 
+    /// Remember where the cursor is, as the place a new search starts from.
+    pub fn begin_search(&mut self) {
+        self.search_origin = self.buffer.lock().unwrap().get_mark_idx(self.cursor);
+    }
+
+    /// Search for `pattern`, moving the cursor to the first match.
+    ///
+    /// While the search prompt is open, each search starts from where the
+    /// cursor was when it was opened. With no pattern, the cursor goes back there.
+    pub fn search(&mut self, pattern: Option<Regex>) {
+        let from = match self.search_origin {
+            Some(origin) => origin,
+            None => self.buffer.lock().unwrap().get_mark_idx(self.cursor).unwrap_or(0),
+        };
+        let found = match pattern {
+            Some(ref pattern) => self.buffer.lock().unwrap().find_forward(pattern, from),
+            None => None,
+        };
+        if pattern.is_some() && found.is_none() {
+            self.show_message("No matches".into());
+        }
+        self.buffer.lock().unwrap().set_mark(self.cursor, found.map_or(from, |(start, _)| start));
+        self.search = pattern;
+        self.maybe_move_screen();
+    }
+
+    /// Move the cursor to the next match of the last search.
+    ///
+    /// Returns false if there has not been a search yet.
+    pub fn find_next(&mut self) -> bool {
+        let found = {
+            let buffer = self.buffer.lock().unwrap();
+            let pattern = match self.search {
+                Some(ref pattern) => pattern,
+                None => return false,
+            };
+            let cursor = buffer.get_mark_idx(self.cursor).unwrap_or(0);
+            buffer.find_forward(pattern, cursor + 1)
+        };
+        self.move_to_match(found);
+        true
+    }
+
+    /// Move the cursor to the previous match of the last search.
+    ///
+    /// Returns false if there has not been a search yet.
+    pub fn find_prev(&mut self) -> bool {
+        let found = {
+            let buffer = self.buffer.lock().unwrap();
+            let pattern = match self.search {
+                Some(ref pattern) => pattern,
+                None => return false,
+            };
+            let cursor = buffer.get_mark_idx(self.cursor).unwrap_or(0);
+            buffer.find_backward(pattern, cursor)
+        };
+        self.move_to_match(found);
+        true
+    }
+
+    fn move_to_match(&mut self, found: Option<(usize, usize)>) {
+        match found {
+            Some((start, _)) => {
+                self.buffer.lock().unwrap().set_mark(self.cursor, start);
+                self.maybe_move_screen();
+            }
+            None => self.show_message("No matches".into()),
+        }
+    }
+
+    /// Leave the cursor at the current match, keeping matches highlighted.
+    pub fn end_search(&mut self) {
+        self.search_origin = None;
+    }
+
+    /// Clear the search, moving the cursor back to where the search started.
+    pub fn cancel_search(&mut self) {
+        if let Some(origin) = self.search_origin.take() {
+            self.buffer.lock().unwrap().set_mark(self.cursor, origin);
+            self.maybe_move_screen();
+        }
+        self.search = None;
+    }
+
+    /// Start replacing the matches of `pattern`, asking about each in turn.
+    ///
+    /// Matches are asked about starting from the cursor, wrapping around to
+    /// the start of the buffer. Returns false if there are no matches.
+    pub fn start_replace(&mut self, pattern: Regex, replacement: &str) -> bool {
+        let (mut matches, origin) = {
+            let buffer = self.buffer.lock().unwrap();
+            (buffer.replacements(&pattern, replacement), buffer.get_mark_idx(self.cursor).unwrap_or(0))
+        };
+        if matches.is_empty() {
+            self.show_message("No matches".into());
+            return false
+        }
+
+        let first = matches.iter().position(|m| m.0 >= origin).unwrap_or(0);
+        matches.rotate_left(first);
+        matches.reverse();
+        // undo takes back every replacement made while asking at once
+        self.buffer.lock().unwrap().log.begin_group();
+        self.replacing = Some(Replacing { remaining: matches, replaced: 0, origin });
+        self.search = Some(pattern);
+        self.move_to_next_replacement();
+        true
+    }
+
+    /// Replace all matches of `pattern` at once.
+    pub fn replace_all(&mut self, pattern: Regex, replacement: &str) {
+        self.substitute(pattern, replacement, true, true)
+    }
+
+    /// Replace matches of `pattern` on the cursor's line, or on every line
+    /// with `whole_file`, like Vi's `:s`.
+    ///
+    /// Only the first match on each line is replaced, unless `global` is set.
+    pub fn substitute(&mut self, pattern: Regex, replacement: &str, whole_file: bool, global: bool) {
+        let (matches, origin) = {
+            let buffer = self.buffer.lock().unwrap();
+            let origin = buffer.get_mark_idx(self.cursor).unwrap_or(0);
+            let (line_start, line_end) = buffer.line_range(origin);
+            let mut last_line = None;
+            let matches: Vec<_> = buffer.replacements(&pattern, replacement).into_iter()
+                .filter(|m| whole_file || (m.0 >= line_start && m.0 < line_end))
+                .filter(|m| {
+                    let line = buffer.line_range(m.0).0;
+                    global || last_line.replace(line) != Some(line)
+                })
+                .collect();
+            (matches, origin)
+        };
+        if matches.is_empty() {
+            self.show_message("No matches".into());
+            return
+        }
+
+        self.buffer.lock().unwrap().log.begin_group();
+        self.replacing = Some(Replacing { remaining: Vec::new(), replaced: 0, origin });
+        self.replace_matches(matches);
+        self.finish_replace();
+    }
+
+    /// Answer whether to replace the current match, replacing it straight away.
+    ///
+    /// Returns true while there are more matches to ask about.
+    pub fn answer_replace(&mut self, answer: ReplaceAnswer) -> bool {
+        let accepted = match self.replacing {
+            Some(ref mut replacing) => {
+                match answer {
+                    ReplaceAnswer::Yes => replacing.remaining.pop().into_iter().collect(),
+                    ReplaceAnswer::No => { replacing.remaining.pop(); Vec::new() }
+                    ReplaceAnswer::All => replacing.remaining.drain(..).collect(),
+                    ReplaceAnswer::Quit => { replacing.remaining.clear(); Vec::new() }
+                }
+            }
+            None => return false,
+        };
+        self.replace_matches(accepted);
+
+        let done = self.replacing.as_ref().is_none_or(|r| r.remaining.is_empty());
+
+        if done {
+            self.finish_replace();
+        } else {
+            self.move_to_next_replacement();
+        }
+        !done
+    }
+
+    fn move_to_next_replacement(&mut self) {
+        let next = self.replacing.as_ref().and_then(|r| r.remaining.last()).map(|m| m.0);
+        if let Some(start) = next {
+            self.buffer.lock().unwrap().set_mark(self.cursor, start);
+            self.maybe_move_screen();
+        }
+    }
+
+    /// Replace matches of the pattern being replaced, all in a single
+    /// transaction, and move the matches still to come to where their text
+    /// now is.
+    fn replace_matches(&mut self, mut matches: Vec<(usize, usize, String)>) {
+        let replacing = match self.replacing {
+            Some(ref mut replacing) if !matches.is_empty() => replacing,
+            _ => return,
+        };
+        matches.sort_by_key(|m| m.0);
+
+        let shift = |idx: usize| -> usize {
+            matches.iter()
+                .take_while(|&&(_, end, _)| end <= idx)
+                .fold(idx as isize, |idx, (start, end, text)| idx + text.len() as isize - (end - start) as isize) as usize
+        };
+        // keep the cursor where it was, past any text replaced before it
+        replacing.origin = shift(replacing.origin);
+        for m in &mut replacing.remaining {
+            *m = (shift(m.0), shift(m.1), m.2.clone());
+        }
+        replacing.replaced += matches.len();
+
+        let mut buffer = self.buffer.lock().unwrap();
+        buffer.replace_ranges(&matches);
+
+        // the top line may have moved, put it back at the start of a line
+        let top_line = cmp::min(buffer.get_mark_idx(self.top_line).unwrap_or(0), buffer.len() - 1);
+        buffer.set_mark(self.top_line, top_line);
+        buffer.set_mark_to_object(self.top_line, TextObject {
+            kind: Kind::Line(Anchor::Start),
+            offset: Offset::Backward(0, self.top_line),
+        });
+    }
+
+    /// Stop replacing, putting the cursor back where it started.
+    fn finish_replace(&mut self) {
+        let replacing = match self.replacing.take() {
+            Some(replacing) => replacing,
+            None => return,
+        };
+        {
+            let mut buffer = self.buffer.lock().unwrap();
+            buffer.set_mark(self.cursor, replacing.origin);
+            buffer.log.end_group();
+        }
+
+        let count = replacing.replaced;
+        self.show_message(format!("Replaced {} occurrence{}", count, if count == 1 { "" } else { "s" }));
+        self.search = None;
+        self.maybe_move_screen();
+    }
+
     // Delete chars from the first index of object to the last index of object
     pub fn delete_object(&mut self, object: TextObject) {
         self.buffer.lock().unwrap().remove_object(object);
@@ -319,98 +827,168 @@ impl<'v> View<'v> {
 
     /// Insert a chacter into the buffer & update cursor position accordingly.
     pub fn insert_char(&mut self, ch: char) {
-        self.buffer.lock().unwrap().insert_char(self.cursor, ch as u8);
-        // NOTE: the last param to char_width here may not be correct
-        if let Some(ch_width) = utils::char_width(ch, false, 4, 1) {
-            let obj = TextObject {
-                kind: Kind::Char,
-                offset: Offset::Forward(ch_width, Mark::Cursor(0))
-            };
-            self.move_mark(Mark::Cursor(0), obj)
+        self.buffer.lock().unwrap().insert_char(self.cursor, ch);
+        let obj = TextObject {
+            kind: Kind::Char,
+            offset: Offset::Forward(1, Mark::Cursor(0))
+        };
+        self.move_mark(Mark::Cursor(0), obj)
+    }
+
+    /// Insert a tab, or with `soft_tabs` as many spaces as it takes to reach
+    /// the next tab stop.
+    pub fn insert_tab(&mut self, soft_tabs: bool) {
+        if !soft_tabs {
+            return self.insert_char('\t')
+        }
+        let spaces = {
+            let buffer = self.buffer.lock().unwrap();
+            let column = buffer.get_mark_display_coords(self.cursor).map_or(0, |coords| coords.0);
+            buffer.tab_width - column % buffer.tab_width
+        };
+        for _ in 0..spaces {
+            self.insert_char(' ')
         }
     }
 
     pub fn undo(&mut self) {
-        {
-            let mut buffer = self.buffer.lock().unwrap();
-            let point = if let Some(transaction) = buffer.undo() { transaction.end_point }
-                        else { return; };
-            buffer.set_mark(self.cursor, point);
-        }
-        self.maybe_move_screen();
+        self.move_in_history(Buffer::undo)
     }
 
     pub fn redo(&mut self) {
+        self.move_in_history(Buffer::redo)
+    }
+
+    /// Go to the state of the text made before the current one
+    pub fn undo_older(&mut self) {
+        self.move_in_history(Buffer::undo_older)
+    }
+
+    /// Go to the state of the text made after the current one
+    pub fn undo_newer(&mut self) {
+        self.move_in_history(Buffer::undo_newer)
+    }
+
+    /// Go to any state of the text in the undo tree
+    pub fn undo_to(&mut self, state: usize) {
+        self.move_in_history(|buffer| buffer.undo_to(state))
+    }
+
+    /// Change the text to another state from its history, moving the cursor
+    /// to where it changed.
+    fn move_in_history<F: FnOnce(&mut Buffer) -> Option<usize>>(&mut self, f: F) {
         {
             let mut buffer = self.buffer.lock().unwrap();
-            let point = if let Some(transaction) = buffer.redo() { transaction.end_point }
-                        else { return; };
+            let point = match f(&mut buffer) {
+                Some(point) => point,
+                None => return,
+            };
             buffer.set_mark(self.cursor, point);
         }
         self.maybe_move_screen();
     }
 
-    fn save_buffer(&mut self) {
+    /// Write the buffer to its file, replacing the file only once all of it is written.
+    fn save_buffer(&mut self, path: &Path) -> io::Result<()> {
         let buffer = self.buffer.lock().unwrap();
-        let path = match buffer.file_path {
-            Some(ref p) => Cow::Borrowed(p),
-            None => {
-                // NOTE: this should never happen, as the file path
-                // should have been set inside the try_save_buffer method.
-                //
-                // If this runs, it probably means save_buffer has been called
-                // directly, rather than try_save_buffer.
-                //
-                // TODO: ask the user to submit a bug report on how they hit this.
-                Cow::Owned(PathBuf::from("untitled"))
-            },
-        };
-        let tmpdir = match TempDir::new_in(&Path::new("."), "iota") {
-            Ok(d) => d,
-            Err(e) => panic!("file error: {}", e)
-        };
+        save::write_atomically(path, |out| buffer.format.write(out, buffer.lines()))
+    }
 
-        let tmppath = tmpdir.path().join(Path::new("tmpfile"));
-        let mut file = match File::create(&tmppath) {
-            Ok(f) => f,
-            Err(e) => {
-                panic!("file error: {}", e)
+    /// Save the buffer to `path`, which becomes its file name, returning
+    /// whether it was saved.
+    ///
+    /// A failure is shown in the status bar, and the buffer keeps its
+    /// unsaved changes and the file name it had.
+    fn save_buffer_to(&mut self, path: PathBuf) -> bool {
+        if let Err(e) = self.save_buffer(&path) {
+            self.show_message(format!("Could not save {}: {}", path.display(), e));
+            return false
+        }
+        let result = {
+            let mut buffer = self.buffer.lock().unwrap();
+            if buffer.file_path.as_ref() != Some(&path) {
+                buffer.set_file_path(path);
             }
+            buffer.dirty = false;
+            if buffer.persistent_undo { history::save(&buffer) } else { Ok(()) }
         };
-
-        //TODO (lee): Is iteration still necessary in this format?
-        for line in buffer.lines() {
-            let result = file.write_all(&*line);
-
-            if result.is_err() {
-                // TODO(greg): figure out what to do here.
-                panic!("Something went wrong while writing the file");
-            }
+        if let Err(e) = result {
+            self.show_message(format!("Could not save the undo history: {}", e));
         }
+        true
+    }
 
-        if let Err(e) = rename(&tmppath, &*path) {
-            panic!("file error: {}", e);
+    /// Save the buffer if it has a file name, returning whether it was saved.
+    ///
+    /// A failure is shown in the status bar, and the buffer keeps its
+    /// unsaved changes.
+    pub fn try_save_buffer(&mut self) -> bool {
+        if self.buffer.lock().unwrap().read_only {
+            let name = self.buffer.lock().unwrap().file_name();
+            self.show_message(format!("{} is a binary file and can't be saved", name));
+            return false
+        }
+        let path = self.buffer.lock().unwrap().file_path.clone();
+        match path {
+            Some(path) => self.save_buffer_to(path),
+            None => {
+                self.show_message("No file name".into());
+                false
+            }
         }
     }
 
-    pub fn try_save_buffer(&mut self) {
-        let mut should_save = false;
+    /// Move the cursor to the start of a line, counting from 1.
+    ///
+    /// Line numbers past the end go to the last line.
+    pub fn goto_line(&mut self, line: usize) {
+        self.clear_selection();
         {
-            let buffer = self.buffer.lock().unwrap();
-
-            match buffer.file_path {
-                Some(_) => { should_save = true; }
-                None => {
-                    self.message = Some(("No file name".into(), SystemTime::now()));
-                }
+            let mut buffer = self.buffer.lock().unwrap();
+            let line = cmp::max(1, cmp::min(line, buffer.line_count()));
+            if let Some((start, _)) = buffer.lines_range(Offset::Absolute(line)) {
+                buffer.set_mark(self.cursor, start);
             }
         }
+        self.maybe_move_screen();
+    }
 
-        if should_save {
-            self.save_buffer();
-            let mut buffer = self.buffer.lock().unwrap();
-            buffer.dirty = false;
+    /// Save the buffer under a new file name, which it keeps for later saves.
+    pub fn save_buffer_as(&mut self, path: PathBuf) {
+        // a binary file keeps its name, as it won't be saved anyway
+        if self.buffer.lock().unwrap().read_only {
+            self.try_save_buffer();
+            return
         }
+        self.save_buffer_to(path);
+    }
+
+    /// Whether saving the buffer as `path` would write over a file other
+    /// than its own.
+    pub fn would_overwrite(&self, path: &Path) -> bool {
+        let buffer = self.buffer.lock().unwrap();
+        let own_file = buffer.file_path.as_ref().is_some_and(|file| utils::canonical(file) == utils::canonical(path));
+        path.exists() && !own_file && !buffer.read_only
+    }
+
+    /// Change the line endings the buffer is saved with, or switch to the
+    /// other kind when none is given.
+    pub fn set_line_ending(&mut self, line_ending: Option<LineEnding>) {
+        let line_ending = {
+            let mut buffer = self.buffer.lock().unwrap();
+            if buffer.read_only {
+                let name = buffer.file_name();
+                drop(buffer);
+                return self.show_message(format!("{} is a binary file and can't be changed", name))
+            }
+            let line_ending = line_ending.unwrap_or_else(|| buffer.format.line_ending.toggled());
+            if buffer.format.line_ending != line_ending {
+                buffer.format.line_ending = line_ending;
+                buffer.dirty = true;
+            }
+            line_ending
+        };
+        self.show_message(format!("Line endings: {}", line_ending));
     }
 
     /// Whether or not the current buffer has unsaved changes
@@ -420,7 +998,31 @@ impl<'v> View<'v> {
 
 }
 
-pub fn draw_line(rb: &mut Crossterm, line: &[u8], idx: u16, left: u16) {
+/// Indexes of the start of each line between `start` and `end`
+fn line_starts(buffer: &Buffer, start: usize, end: usize) -> Vec<usize> {
+    let mut starts = Vec::new();
+    let mut idx = start;
+    loop {
+        let (line, line_end) = buffer.line_range(idx);
+        starts.push(line);
+        if line_end >= end || line_end >= buffer.len() - 1 { break }
+        idx = line_end;
+    }
+    starts
+}
+
+/// Put `mark` on the first char which isn't a space or tab in the line `idx` is on
+fn move_to_first_non_blank(buffer: &mut Buffer, mark: Mark, idx: usize) {
+    let (line, line_end) = buffer.line_range(idx);
+    let blanks = buffer.slice(line, line_end).iter().take_while(|&&c| c == b' ' || c == b'\t').count();
+    buffer.set_mark(mark, line + blanks);
+}
+
+/// Draw a line of text, in the styles given for byte ranges of it.
+///
+/// The rest of the line is drawn in the `base` style, which also fills in
+/// the colours that the other styles leave out.
+pub fn draw_line(rb: &mut Crossterm, line: &str, styles: &[(usize, usize, Style)], base: Style, tab_width: usize, idx: u16, left: u16) {
     let width = rb.terminal().terminal_size().0 - 1;
     let mut x: u16 = 0;
 
@@ -431,11 +1033,22 @@ pub fn draw_line(rb: &mut Crossterm, line: &[u8], idx: u16, left: u16) {
     // at drawing more text than a character at a time. It prefers being buffered. This is the solution.
     let mut formatted_line = String::new(); // Line after applying tabs and characters that fit within view
 
-    for ch in line.iter().skip(left as usize) {
-        let ch = *ch as char;
+    formatted_line.push_str(&base.to_string());
+    let mut current_style = base;
+    for (byte_idx, ch) in line.char_indices().skip(left as usize) {
+        // later styles are drawn over earlier ones
+        let style = styles.iter().rev()
+            .find(|&&(start, end, _)| byte_idx >= start && byte_idx < end)
+            .map_or(base, |&(_, _, style)| style.over(base));
+        if style != current_style {
+            formatted_line.push_str(&Attribute::Reset.to_string());
+            formatted_line.push_str(&style.to_string());
+            current_style = style;
+        }
+
         match ch {
             '\t' => {
-                let w = 4 - x % 4;
+                let w = tab_width as u16 - x % tab_width as u16;
                 for _ in 0..w {
                     // rb.print_char(x, idx, RustBoxStyle::empty(), Color::White, Color::Black, ' ');
                     // print_char!(out, x, idx, ' ');
@@ -456,32 +1069,39 @@ pub fn draw_line(rb: &mut Crossterm, line: &[u8], idx: u16, left: u16) {
         }
     }
 
-    // Replace any cells after end of line with ' '
-    // while x < width {
-    //     // rb.print_char(x, idx, RustBoxStyle::empty(), Color::White, Color::Black, ' ');
-    //     print_char!(out, x, idx, ' ');
-    //     x += 1;
-    // }
-    // if x < width { // Again, crossterm is a little higher-level
-    //     formatted_line.push('\n');
-    // }
+    // fill the rest of the line, so a background colour reaches the edge
+    if current_style != base {
+        formatted_line.push_str(&Attribute::Reset.to_string());
+        formatted_line.push_str(&base.to_string());
+    }
+    while x < width {
+        formatted_line.push(' ');
+        x += 1;
+    }
 
     print_char!(out, 0, idx, formatted_line); // Write the entire line
 
     // If the line is too long to fit on the screen, show an indicator
-    let indicator = if line.len() > (width + left) as usize { '→' } else { ' ' };
+    let indicator = if line.chars().count() > (width + left) as usize { '→' } else { ' ' };
     // rb.print_char(width, idx, RustBoxStyle::empty(), Color::White, Color::Black, indicator);
-    print_char!(out, width, idx, indicator);
+    print_char!(out, width, idx, format!("{}{}", base, indicator));
 }
 
 #[cfg(test)]
 mod tests {
 
+    use std::fs;
     use std::sync::{Arc, Mutex};
     use std::rc::Rc;
 
-    use crate::view::View;
-    use crate::buffer::Buffer;
+    use regex::Regex;
+    use tempdir::TempDir;
+
+    use crate::view::{View, SelectionKind};
+    use crate::buffer::{Buffer, Mark};
+    use crate::command::ReplaceAnswer;
+    use crate::fileformat::LineEnding;
+    use crate::textobject::{Anchor, TextObject, Kind, Offset};
 
     fn setup_view(testcase: &'static str) -> View {
         let buffer = Arc::new(Mutex::new(Buffer::new()));
@@ -505,4 +1125,191 @@ mod tests {
             assert_eq!(buffer.lines().next().unwrap(), b"ttest\n");
         }
     }
+
+    #[test]
+    fn test_search_and_cancel() {
+        let mut view = setup_view("test\nsecond test");
+        view.begin_search();
+        view.search(Some(Regex::new("test").unwrap()));
+        assert_eq!(view.buffer.lock().unwrap().get_mark_idx(view.cursor), Some(0));
+
+        assert!(view.find_next());
+        assert_eq!(view.buffer.lock().unwrap().get_mark_idx(view.cursor), Some(12));
+
+        view.cancel_search();
+        assert_eq!(view.buffer.lock().unwrap().get_mark_idx(view.cursor), Some(0));
+        assert!(!view.find_next());
+    }
+
+    #[test]
+    fn test_selection() {
+        let mut view = setup_view("test\nsecond");
+        let right = TextObject { kind: Kind::Char, offset: Offset::Forward(1, Mark::Cursor(0)) };
+        let down = TextObject { kind: Kind::Line(Anchor::Same), offset: Offset::Forward(1, Mark::Cursor(0)) };
+
+        // a selection made by extending it goes away when the cursor moves on
+        view.extend_selection(right);
+        view.extend_selection(down);
+        assert_eq!(view.selected_range(), Some((0, 6)));
+        view.move_mark(Mark::Cursor(0), right);
+        assert_eq!(view.selected_range(), None);
+
+        // one started at the mark stays until it is cleared
+        view.set_selection_mark();
+        view.move_mark(Mark::Cursor(0), right);
+        view.move_mark(Mark::Cursor(0), right);
+        assert_eq!(view.selected_range(), Some((7, 9)));
+        view.clear_selection();
+        assert_eq!(view.selected_range(), None);
+
+        // Vi's visual modes take in the char under the cursor, or whole lines
+        view.start_selection(SelectionKind::Inclusive);
+        assert_eq!(view.selected_range(), Some((9, 10)));
+        view.move_mark(Mark::Cursor(0), TextObject { kind: Kind::Char, offset: Offset::Backward(3, Mark::Cursor(0)) });
+        assert_eq!(view.selected_range(), Some((6, 10)));
+        view.start_selection(SelectionKind::Lines);
+        assert_eq!(view.selection_kind(), Some(SelectionKind::Lines));
+        assert_eq!(view.selected_range(), Some((5, 11)));
+    }
+
+    #[test]
+    fn test_cut_and_paste() {
+        let mut view = setup_view("one\ntwo\n");
+
+        // without a selection, the whole line is cut
+        assert_eq!(view.cut_selection(), b"one\n".to_vec());
+        let right = TextObject { kind: Kind::Char, offset: Offset::Forward(1, Mark::Cursor(0)) };
+        view.extend_selection(right);
+        view.extend_selection(right);
+        assert_eq!(view.copy_selection(), b"tw".to_vec());
+
+        let pasted = view.paste("one\n");
+        assert_eq!(pasted, (2, 6));
+        assert_eq!(view.replace_paste(pasted, "!"), (2, 3));
+        let buffer = view.buffer.lock().unwrap();
+        assert_eq!(buffer.lines().collect::<Vec<_>>(), vec![b"tw!o\n".to_vec(), b"".to_vec()]);
+        assert_eq!(buffer.get_mark_idx(view.cursor), Some(3));
+    }
+
+    #[test]
+    fn test_operator_ranges() {
+        let mut view = setup_view("one two\nthree\nfour\n");
+        let word = TextObject { kind: Kind::Word(Anchor::Start), offset: Offset::Forward(1, Mark::Cursor(0)) };
+        assert_eq!(view.object_range(word), Some((0, 4)));
+        let lines = TextObject { kind: Kind::Line(Anchor::Same), offset: Offset::Forward(1, Mark::Cursor(0)) };
+        assert_eq!(view.object_range(lines), Some((0, 14)));
+
+        assert_eq!(view.cut_range(0, 4), b"one ".to_vec());
+        view.indent_lines(0, 10, "  ");
+        view.indent_lines(0, 4, "\t");
+        {
+            let buffer = view.buffer.lock().unwrap();
+            assert_eq!(buffer.lines().collect::<Vec<_>>(),
+                       vec![b"\t  two\n".to_vec(), b"  three\n".to_vec(), b"four\n".to_vec(), b"".to_vec()]);
+        }
+
+        // a tab or a tab width of spaces is removed from each line
+        view.dedent_lines(0, 16);
+        let buffer = view.buffer.lock().unwrap();
+        assert_eq!(buffer.lines().collect::<Vec<_>>(),
+                   vec![b"  two\n".to_vec(), b"three\n".to_vec(), b"four\n".to_vec(), b"".to_vec()]);
+        assert_eq!(buffer.get_mark_idx(view.cursor), Some(2));
+    }
+
+    #[test]
+    fn test_substitute_and_goto_line() {
+        let mut view = setup_view("a a\na a\na a");
+        view.goto_line(2);
+        view.substitute(Regex::new("a").unwrap(), "b", false, true);
+        view.substitute(Regex::new("a").unwrap(), "c", true, false);
+        {
+            let buffer = view.buffer.lock().unwrap();
+            assert_eq!(buffer.lines().collect::<Vec<_>>(), vec![b"c a\n".to_vec(), b"b b\n".to_vec(), b"c a".to_vec()]);
+        }
+
+        view.goto_line(100);
+        assert_eq!(view.buffer.lock().unwrap().get_mark_idx(view.cursor), Some(8));
+    }
+
+    #[test]
+    fn test_replace_interactive() {
+        let mut view = setup_view("a a\na");
+        assert!(view.start_replace(Regex::new("a").unwrap(), "bb"));
+        assert!(view.answer_replace(ReplaceAnswer::Yes));
+        // each replacement is made as soon as it is accepted
+        {
+            let buffer = view.buffer.lock().unwrap();
+            assert_eq!(buffer.lines().collect::<Vec<_>>(), vec![b"bb a\n".to_vec(), b"a".to_vec()]);
+            assert_eq!(buffer.get_mark_idx(view.cursor), Some(3));
+        }
+        assert!(view.answer_replace(ReplaceAnswer::No));
+        assert!(!view.answer_replace(ReplaceAnswer::Yes));
+
+        {
+            let buffer = view.buffer.lock().unwrap();
+            assert_eq!(buffer.lines().collect::<Vec<_>>(), vec![b"bb a\n".to_vec(), b"bb".to_vec()]);
+        }
+
+        // all replacements are undone at once
+        view.undo();
+        let buffer = view.buffer.lock().unwrap();
+        assert_eq!(buffer.lines().collect::<Vec<_>>(), vec![b"a a\n".to_vec(), b"a".to_vec()]);
+    }
+
+    #[test]
+    fn test_insert_tab() {
+        let mut view = setup_view("ab\n\tx");
+        view.insert_tab(true);
+        view.buffer.lock().unwrap().set_mark(view.cursor, 9);
+        view.insert_tab(true);
+        view.insert_tab(false);
+
+        // spaces go up to the next tab stop, counting hard tabs as wide as they are drawn
+        let buffer = view.buffer.lock().unwrap();
+        assert_eq!(buffer.lines().collect::<Vec<_>>(), vec![b"    ab\n".to_vec(), b"\tx   \t".to_vec()]);
+    }
+
+    #[test]
+    fn test_set_line_ending_read_only() {
+        let mut view = setup_view("text");
+        {
+            let mut buffer = view.buffer.lock().unwrap();
+            buffer.read_only = true;
+            buffer.dirty = false;
+        }
+        view.set_line_ending(None);
+        let buffer = view.buffer.lock().unwrap();
+        assert_eq!(buffer.format.line_ending, LineEnding::Lf);
+        assert!(!buffer.dirty);
+    }
+
+    #[test]
+    fn test_save_buffer_as() {
+        let dir = TempDir::new("credits").unwrap();
+        let mut view = setup_view("text");
+
+        // a file which can't be written leaves the buffer as it was
+        view.save_buffer_as(dir.path().join("missing/file.txt"));
+        {
+            let buffer = view.buffer.lock().unwrap();
+            assert!(buffer.file_path.is_none());
+            assert!(buffer.dirty);
+        }
+
+        let path = dir.path().join("file.txt");
+        let other = dir.path().join("other.txt");
+        fs::write(&other, "other").unwrap();
+        assert!(!view.would_overwrite(&path));
+        assert!(view.would_overwrite(&other));
+
+        view.save_buffer_as(path.clone());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "text\n");
+        {
+            let buffer = view.buffer.lock().unwrap();
+            assert_eq!(buffer.file_path, Some(path.clone()));
+            assert!(!buffer.dirty);
+        }
+        // saving over its own file needs no asking
+        assert!(!view.would_overwrite(&dir.path().join("./file.txt")));
+    }
 }
@@ -8,11 +8,12 @@ use crossterm;
 use credits;
 
 use std::io::stdin;
+use std::path::PathBuf;
+use std::process;
 // use docopt::Docopt;
 use credits::{
     Editor, Input,
-    StandardMode, NormalMode, EmacsMode,
-    Mode,
+    Config, StartMode,
 };
 
 use crossterm::Crossterm;
@@ -23,13 +24,16 @@ use structopt::StructOpt;
 #[structopt(name = "iota")]
 struct Opt {
     #[structopt(name = "FILE")]
-    arg_filename: Option<String>,
+    arg_filenames: Vec<String>,
     /// Start Credits with Emacs-like mode
     #[structopt(long = "emacs")]
     flag_emacs: bool,
     /// Start Credits with Vi-like modes
     #[structopt(long = "vi")]
     flag_vi: bool,
+    /// Read the configuration from this file, rather than ~/.config/credits/config.toml
+    #[structopt(long = "config", parse(from_os_str))]
+    flag_config: Option<PathBuf>,
 }
 
 fn is_atty(fileno: libc::c_int) -> bool {
@@ -47,11 +51,13 @@ fn main() {
     let stdin_is_atty = is_atty(libc::STDIN_FILENO);
     // let stderr_is_atty = is_atty(libc::STDERR_FILENO);
 
-    // editor source - either a filename or stdin
-    let source = if stdin_is_atty {
-        Input::Filename(args.arg_filename)
+    // editor sources - either filenames or stdin
+    let sources = if !stdin_is_atty {
+        vec![Input::Stdin(stdin())]
+    } else if args.arg_filenames.is_empty() {
+        vec![Input::Filename(None)]
     } else {
-        Input::Stdin(stdin())
+        args.arg_filenames.into_iter().map(|f| Input::Filename(Some(f))).collect()
     };
 
 
@@ -64,18 +70,24 @@ fn main() {
     //     Result::Ok(v) => v,
     //     Result::Err(e) => panic!("{}", e),
     // };
+    let mut config = match Config::load(args.flag_config.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Could not read the configuration, {}", e);
+            process::exit(1);
+        }
+    };
+
     let ct = Crossterm::new();
 
-    // initialise the editor mode
-    let mode: Box<Mode> = if args.flag_vi {
-        Box::new(NormalMode::new())
+    // the editor mode given on the command line wins over the configured one
+    if args.flag_vi {
+        config.mode = StartMode::Vi;
     } else if args.flag_emacs {
-        Box::new(EmacsMode::new())    
-    } else {
-        Box::new(StandardMode::new())
-    };
+        config.mode = StartMode::Emacs;
+    }
 
     // start the editor
-    let mut editor = Editor::new(source, mode, ct);
+    let mut editor = Editor::new(sources, config, ct);
     editor.start();
 }